@@ -2,15 +2,31 @@
 Module handling all the add command related functions
 */
 
-use crate::object::blob::add_blob;
+use std::{env::current_dir, path::PathBuf, process::exit};
 
-pub fn add_to_local_repo(arg: String) {
-    let _folder_vec: Vec<&str> = if arg.contains("/") {
-        let folder_split: Vec<&str> = arg.split("/").collect();
-        folder_split
-    } else {
-        vec![&arg]
-    };
-    add_blob(&arg);
-}
+use crate::{fs::walkdir, object::blob::add_blobs};
 
+pub fn add_to_local_repo(args: Vec<String>) {
+    let workdir = current_dir().expect("Failed to get the current working directory");
+    let mut paths: Vec<String> = Vec::new();
+    for arg in args {
+        let path = workdir.join(&arg);
+        if path.is_dir() {
+            // Expand directories to all the files they contain
+            let mut file_vec: Vec<PathBuf> = Vec::new();
+            let _ = walkdir(&path, &mut file_vec);
+            for each in file_vec {
+                let relative = each.strip_prefix(&workdir).unwrap_or(&each);
+                paths.push(relative.to_str().expect("Failed to cast path to str").to_owned());
+            }
+        } else if path.is_file() {
+            paths.push(arg);
+        } else {
+            lrncore::logs::error_log(&format!("pathspec '{arg}' did not match any files"));
+            exit(1);
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    add_blobs(&paths);
+}
@@ -1,52 +1,400 @@
 use std::{
     env::{self},
-    fs::{self, File},
-    io::Write,
+    path::Path,
     process::exit,
 };
 
-use crate::refs::{parse_head, parse_current_branch};
+use lrngitcore::{
+    config::{self as core_config, Config},
+    objects::commit::read_commit,
+    refs::{delete_ref, is_valid_ref_name, list_refs, read_ref, rename_ref, write_ref},
+    remote::{list_remotes, upstream_ref},
+    revwalk::{ahead_behind, is_ancestor},
+};
+
+use crate::refs::{log_ref_update, parse_current_branch, parse_head, resolve_commit_revision};
+
+const LOCAL_CONFIG: &str = ".lrngit/config";
+
+const BRANCH_USAGE: &str = "lrngit branch [-v | -vv]
+lrngit branch <name> [<start point>]
+lrngit branch -d | -D <name>...
+lrngit branch -m [<old>] <new>
+lrngit branch -u | --set-upstream-to <upstream> [<name>]
+lrngit branch --unset-upstream [<name>]";
+
+/// Options of the branch command
+#[derive(Default)]
+struct BranchOptions<'a> {
+    delete: bool,
+    force_delete: bool,
+    rename: bool,
+    /// 1 with `-v`, 2 with `-vv` to show the upstream name too
+    verbose: u8,
+    set_upstream: Option<&'a str>,
+    unset_upstream: bool,
+    positional: Vec<&'a str>,
+}
+
+/// Upstream of a branch with the counts of commits the branch is ahead and behind it
+pub struct UpstreamStatus {
+    /// Short name of the upstream, like `origin/main`
+    pub name: String,
+    /// None when the upstream ref doesn't exist anymore
+    pub counts: Option<(usize, usize)>,
+}
 
 pub fn branch_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        show_all_branch();
-        exit(0);
-    }
-    if args.len() == 3 {
-        create_new_branch(&args[2]);
-        exit(0);
-    }
-    match args[2].as_str() {
-        "" => {}
-        _ => {
-            lrncore::logs::warning_log("Unknown command");
+    let options = parse_branch_args(&args[2..]);
+    if options.delete || options.force_delete {
+        if options.positional.is_empty() {
+            lrncore::usage_exit::usage_and_exit("Invalid command", "lrngit branch -d <name>...");
+        }
+        for name in &options.positional {
+            delete_branch(name, options.force_delete);
+        }
+    } else if options.rename {
+        match options.positional.as_slice() {
+            [new] => {
+                let head = parse_head();
+                rename_branch(head.strip_prefix("refs/heads/").unwrap_or(&head), new);
+            }
+            [old, new] => rename_branch(old, new),
+            _ => lrncore::usage_exit::usage_and_exit(
+                "Invalid command",
+                "lrngit branch -m [<old>] <new>",
+            ),
+        }
+    } else if let Some(upstream) = options.set_upstream {
+        match options.positional.as_slice() {
+            [] => set_upstream_to(upstream, None),
+            [branch] => set_upstream_to(upstream, Some(branch)),
+            _ => lrncore::usage_exit::usage_and_exit("Invalid command", BRANCH_USAGE),
+        }
+    } else if options.unset_upstream {
+        match options.positional.as_slice() {
+            [] => unset_upstream(None),
+            [branch] => unset_upstream(Some(branch)),
+            _ => lrncore::usage_exit::usage_and_exit("Invalid command", BRANCH_USAGE),
+        }
+    } else if options.positional.is_empty() {
+        show_all_branch(options.verbose);
+    } else {
+        create_new_branch(options.positional[0], options.positional.get(1).copied());
+    }
+    exit(0);
+}
+
+fn parse_branch_args(args: &[String]) -> BranchOptions<'_> {
+    let mut options = BranchOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" => options.delete = true,
+            "-D" => options.force_delete = true,
+            "-m" => options.rename = true,
+            "-v" | "--verbose" => options.verbose += 1,
+            "-vv" => options.verbose += 2,
+            "-l" => {}
+            "-u" | "--set-upstream-to" => match args.next() {
+                Some(upstream) => options.set_upstream = Some(upstream),
+                None => lrncore::usage_exit::usage_and_exit("Invalid command", BRANCH_USAGE),
+            },
+            "--unset-upstream" => options.unset_upstream = true,
+            arg if arg.starts_with("--set-upstream-to=") => {
+                options.set_upstream = Some(&arg["--set-upstream-to=".len()..]);
+            }
+            arg if arg.starts_with('-') => {
+                lrncore::usage_exit::usage_and_exit("Invalid command", BRANCH_USAGE);
+            }
+            arg => options.positional.push(arg),
+        }
+    }
+    options
+}
+
+/// Create a branch on the given start point, HEAD by default
+pub fn create_new_branch(branch_name: &str, start_point: Option<&str>) {
+    check_new_branch(branch_name);
+    let last_commit = match start_point {
+        Some(rev) => resolve_commit_revision(rev),
+        None => parse_current_branch(),
+    };
+    if last_commit.is_empty() {
+        lrncore::logs::error_log(&format!("not a valid object name: '{}'", parse_head()));
+        exit(128);
+    }
+    write_new_branch(branch_name, &last_commit, start_point.unwrap_or("HEAD"));
+}
+
+/// Create a branch on an already resolved commit, `start_point` is the revision it was resolved
+/// from and goes in the reflog
+pub fn write_new_branch(branch_name: &str, commit: &str, start_point: &str) {
+    let refname = check_new_branch(branch_name);
+    if let Err(e) = write_ref(".lrngit", &refname, commit) {
+        lrncore::logs::error_log(&format!("Failed to create branch '{branch_name}': {e}"));
+        exit(1);
+    }
+    log_ref_update(
+        &refname,
+        "",
+        commit,
+        &format!("branch: Created from {start_point}"),
+    );
+}
+
+/// Exit if a branch can't be created with the given name, return its ref
+pub fn check_new_branch(branch_name: &str) -> String {
+    let refname = branch_ref(branch_name);
+    if read_ref(".lrngit", &refname).ok().flatten().is_some() {
+        lrncore::logs::error_log(&format!("a branch named '{branch_name}' already exists"));
+        exit(128);
+    }
+    refname
+}
+
+/// Delete a branch, unless forced it must be merged in the current HEAD
+fn delete_branch(branch_name: &str, force: bool) {
+    let refname = branch_ref(branch_name);
+    if parse_head() == refname {
+        lrncore::logs::error_log(&format!(
+            "Cannot delete branch '{branch_name}' checked out, switch to another branch first"
+        ));
+        exit(1);
+    }
+    let Ok(Some(hash)) = read_ref(".lrngit", &refname) else {
+        lrncore::logs::error_log(&format!("branch '{branch_name}' not found"));
+        exit(1);
+    };
+    if !force {
+        let head_commit = parse_current_branch();
+        let merged = !head_commit.is_empty()
+            && is_ancestor(".lrngit", &hash, &head_commit).unwrap_or_else(|e| {
+                lrncore::logs::error_log(&format!("{e}"));
+                exit(1);
+            });
+        if !merged {
+            lrncore::logs::error_log(&format!("The branch '{branch_name}' is not fully merged."));
+            println!("If you are sure you want to delete it, run 'lrngit branch -D {branch_name}'.");
             exit(1);
         }
     }
+    if let Err(e) = delete_ref(".lrngit", &refname) {
+        lrncore::logs::error_log(&format!("Failed to delete branch '{branch_name}': {e}"));
+        exit(1);
+    }
+    // The upstream of the branch goes away with it
+    if let Err(e) = core_config::remove_section(Path::new(LOCAL_CONFIG), &format!("branch.{branch_name}")) {
+        lrncore::logs::warning_log(&format!("Failed to remove the config of '{branch_name}': {e}"));
+    }
+    println!("Deleted branch {branch_name} (was {}).", &hash[..7]);
 }
 
-fn create_new_branch(branch_name: &str) {
-    let mut file = File::create(format!(".lrngit/refs/heads/{branch_name}"))
-        .expect("Failed to create new branch");
-    let last_commit = parse_current_branch();
-    file.write_all(last_commit.as_bytes())
-        .expect("Failed to write in the new branch file");
+/// Rename a branch and its reflog, HEAD follows when the current branch is renamed
+fn rename_branch(old_name: &str, new_name: &str) {
+    let old_ref = branch_ref(old_name);
+    let new_ref = branch_ref(new_name);
+    if let Err(e) = rename_ref(".lrngit", &old_ref, &new_ref) {
+        lrncore::logs::error_log(&format!("Failed to rename branch '{old_name}': {e}"));
+        exit(1);
+    }
+    let hash = read_ref(".lrngit", &new_ref).ok().flatten().unwrap_or_default();
+    let (old_section, new_section) = (format!("branch.{old_name}"), format!("branch.{new_name}"));
+    if let Err(e) = core_config::rename_section(Path::new(LOCAL_CONFIG), &old_section, &new_section) {
+        lrncore::logs::warning_log(&format!("Failed to rename the config of '{old_name}': {e}"));
+    }
+    if parse_head() == old_ref
+        && let Err(e) = std::fs::write(".lrngit/HEAD", format!("ref: {new_ref}"))
+    {
+        lrncore::logs::error_log(&format!("Failed to update HEAD: {e}"));
+        exit(1);
+    }
+    log_ref_update(
+        &new_ref,
+        &hash,
+        &hash,
+        &format!("Branch: renamed {old_ref} to {new_ref}"),
+    );
 }
 
-fn show_all_branch() {
+/// List local branches, nested names included. In verbose mode the tip commit and how far the
+/// branch is from its upstream are shown, the upstream name too at level 2.
+fn show_all_branch(verbose: u8) {
     let current_branch = parse_head();
-    let split_current_branch: Vec<&str> = current_branch.split("/").collect();
-    let branchdir =
-        fs::read_dir(".lrngit/refs/heads/").expect("Failed to get branch directory content");
-    for path in branchdir {
-        let branch_name = path.unwrap().file_name();
-        let branch_name_str = branch_name.to_str().unwrap();
-        if branch_name_str == split_current_branch[split_current_branch.len() - 1] {
-            println!("*{branch_name_str}");
-        } else {
-            println!("{branch_name_str}");
+    let branches = list_refs(".lrngit", "refs/heads").unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to list branches: {e}"));
+        exit(1);
+    });
+    let width = branches
+        .iter()
+        .map(|(x, _)| x.len() - "refs/heads/".len())
+        .max()
+        .unwrap_or_default();
+    if current_branch == "HEAD" {
+        let head_commit = parse_current_branch();
+        println!("* (HEAD detached at {})", &head_commit[..7]);
+    }
+    for (refname, hash) in branches {
+        let branch_name = refname.trim_start_matches("refs/heads/");
+        let marker = if refname == current_branch { "*" } else { "" };
+        if verbose == 0 {
+            println!("{marker}{branch_name}");
+            continue;
+        }
+        let tracking = upstream_status(&refname, &hash)
+            .map(|x| tracking_label(&x, verbose > 1))
+            .unwrap_or_default();
+        let subject = read_commit(&hash, ".lrngit")
+            .map(|x| {
+                String::from_utf8_lossy(&x.message)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned()
+            })
+            .unwrap_or_default();
+        let marker = if marker.is_empty() { " " } else { marker };
+        println!("{marker} {branch_name:width$} {} {tracking}{subject}", &hash[..7]);
+    }
+}
+
+/// Label of the upstream of a branch in the verbose list, like `[origin/main: ahead 1]`
+fn tracking_label(status: &UpstreamStatus, with_name: bool) -> String {
+    let counts = match status.counts {
+        None => Some(String::from("gone")),
+        Some((0, 0)) => None,
+        Some((ahead, 0)) => Some(format!("ahead {ahead}")),
+        Some((0, behind)) => Some(format!("behind {behind}")),
+        Some((ahead, behind)) => Some(format!("ahead {ahead}, behind {behind}")),
+    };
+    match (with_name, counts) {
+        (true, Some(counts)) => format!("[{}: {counts}] ", status.name),
+        (true, None) => format!("[{}] ", status.name),
+        (false, Some(counts)) => format!("[{counts}] "),
+        (false, None) => String::new(),
+    }
+}
+
+/// Upstream of a branch with its ahead and behind counts, None if the branch has no upstream.
+/// `hash` is the tip of the branch, empty if it has no commit yet.
+pub fn upstream_status(refname: &str, hash: &str) -> Option<UpstreamStatus> {
+    let upstream = upstream_ref(".lrngit", refname).ok().flatten()?;
+    let counts = match read_ref(".lrngit", &upstream).ok().flatten() {
+        Some(upstream_hash) if !hash.is_empty() => {
+            ahead_behind(".lrngit", hash, &upstream_hash).ok()
+        }
+        _ => None,
+    };
+    Some(UpstreamStatus {
+        name: short_upstream_name(&upstream).to_owned(),
+        counts,
+    })
+}
+
+/// Short name of an upstream, `refs/remotes/origin/main` is `origin/main`
+fn short_upstream_name(upstream: &str) -> &str {
+    upstream
+        .strip_prefix("refs/remotes/")
+        .or_else(|| upstream.strip_prefix("refs/heads/"))
+        .unwrap_or(upstream)
+}
+
+/// Record the upstream of a local branch, `merge` is the branch of `remote` it follows
+pub fn set_upstream(branch_name: &str, remote: &str, merge: &str) {
+    for (key, value) in [("remote", remote), ("merge", merge)] {
+        let key = format!("branch.{branch_name}.{key}");
+        if let Err(e) = core_config::set_value(Path::new(LOCAL_CONFIG), &key, value) {
+            lrncore::logs::error_log(&format!("Failed to set {key}: {e}"));
+            exit(1);
         }
     }
 }
 
+/// Name of the branch given on the command line or of the current one
+fn branch_or_current(branch_name: Option<&str>, action: &str) -> String {
+    if let Some(name) = branch_name {
+        return name.to_owned();
+    }
+    let head = parse_head();
+    match head.strip_prefix("refs/heads/") {
+        Some(name) => name.to_owned(),
+        None => {
+            lrncore::logs::error_log(&format!("could not {action} of HEAD, it isn't on a branch"));
+            exit(128);
+        }
+    }
+}
+
+/// Make a branch track a remote tracking branch like `origin/main`, or a local branch
+fn set_upstream_to(upstream: &str, branch_name: Option<&str>) {
+    let branch_name = branch_or_current(branch_name, "set upstream");
+    if read_ref(".lrngit", &branch_ref(&branch_name)).ok().flatten().is_none() {
+        lrncore::logs::error_log(&format!("branch '{branch_name}' does not exist"));
+        exit(128);
+    }
+    let candidates = if upstream.starts_with("refs/") {
+        vec![upstream.to_owned()]
+    } else {
+        vec![format!("refs/remotes/{upstream}"), format!("refs/heads/{upstream}")]
+    };
+    let Some(upstream_ref) = candidates
+        .into_iter()
+        .find(|x| read_ref(".lrngit", x).ok().flatten().is_some())
+    else {
+        lrncore::logs::error_log(&format!(
+            "the requested upstream branch '{upstream}' does not exist"
+        ));
+        exit(128);
+    };
+    let (remote, merge) = if upstream_ref.starts_with("refs/heads/") {
+        (String::from("."), upstream_ref.clone())
+    } else {
+        let found = list_remotes(&Config::load(".lrngit"))
+            .into_iter()
+            .find_map(|x| Some((x.remote_ref(&upstream_ref).ok().flatten()?, x.name)));
+        match found {
+            Some((merge, name)) => (name, merge),
+            None => {
+                lrncore::logs::error_log(&format!(
+                    "'{upstream}' is not a remote tracking branch of any remote"
+                ));
+                exit(128);
+            }
+        }
+    };
+    set_upstream(&branch_name, &remote, &merge);
+    println!(
+        "branch '{branch_name}' set up to track '{}'.",
+        short_upstream_name(&upstream_ref)
+    );
+}
+
+fn unset_upstream(branch_name: Option<&str>) {
+    let branch_name = branch_or_current(branch_name, "unset upstream");
+    let mut found = false;
+    for key in ["remote", "merge"] {
+        let key = format!("branch.{branch_name}.{key}");
+        match core_config::unset_value(Path::new(LOCAL_CONFIG), &key) {
+            Ok(unset) => found |= unset,
+            Err(e) => {
+                lrncore::logs::error_log(&format!("Failed to unset {key}: {e}"));
+                exit(1);
+            }
+        }
+    }
+    if !found {
+        lrncore::logs::error_log(&format!("branch '{branch_name}' has no upstream information"));
+        exit(128);
+    }
+}
+
+/// Full ref of a branch, exit if the name isn't a valid branch name
+fn branch_ref(name: &str) -> String {
+    let refname = format!("refs/heads/{name}");
+    if name == "HEAD" || name.starts_with('-') || !is_valid_ref_name(&refname) {
+        lrncore::logs::error_log(&format!("'{name}' is not a valid branch name"));
+        exit(128);
+    }
+    refname
+}
@@ -0,0 +1,138 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::exit,
+};
+
+use lrngitcore::{
+    objects::{
+        commit::{commit_parents, parse_commit_author, read_commit},
+        tag::read_tag,
+        tree::{DIR, read_tree},
+        utils::{object_type, parse_object_header, read_object, split_object_header},
+    },
+    revparse,
+};
+
+use crate::refs::resolve_revision;
+
+pub fn cat_file_command() {
+    let args: Vec<String> = env::args().collect();
+    let (flag, revision) = match (args.get(2), args.get(3)) {
+        (Some(flag), Some(revision)) if flag.starts_with('-') => (flag.as_str(), revision),
+        (Some(revision), None) if !revision.starts_with('-') => ("-p", revision),
+        _ => {
+            lrncore::usage_exit::usage_and_exit(
+                "Invalid command",
+                "lrngit cat-file (-t | -s | -p | -e) <object>",
+            );
+            return;
+        }
+    };
+    match flag {
+        // Only the exit code matter, nothing is printed
+        "-e" => match revparse::resolve(".lrngit", revision) {
+            Ok(hash) if read_object(&hash, ".lrngit").is_ok() => exit(0),
+            _ => exit(1),
+        },
+        "-t" => {
+            let (object_type, _) = parse_object_header(&resolve_revision(revision));
+            println!("{object_type}");
+        }
+        "-s" => println!("{}", object_content(&resolve_revision(revision)).len()),
+        "-p" => print_object(&resolve_revision(revision)),
+        _ => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+    }
+    exit(0);
+}
+
+/// Content of an object, header excluded
+fn object_content(hash: &str) -> Vec<u8> {
+    match read_object(hash, ".lrngit") {
+        Ok(buffer) => split_object_header(buffer).1,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    }
+}
+
+/// Pretty print an object according to its type
+fn print_object(hash: &str) {
+    let buffer = match read_object(hash, ".lrngit") {
+        Ok(b) => b,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    };
+    let result = match object_type(&buffer).as_str() {
+        "commit" => print_commit(hash),
+        "tree" => print_tree(hash),
+        "tag" => print_tag(hash),
+        _ => io::stdout().write_all(&split_object_header(buffer).1),
+    };
+    if let Err(e) = result {
+        lrncore::logs::error_log(&format!("Failed to print object: {e}"));
+        exit(1);
+    }
+}
+
+fn print_commit(hash: &str) -> io::Result<()> {
+    let commit = read_commit(hash, ".lrngit").map_err(|e| io::Error::other(e.to_string()))?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "tree {}", hex::encode(commit.tree))?;
+    for parent in commit_parents(&commit) {
+        writeln!(out, "parent {parent}")?;
+    }
+    for (field, user) in [("author", &commit.author), ("committer", &commit.commiter)] {
+        let user = parse_commit_author(user.clone());
+        writeln!(
+            out,
+            "{field} {} <{}> {} {}",
+            String::from_utf8_lossy(&user.name),
+            String::from_utf8_lossy(&user.email),
+            user.timestamp,
+            String::from_utf8_lossy(&user.timezone)
+        )?;
+    }
+    writeln!(out, "\n{}", String::from_utf8_lossy(&commit.message))
+}
+
+fn print_tag(hash: &str) -> io::Result<()> {
+    let tag = read_tag(hash, ".lrngit").map_err(|e| io::Error::other(e.to_string()))?;
+    let tagger = parse_commit_author(tag.tagger);
+    let mut out = io::stdout().lock();
+    writeln!(out, "object {}", hex::encode(tag.object))?;
+    writeln!(out, "type {}", String::from_utf8_lossy(&tag.object_type))?;
+    writeln!(out, "tag {}", String::from_utf8_lossy(&tag.tag))?;
+    writeln!(
+        out,
+        "tagger {} <{}> {} {}",
+        String::from_utf8_lossy(&tagger.name),
+        String::from_utf8_lossy(&tagger.email),
+        tagger.timestamp,
+        String::from_utf8_lossy(&tagger.timezone)
+    )?;
+    writeln!(out, "\n{}", String::from_utf8_lossy(&tag.message))
+}
+
+/// Print one line per entry, `<mode> <type> <hash>\t<name>`
+fn print_tree(hash: &str) -> io::Result<()> {
+    let entries = read_tree(hash, ".lrngit").map_err(|e| io::Error::other(e.to_string()))?;
+    let mut out = io::stdout().lock();
+    for entry in entries {
+        let kind = if entry.mode == DIR { "tree" } else { "blob" };
+        writeln!(
+            out,
+            "{:06o} {kind} {}\t{}",
+            entry.mode,
+            hex::encode(entry.hash),
+            String::from_utf8_lossy(&entry.name)
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use std::env;
+
+use crate::sequencer::{Action, sequencer_command};
+
+/// `lrngit cherry-pick <rev>...`, apply the changes of each commit on the current branch
+pub fn cherry_pick_command() {
+    let args: Vec<String> = env::args().collect();
+    sequencer_command(Action::Pick, &args[2..]);
+}
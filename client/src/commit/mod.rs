@@ -1,80 +1,378 @@
 use std::{
     collections::HashMap,
     env,
+    fs,
     process::exit,
 };
 
+use lrngitcore::objects::{
+    commit::{parse_commit_author, CommitUser},
+    index::CachedTree,
+    tree::{DIR, RWO},
+};
+
 use crate::{
-    object::{commit::create_commit_object, index, tree::batch_tree_add},
+    hooks::{run_hook, run_hook_or_exit},
+    object::{
+        blob::add_blobs,
+        commit::{
+            amend_commit_object, author_identity, create_commit_object, parse_commit_by_hash,
+            parse_date_or_exit,
+        },
+        index,
+        tree::batch_tree_add,
+    },
+    refs::parse_current_branch,
+    status,
     types::BatchIndexEntriesMap,
+    utils::{edit_file, strip_comments},
 };
 
+const COMMIT_EDITMSG: &str = ".lrngit/COMMIT_EDITMSG";
+
+/// Options of the commit command
+#[derive(Default)]
+struct CommitOptions {
+    /// Paragraphs given with `-m`, joined by a blank line
+    messages: Vec<String>,
+    /// File holding the message, given with `-F`
+    file: Option<String>,
+    all: bool,
+    amend: bool,
+    allow_empty: bool,
+    /// Author given with `--author`, as `Name <email>`
+    author: Option<String>,
+    /// Author date given with `--date`
+    date: Option<String>,
+    /// Skip the pre-commit and commit-msg hooks
+    no_verify: bool,
+}
+
 pub fn commit_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        lrncore::usage_exit::usage_and_exit("Invalid command", "use -m flag");
+    let options = parse_options(args.get(2..).unwrap_or_default());
+    if options.all {
+        stage_tracked_changes();
     }
-    match args[2].as_str() {
-        "-m" => {
-            let message = args[3].as_str();
-            new_commit(message);
+    let head = parse_current_branch();
+    if options.amend && head.is_empty() {
+        lrncore::logs::error_log("You have nothing to amend");
+        exit(1);
+    }
+    if !options.no_verify {
+        run_hook_or_exit("pre-commit", &[], &[]);
+    }
+    let root_tree = write_index_tree();
+    // Commits without any file can't be written, even with --allow-empty
+    if !options.allow_empty || root_tree == [0u8; 20] {
+        // An amended commit is compared to the parent it will have
+        let parent = if options.amend {
+            String::from_utf8_lossy(&parse_commit_by_hash(&head).parent).to_string()
+        } else {
+            head.clone()
+        };
+        check_not_empty(root_tree, &parent, options.amend);
+    }
+    let author = commit_author(&options, &head);
+    let from_editor = options.file.is_none() && options.messages.is_empty();
+    let message = if let Some(file) = &options.file {
+        fs::read_to_string(file).unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("could not read log file '{file}': {e}"));
+            exit(1);
+        })
+    } else if !options.messages.is_empty() {
+        options.messages.join("\n\n")
+    } else {
+        // The amended commit message is proposed in the editor
+        let default = if options.amend {
+            String::from_utf8_lossy(&parse_commit_by_hash(&head).message).to_string()
+        } else {
+            String::new()
+        };
+        edit_message(&default)
+    };
+    let mut message = cleanup_message(&message, from_editor);
+    if !options.no_verify && !message.is_empty() {
+        message = run_commit_msg_hook(&message, from_editor);
+    }
+    if message.is_empty() {
+        lrncore::logs::error_log("Aborting commit due to empty commit message.");
+        exit(1);
+    }
+    if options.amend {
+        amend_commit_object(root_tree, &message, &author);
+    } else {
+        create_commit_object(root_tree, &message, &author);
+    }
+    // The commit is done, a failing post-commit hook can't change the outcome
+    run_hook("post-commit", &[], &[]);
+}
+
+fn parse_options(args: &[String]) -> CommitOptions {
+    let mut options = CommitOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-a" | "--all" => options.all = true,
+            "--amend" => options.amend = true,
+            "--allow-empty" => options.allow_empty = true,
+            "-n" | "--no-verify" => options.no_verify = true,
+            flag @ ("--author" | "--date") => {
+                i += 1;
+                let Some(value) = args.get(i) else {
+                    lrncore::logs::error_log(&format!("{flag} expects a value"));
+                    exit(1);
+                };
+                if flag == "--author" {
+                    options.author = Some(value.clone());
+                } else {
+                    options.date = Some(value.clone());
+                }
+            }
+            flag @ ("-m" | "-am" | "-F") => {
+                i += 1;
+                let Some(value) = args.get(i) else {
+                    let expected = if flag == "-F" { "a file" } else { "a message" };
+                    lrncore::logs::error_log(&format!("{flag} expects {expected}"));
+                    exit(1);
+                };
+                if flag == "-F" {
+                    options.file = Some(value.clone());
+                } else {
+                    options.all |= flag == "-am";
+                    options.messages.push(value.clone());
+                }
+            }
+            arg if arg.starts_with("--author=") => {
+                options.author = Some(arg["--author=".len()..].to_owned());
+            }
+            arg if arg.starts_with("--date=") => {
+                options.date = Some(arg["--date=".len()..].to_owned());
+            }
+            _ => {
+                lrncore::usage_exit::usage_and_exit(
+                    "Invalid command",
+                    "lrngit commit [-a] [--amend] [--allow-empty] [--no-verify] [--author <author>] [--date <date>] [-m <msg>]... [-F <file>]",
+                );
+                exit(1);
+            }
         }
-        _ => {
-            lrncore::logs::warning_log("Unknown command");
+        i += 1;
+    }
+    if options.file.is_some() && !options.messages.is_empty() {
+        lrncore::logs::error_log("Option -m cannot be combined with -F");
+        exit(1);
+    }
+    options
+}
+
+/// Author of the new commit, an amended commit keeps its author unless `--author` or `--date`
+/// replace them
+fn commit_author(options: &CommitOptions, head: &str) -> CommitUser {
+    let mut author = if options.amend {
+        parse_commit_author(parse_commit_by_hash(head).author)
+    } else {
+        author_identity()
+    };
+    if let Some(identity) = &options.author {
+        let Some((name, email)) = identity
+            .strip_suffix('>')
+            .and_then(|x| x.split_once('<'))
+            .map(|(name, email)| (name.trim(), email.trim()))
+            .filter(|(name, email)| !name.is_empty() && !email.is_empty())
+        else {
+            lrncore::logs::error_log(&format!("--author '{identity}' is not 'Name <email>'"));
+            exit(1);
+        };
+        author.name = name.as_bytes().to_vec();
+        author.email = email.as_bytes().to_vec();
+    }
+    if let Some(date) = &options.date {
+        let (timestamp, timezone) = parse_date_or_exit(date);
+        author.timestamp = timestamp;
+        author.timezone = timezone.into_bytes();
+    }
+    author
+}
+
+/// Exit if the new root tree is the tree of the parent, the commit would record no change.
+/// An empty index can't be committed either.
+fn check_not_empty(root_tree: [u8; 20], parent: &str, amend: bool) {
+    let parent_tree = if parent.is_empty() {
+        [0u8; 20]
+    } else {
+        parse_commit_by_hash(parent).tree
+    };
+    if root_tree != parent_tree && root_tree != [0u8; 20] {
+        return;
+    }
+    if amend {
+        println!("You asked to amend the most recent commit, but doing so would make it empty.");
+        println!("Use --allow-empty to create the empty commit anyway.");
+        exit(1);
+    }
+    let files_status = status::get_files_status();
+    if !files_status.modified.is_empty() || !files_status.deleted.is_empty() {
+        println!("no changes added to commit (use \"lrngit add\" and/or \"lrngit commit -a\")");
+    } else if !files_status.untracked.is_empty() {
+        println!("nothing added to commit but untracked files present (use \"lrngit add\" to track)");
+    } else {
+        println!("nothing to commit, working tree clean");
+    }
+    exit(1);
+}
+
+/// Stage the modified and deleted tracked files, untracked files are left out
+fn stage_tracked_changes() {
+    let files_status = status::get_files_status();
+    let modified: Vec<String> = files_status.modified.into_iter().map(|x| x.file).collect();
+    if !modified.is_empty() {
+        add_blobs(&modified);
+    }
+    for each in files_status.deleted {
+        index::remove_index_entry(&each.file);
+    }
+}
+
+/// Run the commit-msg hook on the message written in COMMIT_EDITMSG, the hook can edit the file.
+/// Return the message read back from the file.
+fn run_commit_msg_hook(message: &str, from_editor: bool) -> String {
+    if let Err(e) = fs::write(COMMIT_EDITMSG, format!("{message}\n")) {
+        lrncore::logs::error_log(&format!("Failed to write {COMMIT_EDITMSG}: {e}"));
+        exit(1);
+    }
+    run_hook_or_exit("commit-msg", &[COMMIT_EDITMSG], &[]);
+    match fs::read_to_string(COMMIT_EDITMSG) {
+        Ok(m) => cleanup_message(&m, from_editor),
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to read {COMMIT_EDITMSG}: {e}"));
             exit(1);
         }
     }
 }
 
-pub fn new_commit(commit_message: &str) {
+/// Clean a commit message: trailing spaces and surrounding blank lines are removed. Lines
+/// starting with `#` are comments only in a message written in the editor, a message given with
+/// `-m` or `-F` keeps them.
+fn cleanup_message(message: &str, from_editor: bool) -> String {
+    if from_editor {
+        return strip_comments(message);
+    }
+    let lines: Vec<&str> = message.lines().map(|x| x.trim_end()).collect();
+    lines.join("\n").trim().to_owned()
+}
+
+/// Open the editor on a template listing the staged files and return the edited message
+fn edit_message(default: &str) -> String {
+    let mut template = format!(
+        "{default}\n\
+        # Please enter the commit message for your changes. Lines starting\n\
+        # with '#' will be ignored, and an empty message aborts the commit.\n\
+        #\n"
+    );
+    let staged = status::get_files_status().staged;
+    if !staged.is_empty() {
+        template.push_str("# Changes to be committed:\n");
+        for each in staged {
+            template.push_str(&format!("#\t{}\n", each.file));
+        }
+    }
+    if let Err(e) = fs::write(COMMIT_EDITMSG, template) {
+        lrncore::logs::error_log(&format!("Failed to write {COMMIT_EDITMSG}: {e}"));
+        exit(1);
+    }
+    edit_file(COMMIT_EDITMSG);
+    fs::read_to_string(COMMIT_EDITMSG).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to read {COMMIT_EDITMSG}: {e}"));
+        exit(1);
+    })
+}
+
+/// Create the trees of the index content and return the hash of the root tree.
+/// The trees of the directories unchanged since the last write are reused from the cached-tree
+/// extension of the index.
+pub fn write_index_tree() -> [u8; 20] {
     let config = index::parse_index();
+    let files: Vec<(String, [u8; 20])> = config
+        .entries
+        .into_iter()
+        .map(|x| (String::from_utf8_lossy(&x.path).to_string(), x.hash))
+        .collect();
+    let mut cached_trees: HashMap<String, [u8; 20]> = config
+        .cached_trees
+        .into_iter()
+        .map(|x| (x.path, x.hash))
+        .collect();
+    let root_tree = write_tree_cached(files, &mut cached_trees);
+    let mut cached_trees: Vec<CachedTree> = cached_trees
+        .into_iter()
+        .map(|(path, hash)| CachedTree { path, hash })
+        .collect();
+    cached_trees.sort_by(|a, b| a.path.cmp(&b.path));
+    index::write_cached_trees(cached_trees);
+    root_tree
+}
+
+/// Create the trees of the given files, path and blob hash, and return the hash of the root tree
+pub fn write_tree(files: Vec<(String, [u8; 20])>) -> [u8; 20] {
+    write_tree_cached(files, &mut HashMap::new())
+}
+
+/// Create the trees of the given files like `write_tree`, directories found in `cached_trees`
+/// aren't written again. On return `cached_trees` holds the tree of every directory.
+fn write_tree_cached(
+    files: Vec<(String, [u8; 20])>,
+    cached_trees: &mut HashMap<String, [u8; 20]>,
+) -> [u8; 20] {
     let mut root_tree: [u8; 20] = [0; 20];
-    // HashMap to store all index entry with blob and tree for batch tree creation
-    // Use strings to avoid dropping value and dangling ref
+    // HashMap to store all index entry with blob and tree for batch tree creation, keyed by the
+    // full path of the directory so directories with the same name don't collide
     let mut index_entry_map: BatchIndexEntriesMap =
         HashMap::new();
     // Iterate over the index, each entry contain file path and blob hash
-    for each in config.entries {
-        let path = &each.path;
-        let path_string = String::from_utf8_lossy(path).to_string();
-        let mut folder_vec: Vec<&str> = if path_string.contains("/") {
-            let folder_split: Vec<&str> = path_string.split("/").collect();
-            folder_split
-        } else {
-            vec![&path_string]
-        };
-        folder_vec.reverse();
-        folder_vec.push("");
-        let mut folder_vec_clone = folder_vec.clone();
-        // Index iterator to keep track if last element of path is file or folder
-        let mut i: usize = 0;
-        while !folder_vec_clone.is_empty() {
-            let last = folder_vec_clone.remove(0);
-            let key = if !folder_vec_clone.is_empty() {
-                folder_vec_clone[0]
+    for (path_string, hash) in files {
+        let components: Vec<&str> = path_string.split('/').collect();
+        for (depth, name) in components.iter().enumerate() {
+            let dir = components[..depth].join("/");
+            let entry_vec = index_entry_map.entry((dir, depth)).or_default();
+            // Avoid duplicate entries, by checking if entry doesn't exist
+            if entry_vec.iter().any(|(x, _, _)| x == name) {
+                continue;
+            }
+            if depth + 1 < components.len() {
+                entry_vec.push((name.to_string(), DIR, [0u8; 20]));
             } else {
-                ""
-            };
-            // Only insert if last is not empty to avoid empty keys
-            if !last.is_empty() {
-                // Use reverse index to represent folder hierarchy correctly
-                let folder_index = folder_vec.len() - i - 1;
-                let entry_vec = index_entry_map
-                    .entry((key.to_string(), folder_index))
-                    .or_default();
-                // Avoid duplicate entries, by checking if entry doesn't exist
-                if !entry_vec.iter().any(|(name, _, _)| name == last) {
-                    if i != 0 {
-                        entry_vec.push((last.to_owned().to_string(), 0o040000, [0u8; 20]));
-                    } else {
-                        entry_vec.push((last.to_owned().to_string(), 0o100644, each.hash));
-                    }
-                }
+                entry_vec.push((name.to_string(), RWO, hash));
             }
-            i += 1;
         }
     }
-    batch_tree_add(index_entry_map, &mut root_tree);
-    create_commit_object(root_tree, commit_message);
+    batch_tree_add(index_entry_map, &mut root_tree, cached_trees);
+    root_tree
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_option_keeps_hash_lines() {
+        assert_eq!(cleanup_message("#42 fix thing", false), "#42 fix thing");
+        assert_eq!(
+            cleanup_message("\nsubject  \n\n# not a comment\n\n", false),
+            "subject\n\n# not a comment"
+        );
+    }
+
+    #[test]
+    fn editor_message_strips_comments() {
+        assert_eq!(
+            cleanup_message("subject\n\nbody\n# Please enter the commit message\n#\n", true),
+            "subject\n\nbody"
+        );
+        assert_eq!(cleanup_message("#42 fix thing\n", true), "");
+        assert_eq!(
+            cleanup_message("one\n\n# skipped\n\ntwo  \n\n\nthree\n", true),
+            "one\n\ntwo\n\nthree"
+        );
+    }
+}
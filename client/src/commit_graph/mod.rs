@@ -0,0 +1,34 @@
+use std::{env, process::exit};
+
+use lrngitcore::commit_graph::{CommitGraph, write_commit_graph};
+
+pub fn commit_graph_command() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() <= 2 {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit commit-graph <write|verify>",
+        );
+    }
+    match args[2].as_str() {
+        "write" => match write_commit_graph(".lrngit") {
+            Ok(count) => println!("Wrote commit-graph with {count} commits"),
+            Err(e) => {
+                lrncore::logs::error_log(&format!("Failed to write commit-graph: {e}"));
+                exit(1);
+            }
+        },
+        "verify" => match CommitGraph::load(".lrngit") {
+            Some(graph) => println!("commit-graph is valid, {} commits", graph.len()),
+            None => {
+                lrncore::logs::error_log("No valid commit-graph found");
+                exit(1);
+            }
+        },
+        _ => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+    }
+    exit(0);
+}
@@ -2,49 +2,131 @@ use std::{
     env,
     fs::File,
     io::Write,
+    path::Path,
     process::exit,
 };
 
-use lrncore::path::get_current_path;
-use serde::Serialize;
-
-pub struct GlobalConfig {
-    pub user: GlobalConfigUser,
-    pub url: GlobalConfigUrl,
-}
+use lrngitcore::config::{self as core_config, parse_bool, parse_int, Config, ConfigScope};
 
 pub struct GlobalConfigUser {
     pub name: String,
     pub email: String,
 }
 
-pub struct GlobalConfigUrl {
-    pub remote: String,
+const CONFIG_USAGE: &str = "lrngit config [--global|--local|--system] get [--type=bool|int|path] <key>
+lrngit config [--global|--local|--system] set <key> <value>
+lrngit config [--global|--local|--system] unset <key>
+lrngit config [--global|--local|--system] list
+lrngit config init";
+
+pub fn config_commands() {
+    let args: Vec<String> = env::args().collect();
+    let mut args = args.get(2..).unwrap_or_default();
+    let scope = match args.first().map(|x| x.as_str()) {
+        Some("--global") => Some(ConfigScope::Global),
+        Some("--local") => Some(ConfigScope::Local),
+        Some("--system") => Some(ConfigScope::System),
+        _ => None,
+    };
+    if scope.is_some() {
+        args = &args[1..];
+    }
+    let Some(subcommand) = args.first() else {
+        lrncore::usage_exit::usage_and_exit("Invalid command", CONFIG_USAGE);
+        exit(1);
+    };
+    let args = &args[1..];
+    match (subcommand.as_str(), args) {
+        ("init", []) => init_global_config(),
+        ("cat", []) => config_list(Some(ConfigScope::Global)),
+        ("list", []) => config_list(scope),
+        ("get", [key]) => config_get(scope, key, None),
+        ("get", [kind, key]) if kind.starts_with("--type=") => {
+            config_get(scope, key, Some(&kind["--type=".len()..]))
+        }
+        ("set", [key, value]) => config_set(scope, key, value),
+        ("unset", [key]) => config_unset(scope, key),
+        _ => {
+            lrncore::usage_exit::usage_and_exit("Invalid command", CONFIG_USAGE);
+            exit(1);
+        }
+    }
+    exit(0);
 }
 
-#[derive(Serialize, Debug)]
-pub struct LocalConfig {
-    pub remotes: Remotes,
+/// Load the config of a scope, every scope merged without one
+fn load(scope: Option<ConfigScope>) -> Config {
+    match scope {
+        Some(scope) => Config::load_scope(scope, ".lrngit"),
+        None => Config::load(".lrngit"),
+    }
 }
 
-#[derive(Serialize, Debug)]
-pub struct Remotes {
-    pub url: String,
-    #[allow(dead_code)]
-    pub fetch: String,
+fn config_list(scope: Option<ConfigScope>) {
+    for entry in load(scope).entries() {
+        println!("{}={}", entry.key, entry.value);
+    }
 }
 
-pub fn config_commands() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
+/// Print the value of a key, `kind` checks and canonicalizes it. Exit with 1 if the key is
+/// absent.
+fn config_get(scope: Option<ConfigScope>, key: &str, kind: Option<&str>) {
+    let config = load(scope);
+    let Some(value) = config.get(key) else {
         exit(1);
+    };
+    let value = match kind {
+        None => value.to_owned(),
+        Some("bool") => parse_bool(key, value).map(|x| x.to_string()).unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(1);
+        }),
+        Some("int") => parse_int(key, value).map(|x| x.to_string()).unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(1);
+        }),
+        Some("path") => core_config::expand_path(value).display().to_string(),
+        Some(other) => {
+            lrncore::logs::error_log(&format!("unrecognized --type argument, {other}"));
+            exit(1);
+        }
+    };
+    println!("{value}");
+}
+
+/// File written by set and unset, the local one by default
+fn writable_path(scope: Option<ConfigScope>) -> std::path::PathBuf {
+    let scope = scope.unwrap_or(ConfigScope::Local);
+    if scope == ConfigScope::Local && !Path::new(".lrngit").is_dir() {
+        lrncore::logs::error_log("not in a lrngit repository, use --global to write the global config");
+        exit(1);
+    }
+    match scope.writable_path(".lrngit") {
+        Some(path) => path,
+        None => {
+            lrncore::logs::error_log(&format!("the {scope} config can't be written"));
+            exit(1);
+        }
     }
-    match args[2].as_str() {
-        "init" => init_global_config(),
-        "cat" => cat_global_config(),
+}
 
-        _ => {
-            eprintln!("enter a config commands")
+fn config_set(scope: Option<ConfigScope>, key: &str, value: &str) {
+    let path = writable_path(scope);
+    if let Err(e) = core_config::set_value(&path, key, value) {
+        lrncore::logs::error_log(&format!("Failed to set {key} in {}: {e}", path.display()));
+        exit(1);
+    }
+}
+
+/// Remove a key, exit with 5 like git if it wasn't set
+fn config_unset(scope: Option<ConfigScope>, key: &str) {
+    let path = writable_path(scope);
+    match core_config::unset_value(&path, key) {
+        Ok(true) => {}
+        Ok(false) => exit(5),
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to unset {key} in {}: {e}", path.display()));
+            exit(1);
         }
     }
 }
@@ -53,9 +135,6 @@ fn config_template() -> String {
     r"[user]
 name = ''
 email = ''
-
-[url]
-remote = ''
 "
     .to_string()
 }
@@ -104,85 +183,26 @@ fn write_global_config() {
         .expect("Failed to update global config file");
 }
 
-/// Parse the global configuration file in the home directory
-pub fn parse_global_config() -> GlobalConfig {
-    let config_path = dirs::home_dir().unwrap().to_str().unwrap().to_string() + "/.lrngitconfig";
-    let ini_file =
-        ini::Ini::load_from_file(&config_path).expect("Failed to open global config file");
-    let user_section = ini_file
-        .section(Some("user"))
-        .expect("Missing [user] section in config file");
-    let url_section = ini_file
-        .section(Some("url"))
-        .expect("Missing [url] section in config file");
-    let name = user_section
-        .get("name")
-        .expect("Missing 'name' in [user] section")
-        .to_string();
-    let email = user_section
-        .get("email")
-        .expect("Missing 'email' in [user] section")
-        .to_string();
-    let remote = url_section
-        .get("remote")
-        .expect("Missing 'remote' in [url] section")
-        .to_string();
-    GlobalConfig {
-        user: GlobalConfigUser { name, email },
-        url: GlobalConfigUrl { remote },
-    }
-}
-
-/// Parse the configuration file in local repository
-pub fn parse_local_config() -> LocalConfig {
-    let config_path = get_current_path() + "/.lrngit/config";
-    let ini_file =
-        ini::Ini::load_from_file(&config_path).expect("Failed to load local config file");
-    let remote_section = ini_file
-        .section(Some("remote"))
-        .expect("Missing [remote] section in local config file");
-    let url = remote_section
-        .get("url")
-        .expect("Missing 'url' in [remote] section")
-        .to_owned();
-    let fetch = remote_section
-        .get("fetch")
-        .expect("Missing 'fetch' in [remote] section")
-        .to_owned();
-    LocalConfig {
-        remotes: Remotes { url, fetch },
-    }
+/// Get the user from the configuration without failing, used where a missing configuration
+/// must not abort the command like for reflog entries
+pub fn try_parse_user() -> Option<GlobalConfigUser> {
+    Some(GlobalConfigUser {
+        name: user_field("name")?,
+        email: user_field("email")?,
+    })
 }
 
-pub fn update_remote_url_local_config(url: &str) {
-    let config_path = get_current_path() + "/.lrngit/config";
-    let mut config_file =
-        ini::Ini::load_from_file(&config_path).expect("Failed to open global config file");
-    let mut remote = config_file.with_section(Some("remote"));
-    remote.set("url", url);
-    config_file
-        .write_to_file(config_path)
-        .expect("Failed to update global config file");
+/// Get a field of the `[user]` section, the local repository config beats the global one
+pub fn user_field(key: &str) -> Option<String> {
+    Config::load(".lrngit").get(&format!("user.{key}")).map(|x| x.to_owned())
 }
 
-fn cat_global_config() {
-    let config = parse_global_config();
-    println!(
-        "[user]\nname = {}\nemail = {}\n\n[url]\nremote = {}",
-        config.user.name, config.user.email, config.url.remote
-    );
+/// Get a path of the `[user]` section, a leading `~/` is the home directory
+pub fn user_path(key: &str) -> Option<std::path::PathBuf> {
+    Config::load(".lrngit").get_path(&format!("user.{key}"))
 }
 
-/// Create the config file for the local repository using a basic template
+/// Create the config file for the local repository, empty until a remote or a value is set
 pub fn init_config_repo() {
-    let mut config =
-        File::create_new(".lrngit/config").expect("Failed to create local repository config file");
-    let template = r"[remote]
-url = ''
-fetch = +refs/heads/*:refs/remotes/origin/*
-"
-    .to_string();
-    config
-        .write_all(template.as_bytes())
-        .expect("Failed to write template in config file");
+    File::create_new(".lrngit/config").expect("Failed to create local repository config file");
 }
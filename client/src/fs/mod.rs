@@ -1,24 +1,38 @@
 use std::{
-    fs::{self, File, OpenOptions},
-    io::Write,
-    path::PathBuf,
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::exit,
 };
 
 use lrngitcore::objects::{index::TempIndex, utils::get_path_by_hash};
 
-use crate::object::blob;
+use crate::{object::{blob, index::parse_index}, vec_of_path};
 
-/// Remove file at the end of the path and try to remove directory if empty  
+/// Remove file at the end of the path and the directories it leaves empty. A file already
+/// gone from the disk is the expected result, not an error.
 pub fn delete_path(path: &PathBuf) {
-    if !fs::exists(path).unwrap() {
-        panic!("Error while removing path. Path does not exist.");
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            panic!("Failed to remove path from disk: {e}")
+        }
+        _ => {}
+    }
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        // remove_dir only removes empty directories, stop at the first one still used
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
     }
-    fs::remove_file(path).expect("Failed to remove path from disk");
 }
 
+/// Write a file, the directories leading to it are created if needed
 pub fn write_files(buff: &[u8], path: &str) {
-    if !fs::exists(path).expect("Failed to check if the path exist") {
-        File::create_new(path).expect("Failed to create the new file");
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent).expect("Failed to create the parent directories");
     }
     let mut file = OpenOptions::new()
         .read(false)
@@ -30,24 +44,115 @@ pub fn write_files(buff: &[u8], path: &str) {
     file.write_all(buff).expect("Failed to write in file");
 }
 
-/// Update the working directory depending on the temporary index
-///
+/// Write the content of a blob object in a file of the working directory
+pub fn checkout_blob(hash: &[u8; 20], path: &str) {
+    let hash_char: Vec<char> = hex::encode(hash).chars().collect();
+    let hash_path = get_path_by_hash(&hash_char);
+    let blob_content = blob::read_blob_content(&hash_path);
+    write_files(&blob_content, path);
+}
+
+/// Update the working directory depending on the temporary index.
+/// Abort before touching anything if an untracked file would be overwritten.
 pub fn update_workdir(temp_index: TempIndex) {
+    check_untracked_overwrite(&temp_index);
     for each in temp_index.to_delete_files {
         delete_path(&each);
     }
     for each in temp_index.new_files {
-        let hash: &str = &hex::encode(each.1);
-        let hash_char: Vec<char> = hash.chars().collect();
-        let hash_path = get_path_by_hash(&hash_char);
-        let blob_content = blob::read_blob_content(&hash_path);
-        write_files(&blob_content, each.0.to_str().unwrap());
+        checkout_blob(&each.1, each.0.to_str().unwrap());
     }
     for each in temp_index.changed_files {
-        let hash: &str = &hex::encode(each.hash);
-        let hash_char: Vec<char> = hash.chars().collect();
-        let hash_path = get_path_by_hash(&hash_char);
-        let blob_content = blob::read_blob_content(&hash_path);
-        write_files(&blob_content, str::from_utf8(&each.path).unwrap());
+        checkout_blob(&each.hash, str::from_utf8(&each.path).unwrap());
+    }
+}
+
+/// Exit if a file created by the checkout is in the way of an untracked file, the path itself
+/// or one of its parent directories existing as an untracked file
+pub fn check_untracked_overwrite(temp_index: &TempIndex) {
+    let tracked: HashSet<PathBuf> = parse_index()
+        .entries
+        .iter()
+        .map(|x| PathBuf::from(String::from_utf8_lossy(&x.path).to_string()))
+        .collect();
+    let mut untracked: Vec<PathBuf> = Vec::new();
+    for (path, _) in &temp_index.new_files {
+        if path.exists() && !tracked.contains(path) {
+            if path.is_dir() {
+                let mut files: Vec<PathBuf> = Vec::new();
+                let _ = walkdir(path, &mut files);
+                untracked.extend(files.into_iter().filter(|x| !tracked.contains(x)));
+            } else {
+                untracked.push(path.clone());
+            }
+        }
+        untracked.extend(
+            path.ancestors()
+                .skip(1)
+                .filter(|x| x.is_file() && !tracked.contains(*x))
+                .map(|x| x.to_path_buf()),
+        );
+    }
+    if untracked.is_empty() {
+        return;
+    }
+    untracked.sort();
+    untracked.dedup();
+    println!("error: The following untracked working tree files would be overwritten by checkout:");
+    for each in untracked {
+        println!("\t{}", each.display());
+    }
+    println!("Please move or remove them before you switch branches.");
+    println!("Aborting");
+    exit(1);
+}
+
+/// Recursive function to get all files in current workdir
+///
+/// # Errors
+///
+/// This function will return an error if the function cannot access a directory.
+pub fn walkdir(workdir: &PathBuf, file_vec: &mut Vec<PathBuf>) -> io::Result<()> {
+    let avoid_path_sufx: Vec<&Path> = vec_of_path!(".lrngit", ".git", "target");
+    if workdir.is_dir() {
+        for entry in fs::read_dir(workdir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // avoid all unwanted path
+            if !avoid_path_sufx
+                .iter()
+                .any(|&suffix| entry.file_name() == suffix)
+            {
+                if path.is_dir() {
+                    if let Err(e) = walkdir(&path, file_vec) {
+                        eprintln!("Error walking directory {path:?}: {e}");
+                    }
+                } else if path.is_file() {
+                    file_vec.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delete_path_removes_empty_parents() {
+        let root = std::env::temp_dir().join(format!("lrngit-delete-{}", std::process::id()));
+        let file = root.join("a/b/file");
+        write_files(b"x", file.to_str().unwrap());
+        fs::write(root.join("a/kept"), b"y").unwrap();
+        delete_path(&file);
+        assert!(!root.join("a/b").exists());
+        assert!(root.join("a/kept").exists());
+        // Already removed, e.g. by the user before a checkout
+        delete_path(&file);
+        delete_path(&root.join("a/b/missing"));
+        fs::remove_dir_all(&root).unwrap();
     }
 }
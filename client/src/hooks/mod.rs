@@ -0,0 +1,46 @@
+/*
+Module running the client-side hooks found in .lrngit/hooks
+*/
+
+use std::{
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Stdio, exit},
+};
+
+/// Run the hook `name` with the given arguments, `input` is written on its stdin.
+/// A missing or non executable hook is skipped. Return false if the hook failed.
+pub fn run_hook(name: &str, args: &[&str], input: &[u8]) -> bool {
+    let path = Path::new(".lrngit/hooks").join(name);
+    let executable = path
+        .metadata()
+        .is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0);
+    if !executable {
+        return true;
+    }
+    let child = Command::new(&path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(c) => c,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to run the {name} hook: {e}"));
+            return false;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The hook may exit without reading its input
+        let _ = stdin.write_all(input);
+    }
+    child.wait().is_ok_and(|x| x.success())
+}
+
+/// Run a hook that can stop the command, exit if it fails
+pub fn run_hook_or_exit(name: &str, args: &[&str], input: &[u8]) {
+    if !run_hook(name, args, input) {
+        lrncore::logs::error_log(&format!("The {name} hook failed, aborting"));
+        exit(1);
+    }
+}
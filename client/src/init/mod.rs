@@ -14,7 +14,7 @@ use crate::{
     object::index,
     refs::{
         init_head,
-        origin::{init_origin_head, init_origin_main, init_remote_origin},
+        origin::init_origin_head,
     },
 };
 
@@ -64,7 +64,6 @@ pub fn init_local_repo() {
         .arg(".lrngit/refs/heads")
         .arg(".lrngit/refs/tags")
         .arg(".lrngit/refs/remotes")
-        .arg(".lrngit/refs/remotes/origin")
         .spawn()
         .expect("Failed to create all directories");
     let wait_mkdir = mkdir.wait().expect("Failed to wait the mkdir command");
@@ -73,12 +72,8 @@ pub fn init_local_repo() {
     }
     // Init head file
     init_head();
-    // Init remote origin dir and files
-    init_remote_origin();
     // Init ORIG_HEAD file
     init_origin_head();
-    // Init default origin branch(main)
-    init_origin_main();
     // Init repository local config
     config::init_config_repo();
     // Init index
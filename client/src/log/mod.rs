@@ -1,109 +1,306 @@
-use std::{env, io::Read, process::exit};
+use std::{env, process::exit};
 
-use lrngitcore::objects::commit::{parse_commit, parse_commit_author, parse_init_commit, CommitObject, CommitUser, InitCommitContent};
-use lrngitcore::objects::utils::get_file_by_hash;
+use lrngitcore::{
+    objects::commit::{commit_parents, parse_commit_author, CommitContent, CommitObject, CommitUser},
+    revwalk::{RevWalk, Sort},
+};
 
 use crate::{
-    refs::parse_current_branch, utils
+    object::{
+        commit::{parse_commit_by_hash, parse_date_or_exit},
+        utils::target_walk_root_tree,
+    },
+    refs::{parse_current_branch, resolve_commit_revision},
+    utils::{self, DateFormat},
+    verify_commit::print_signature,
 };
 
+/// Options of the log command, filters are all applied together
+#[derive(Default)]
+struct LogOptions {
+    max_count: Option<usize>,
+    oneline: bool,
+    format: Option<String>,
+    author: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    grep: Option<String>,
+    paths: Vec<String>,
+    sorting: Sort,
+    first_parent: bool,
+    date_format: DateFormat,
+    show_signature: bool,
+    // Revisions to start from, `^rev` and ranges exclude history
+    revisions: Vec<String>,
+}
+
 pub fn log_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        log_commits();
-        exit(0);
+    let options = parse_log_args(&args[2..]);
+    log_commits(&options);
+    exit(0);
+}
+
+fn parse_log_args(args: &[String]) -> LogOptions {
+    let mut options = LogOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "-n" => {
+                i += 1;
+                options.max_count = Some(parse_count(args.get(i).map(|x| x.as_str())));
+            }
+            "--oneline" => options.oneline = true,
+            "--topo-order" => options.sorting = Sort::Topological,
+            "--date-order" => options.sorting = Sort::Time,
+            "--first-parent" => options.first_parent = true,
+            "--show-signature" => options.show_signature = true,
+            "--" => {
+                options.paths = args[i + 1..]
+                    .iter()
+                    .map(|x| x.trim_end_matches('/').to_owned())
+                    .collect();
+                break;
+            }
+            _ => {
+                if let Some(count) = arg.strip_prefix("--max-count=") {
+                    options.max_count = Some(parse_count(Some(count)));
+                } else if let Some(count) = arg.strip_prefix("-n") {
+                    options.max_count = Some(parse_count(Some(count)));
+                } else if let Some(format) = arg.strip_prefix("--format=") {
+                    options.format = Some(format.to_owned());
+                } else if let Some(author) = arg.strip_prefix("--author=") {
+                    options.author = Some(author.to_owned());
+                } else if let Some(since) = arg.strip_prefix("--since=") {
+                    options.since = Some(parse_date_or_exit(since).0);
+                } else if let Some(until) = arg.strip_prefix("--until=") {
+                    options.until = Some(parse_date_or_exit(until).0);
+                } else if let Some(format) = arg.strip_prefix("--date=") {
+                    options.date_format = DateFormat::parse(format).unwrap_or_else(|| {
+                        lrncore::logs::error_log(&format!("unknown date format {format}"));
+                        exit(1);
+                    });
+                } else if let Some(grep) = arg.strip_prefix("--grep=") {
+                    options.grep = Some(grep.to_owned());
+                } else if arg.starts_with('-') {
+                    lrncore::logs::warning_log("Unknown command");
+                    exit(1);
+                } else {
+                    options.revisions.push(arg.to_owned());
+                }
+            }
+        }
+        i += 1;
     }
-    match args[2].as_str() {
-        "" => {
-            todo!()
+    options
+}
+
+fn parse_count(count: Option<&str>) -> usize {
+    match count.and_then(|x| x.parse::<usize>().ok()) {
+        Some(c) => c,
+        None => {
+            lrncore::logs::error_log("-n expects a number of commits");
+            exit(1);
         }
-        _ => {
-            lrncore::logs::warning_log("Unknown command");
+    }
+}
+
+/// log all commits matching the options
+fn log_commits(options: &LogOptions) {
+    let mut walk = RevWalk::new(".lrngit");
+    if options.revisions.is_empty() {
+        let last_commit = parse_current_branch();
+        if last_commit.is_empty() {
+            lrncore::logs::error_log("Your current branch does not have any commits yet");
             exit(1);
         }
+        walk.push(&last_commit);
+    }
+    for revision in &options.revisions {
+        push_revision(&mut walk, revision);
+    }
+    walk.set_sorting(options.sorting);
+    if options.first_parent {
+        walk.simplify_first_parent();
+    }
+    let mut shown: usize = 0;
+    for hash in walk {
+        if options.max_count.is_some_and(|max| shown >= max) {
+            break;
+        }
+        let hash = match hash {
+            Ok(h) => h,
+            Err(e) => {
+                lrncore::logs::error_log(&format!("Failed to walk history: {e}"));
+                exit(1);
+            }
+        };
+        let each = CommitObject {
+            commit_content: parse_commit_by_hash(&hash),
+            commit_hash: hash.into_bytes(),
+        };
+        let author = parse_commit_author(each.commit_content.author.clone());
+        if !match_filters(options, &each, &author) {
+            continue;
+        }
+        shown += 1;
+        let hash = str::from_utf8(&each.commit_hash).unwrap();
+        let message = String::from_utf8_lossy(&each.commit_content.message);
+        // Custom formats have no place for the signature, it's printed before the commit
+        if options.show_signature
+            && !each.commit_content.signature.is_empty()
+            && (options.format.is_some() || options.oneline)
+        {
+            print_signature(&each.commit_content);
+        }
+        if let Some(format) = &options.format {
+            println!(
+                "{}",
+                format_commit(format, hash, &author, &message, options.date_format)
+            );
+        } else if options.oneline {
+            println!("{} {}", &hash[..7], message.lines().next().unwrap_or_default());
+        } else {
+            print_commit(hash, &each.commit_content, options.date_format, options.show_signature);
+        }
     }
 }
 
-/// log all commits
-fn log_commits() {
-    let last_commit = parse_current_branch();
-    let last_commit_buf = last_commit.as_bytes().to_vec();
-    let commit_vec: Vec<CommitObject> = Vec::new();
-    let mut commits_vec: (Vec<CommitObject>, InitCommitContent) = (
-        commit_vec,
-        InitCommitContent {
-            tree: [0u8; 20],
-            author: vec![],
-            commiter: vec![],
-            message: vec![],
-        },
-    );
-    commits_vec = unwind_commits(last_commit_buf, commits_vec);
-    let mut author: CommitUser;
-    for each in commits_vec.0 {
-        println!("commit: {}", str::from_utf8(&each.commit_hash).unwrap());
-        author = parse_commit_author(each.commit_content.author);
-        println!(
-            "author: {} {}",
-            String::from_utf8_lossy(&author.name),
-            String::from_utf8_lossy(&author.email)
-        );
-        println!(
-            "date: {} {}",
-            utils::timestamp_to_datetime(author.timestamp),
-            str::from_utf8(&author.timezone).expect("Failed to parse timezone to str")
-        );
-        println!(
-            "\n\t{}",
-            String::from_utf8_lossy(&each.commit_content.message)
-        );
-        println!();
+/// Print a commit in the default log format, hash, author, date and message. With
+/// `show_signature` the result of the signature verification follows the hash.
+pub fn print_commit(
+    hash: &str,
+    commit: &CommitContent,
+    date_format: DateFormat,
+    show_signature: bool,
+) {
+    let author = parse_commit_author(commit.author.clone());
+    println!("commit: {hash}");
+    if show_signature && !commit.signature.is_empty() {
+        print_signature(commit);
     }
-    let init_commit = commits_vec.1;
-    author = parse_commit_author(init_commit.author);
-    println!("commit: {}", hex::encode(init_commit.tree));
     println!(
         "author: {} {}",
         String::from_utf8_lossy(&author.name),
         String::from_utf8_lossy(&author.email)
     );
     println!(
-        "date: {} {}",
-        utils::timestamp_to_datetime(author.timestamp),
-        str::from_utf8(&author.timezone).expect("Failed to parse timezone to str")
+        "date: {}",
+        utils::format_date(author.timestamp, &author.timezone, date_format)
     );
-    println!("\n\t{}", String::from_utf8_lossy(&init_commit.message));
+    println!("\n\t{}", String::from_utf8_lossy(&commit.message));
+    println!();
 }
 
-fn unwind_commits(
-    commit_hash: Vec<u8>,
-    mut commits: (Vec<CommitObject>, InitCommitContent),
-) -> (Vec<CommitObject>, InitCommitContent) {
-    let mut commit_object = get_file_by_hash(
-        str::from_utf8(&commit_hash).expect("Failed to convert buffer to str"), ".lrngit"
-    );
-    let mut content_buf: Vec<u8> = Vec::new();
-    commit_object
-        .read_to_end(&mut content_buf)
-        .expect("Failed to read commit content");
-    // decode buffer using zlib
-    let mut d = flate2::read::ZlibDecoder::new(content_buf.as_slice());
-    let mut buffer = Vec::new();
-    // read decoded file and fill buffer
-    d.read_to_end(&mut buffer).unwrap();
-
-    let parse_commit = parse_commit(buffer.clone());
-    let init_commit: InitCommitContent;
-    if parse_commit.is_err() {
-        init_commit = parse_init_commit(buffer).unwrap();
-        commits.1 = init_commit;
-        return commits;
+/// Add a revision to the walk, `^A` hides A, `A..B` and `A...B` are ranges and an empty side of
+/// a range means HEAD
+fn push_revision(walk: &mut RevWalk, revision: &str) {
+    let resolve = |x: &str| resolve_commit_revision(if x.is_empty() { "HEAD" } else { x });
+    let range = if let Some((a, b)) = revision.split_once("...") {
+        format!("{}...{}", resolve(a), resolve(b))
+    } else if let Some((a, b)) = revision.split_once("..") {
+        format!("{}..{}", resolve(a), resolve(b))
+    } else if let Some(hidden) = revision.strip_prefix('^') {
+        walk.hide(&resolve_commit_revision(hidden));
+        return;
+    } else {
+        walk.push(&resolve_commit_revision(revision));
+        return;
     };
-    let commit_unwrapped = parse_commit.unwrap();
-    let new_commit_object: CommitObject = CommitObject {
-        commit_hash,
-        commit_content: commit_unwrapped.clone(),
-    };
-    commits.0.push(new_commit_object);
-    unwind_commits(commit_unwrapped.parent.to_vec(), commits)
+    if let Err(e) = walk.push_range(&range) {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(128);
+    }
+}
+
+/// Check if a commit match the author, date, message and path filters
+fn match_filters(options: &LogOptions, commit: &CommitObject, author: &CommitUser) -> bool {
+    if let Some(pattern) = &options.author {
+        let identity = format!(
+            "{} <{}>",
+            String::from_utf8_lossy(&author.name),
+            String::from_utf8_lossy(&author.email)
+        );
+        if !identity.contains(pattern.as_str()) {
+            return false;
+        }
+    }
+    if options.since.is_some_and(|since| author.timestamp < since)
+        || options.until.is_some_and(|until| author.timestamp > until)
+    {
+        return false;
+    }
+    if let Some(pattern) = &options.grep
+        && !String::from_utf8_lossy(&commit.commit_content.message).contains(pattern.as_str())
+    {
+        return false;
+    }
+    if !options.paths.is_empty() && !touch_paths(commit, &options.paths) {
+        return false;
+    }
+    true
+}
+
+/// Check if a commit changed one of the given paths compared to its parent
+fn touch_paths(commit: &CommitObject, paths: &[String]) -> bool {
+    let tree = hex::encode(commit.commit_content.tree);
+    let parent_tree = commit_parents(&commit.commit_content)
+        .first()
+        .map(|x| hex::encode(parse_commit_by_hash(x).tree));
+    paths.iter().any(|path| {
+        let mut hash = [0u8; 20];
+        target_walk_root_tree(&tree, path, &mut hash);
+        let mut parent_hash = [0u8; 20];
+        if let Some(parent_tree) = &parent_tree {
+            target_walk_root_tree(parent_tree, path, &mut parent_hash);
+        }
+        hash != parent_hash
+    })
+}
+
+/// Expand a format template. Supported placeholders are `%H` hash, `%h` short hash, `%an` author
+/// name, `%ae` author email, `%ad` author date, `%s` subject, `%n` new line and `%%`.
+fn format_commit(
+    template: &str,
+    hash: &str,
+    author: &CommitUser,
+    message: &str,
+    date_format: DateFormat,
+) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('H') => out.push_str(hash),
+            Some('h') => out.push_str(&hash[..7]),
+            Some('s') => out.push_str(message.lines().next().unwrap_or_default()),
+            Some('n') => out.push('\n'),
+            Some('%') => out.push('%'),
+            Some('a') => match chars.next() {
+                Some('n') => out.push_str(&String::from_utf8_lossy(&author.name)),
+                Some('e') => out.push_str(&String::from_utf8_lossy(&author.email)),
+                Some('d') => out.push_str(&utils::format_date(
+                    author.timestamp,
+                    &author.timezone,
+                    date_format,
+                )),
+                Some(other) => {
+                    out.push_str("%a");
+                    out.push(other);
+                }
+                None => out.push_str("%a"),
+            },
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
 }
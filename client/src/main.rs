@@ -1,19 +1,34 @@
 mod add;
 pub mod branch;
 mod commit;
+mod commit_graph;
+mod cat_file;
+mod cherry_pick;
 mod config;
 pub mod fs;
+mod hooks;
 mod init;
 mod log;
+mod merge_base;
+mod reflog;
+mod rev_parse;
+mod revert;
+mod sequencer;
+mod show;
+mod stash;
 pub mod macros;
 pub mod object;
 pub mod parser;
+mod plumbing;
 pub mod refs;
 mod status;
 mod switch;
+mod tag;
+mod verify_commit;
 pub mod utils;
 pub mod types;
 mod push;
+mod rebase;
 pub mod remote;
 pub mod pack;
 pub mod tcp;
@@ -39,16 +54,34 @@ Commands:
     commit          Commit to the local repository
     push            Push to remote repository
     pull            Pull from remote repositoy
-    branch          Create a new branch or list all branches
+    branch          List, create, rename or delete branches
     switch          Switch branch to the given one
-    cat-file        Cat content of a given hash
+    stash           Save local changes away and apply them back later
+    cherry-pick     Apply the changes of existing commits on the current branch
+    revert          Create commits reverting the changes of existing commits
+    rebase          Replay the commits of the current branch on top of another one
+    tag             Create, list or delete tags
+    cat-file        Print the type, size or content of an object
+    show            Show a commit with its changes, a tree or a blob
     ls-file         Print content of the index file
     status          Show the status of the local repository
     log             Show the commit historic
+    merge-base      Find the best common ancestor of two commits
+    rev-parse       Resolve revisions to object hashes
+    reflog          Show the history of updates of a ref
+    verify-commit   Check the signature of commits against the allowed signers
+    commit-graph    Write the commit-graph file used to speed up history walks
     config          Manage config
     help            Show this help message
     version         Show the version
 
+Plumbing commands:
+    hash-object     Compute the blob hash of files, and write them with -w
+    ls-tree         List the content of a tree
+    write-tree      Create a tree from the index
+    commit-tree     Create a commit from a tree without updating refs
+    update-ref      Update, create or delete a ref
+
 Options:
 
     -h, --help      Show command usage
@@ -61,18 +94,34 @@ Options:
 #[derive(Debug, Clone)]
 enum Commands {
     Init,
-    Add { arg: String },
+    Add { args: Vec<String> },
     Commit,
     Push,
     Pull,
     Branch,
     Switch,
-    CatFile { arg: String },
+    Stash,
+    CherryPick,
+    Revert,
+    Rebase,
+    Tag,
+    CatFile,
+    Show,
     LsFile,
     Status,
     Remote,
     Log,
+    MergeBase,
+    RevParse,
+    Reflog,
+    VerifyCommit,
+    CommitGraph,
     Config,
+    HashObject,
+    LsTree,
+    WriteTree,
+    CommitTree,
+    UpdateRef,
     Version,
     Help,
 }
@@ -80,7 +129,9 @@ enum Commands {
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if let Some(arg) = args.iter().last() {
+    // Only a lone flag asks for the version, commands like `branch -v` have their own -v
+    if args.len() == 2 {
+        let arg = &args[1];
         match arg.as_str().trim() {
             "-v" => {
                 lrncore::usage_exit::command_usage(&lrngit_version());
@@ -95,35 +146,42 @@ fn main() {
     let command = match args.get(1).map(|s| s.as_str()) {
         Some("init") => Commands::Init,
         Some("add") => Commands::Add {
-            arg: args
-                // Get index 2 because 0 is the binary, 1 the command and 2 the arg passed to the command
-                .get(2)
-                .unwrap_or_else(|| {
+            // Get from index 2 because 0 is the binary, 1 the command and 2.. the args passed to the command
+            args: {
+                if args.len() <= 2 {
                     eprintln!("Please provide a file to add.");
                     exit(1);
-                })
-                .to_string(),
+                }
+                args[2..].to_vec()
+            },
         },
         Some("commit") => Commands::Commit,
         Some("push") => Commands::Push,
         Some("pull") => Commands::Pull,
         Some("branch") => Commands::Branch,
         Some("switch") => Commands::Switch,
-        Some("cat-file") => Commands::CatFile {
-            arg: args
-                // Get index 2 because 0 is the binary, 1 the command and 2 the arg passed to the command
-                .get(2)
-                .unwrap_or_else(|| {
-                    eprintln!("Please provide a file to add.");
-                    exit(1);
-                })
-                .to_string(),
-        },
+        Some("stash") => Commands::Stash,
+        Some("cherry-pick") => Commands::CherryPick,
+        Some("revert") => Commands::Revert,
+        Some("rebase") => Commands::Rebase,
+        Some("tag") => Commands::Tag,
+        Some("cat-file") => Commands::CatFile,
+        Some("show") => Commands::Show,
         Some("ls-file") => Commands::LsFile,
         Some("status") => Commands::Status,
         Some("remote") => Commands::Remote,
         Some("log") => Commands::Log,
+        Some("merge-base") => Commands::MergeBase,
+        Some("rev-parse") => Commands::RevParse,
+        Some("reflog") => Commands::Reflog,
+        Some("verify-commit") => Commands::VerifyCommit,
+        Some("commit-graph") => Commands::CommitGraph,
         Some("config") => Commands::Config,
+        Some("hash-object") => Commands::HashObject,
+        Some("ls-tree") => Commands::LsTree,
+        Some("write-tree") => Commands::WriteTree,
+        Some("commit-tree") => Commands::CommitTree,
+        Some("update-ref") => Commands::UpdateRef,
         Some("version") => Commands::Version,
         Some("help") => Commands::Help,
         _ => {
@@ -134,18 +192,34 @@ fn main() {
 
     match command {
         Commands::Init => init::init_command(),
-        Commands::Add { arg } => add::add_to_local_repo(arg),
+        Commands::Add { args } => add::add_to_local_repo(args),
         Commands::Commit => commit::commit_command(),
         Commands::Push => push::push_command(),
         Commands::Pull => pull::pull_command(),
         Commands::Branch => branch::branch_command(),
         Commands::Switch => switch::switch_command(),
-        Commands::CatFile { arg } => object::utils::read_blob_file(&arg),
+        Commands::Stash => stash::stash_command(),
+        Commands::CherryPick => cherry_pick::cherry_pick_command(),
+        Commands::Revert => revert::revert_command(),
+        Commands::Rebase => rebase::rebase_command(),
+        Commands::Tag => tag::tag_command(),
+        Commands::CatFile => cat_file::cat_file_command(),
+        Commands::Show => show::show_command(),
         Commands::LsFile => object::index::ls_file(),
         Commands::Status => status::status_command(),
         Commands::Remote => remote::remote_command(),
         Commands::Log => log::log_command(),
+        Commands::MergeBase => merge_base::merge_base_command(),
+        Commands::RevParse => rev_parse::rev_parse_command(),
+        Commands::Reflog => reflog::reflog_command(),
+        Commands::VerifyCommit => verify_commit::verify_commit_command(),
+        Commands::CommitGraph => commit_graph::commit_graph_command(),
         Commands::Config => config::config_commands(),
+        Commands::HashObject => plumbing::hash_object::hash_object_command(),
+        Commands::LsTree => plumbing::ls_tree::ls_tree_command(),
+        Commands::WriteTree => plumbing::write_tree::write_tree_command(),
+        Commands::CommitTree => plumbing::commit_tree::commit_tree_command(),
+        Commands::UpdateRef => plumbing::update_ref::update_ref_command(),
         Commands::Version => lrncore::usage_exit::command_usage(&lrngit_version()),
         Commands::Help => lrncore::usage_exit::command_usage(lrngit_usage()),
     }
@@ -0,0 +1,48 @@
+use std::{env, process::exit};
+
+use lrngitcore::revwalk::{is_ancestor, merge_base};
+
+use crate::refs::resolve_commit_revision;
+
+pub fn merge_base_command() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() <= 3 {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit merge-base [--is-ancestor] <commit> <commit>",
+        );
+    }
+    match args[2].as_str() {
+        "--is-ancestor" => {
+            if args.len() <= 4 {
+                lrncore::usage_exit::usage_and_exit(
+                    "Invalid command",
+                    "lrngit merge-base --is-ancestor <commit> <commit>",
+                );
+            }
+            // Only the exit code matter, like git
+            let ancestor = resolve_commit_revision(&args[3]);
+            let descendant = resolve_commit_revision(&args[4]);
+            match is_ancestor(".lrngit", &ancestor, &descendant) {
+                Ok(true) => exit(0),
+                Ok(false) => exit(1),
+                Err(e) => {
+                    lrncore::logs::error_log(&format!("{e}"));
+                    exit(128);
+                }
+            }
+        }
+        _ => match merge_base(
+            ".lrngit",
+            &resolve_commit_revision(&args[2]),
+            &resolve_commit_revision(&args[3]),
+        ) {
+            Ok(Some(base)) => println!("{base}"),
+            Ok(None) => exit(1),
+            Err(e) => {
+                lrncore::logs::error_log(&format!("{e}"));
+                exit(128);
+            }
+        },
+    }
+}
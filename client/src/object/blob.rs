@@ -1,54 +1,57 @@
 use blob::{Blob, Standard};
 use lrngitcore::fs::new_file_dir;
 use lrngitcore::objects::blob::{FileHashBlob, BlobObject};
+use lrngitcore::objects::index::IndexEntry;
 use lrngitcore::objects::tree::RWO;
 use lrngitcore::objects::utils::split_object_header;
 
 use super::{index, utils::hash_sha1};
 use crate::object::utils::{compress_file, git_object_header};
+use crate::utils::parallel_map;
 use std::fs;
-use std::fs::File;
 use std::io::{Read, Write};
 use std::os::unix::fs::MetadataExt;
 
-/// The function `add_blob` reads a file, calculates its SHA-1 hash, creates a new blob, and stores the
-/// file in a local repository with error handling.
-///
-/// Arguments:
-///
-/// * `arg`: The function `add_blob` takes a reference to a string `arg` as a parameter. This function
-///   reads the contents of a file specified by the `arg`, calculates its SHA-1 hash, creates a new blob
-///   from the hash, and then stores the blob in a local repository.
-///
-/// Returns:
-///
-/// The function `add_blob` returns a `String` which is the hexadecimal representation of the SHA-1 hash
-/// of the file content that was read and added to the local repository.
-pub fn add_blob(arg: &str) -> [u8; 20] {
-    let blob_hash = compute_file_hash_and_blob(arg)
-        .expect("Failed to get the blob and the hash from the file path");
-    // check index entry
-    index::remove_index_entry(arg);
-    // creation of file to local repo
-    let mut file: File;
-    let file_result = new_file_dir(&blob_hash.hash_split);
-    match file_result {
-        Ok(f) => file = f,
-        Err(e) => {
-            lrncore::logs::error_log(&format!("Error writing to tree file: {e}"));
-            return [0u8; 20];
+// Files read and compressed at once by add_blobs, bounds the memory held before writing them
+const ADD_CHUNK_SIZE: usize = 256;
+
+/// Add a batch of files to the local repository. Files are read, hashed and compressed across the
+/// worker pool by chunks, the objects of a chunk are written before the next one is read. The
+/// index is updated once for the whole batch.
+pub fn add_blobs(paths: &[String]) {
+    let mut new_entries: Vec<IndexEntry> = Vec::new();
+    for chunk in paths.chunks(ADD_CHUNK_SIZE) {
+        let blobs = parallel_map(chunk, |path| {
+            let blob_hash = compute_file_hash_and_blob(path).ok()?;
+            let metadata = fs::metadata(path).ok()?;
+            let compressed_bytes_vec = compress_file(blob_hash.blob.clone());
+            Some((blob_hash, compressed_bytes_vec, metadata))
+        });
+        for (path, blob) in chunk.iter().zip(blobs) {
+            let Some((blob_hash, compressed_bytes_vec, metadata)) = blob else {
+                lrncore::logs::warning_log(&format!("Skipping {path}"));
+                continue;
+            };
+            // Objects are written sequentially, object folders creation isn't safe across threads
+            let mut file = match new_file_dir(&blob_hash.hash_split) {
+                Ok(f) => f,
+                Err(e) => {
+                    lrncore::logs::error_log(&format!("Error writing to tree file: {e}"));
+                    continue;
+                }
+            };
+            file.write_all(&compressed_bytes_vec).unwrap();
+            new_entries.push(IndexEntry {
+                mtime: metadata.mtime().try_into().unwrap(),
+                file_size: metadata.len().try_into().unwrap(),
+                mode: RWO,
+                hash: blob_hash.hash,
+                flag: 0,
+                path: path.clone().into_bytes(),
+            });
         }
     }
-    let compressed_bytes_vec = compress_file(blob_hash.blob);
-    // write compress file with zlib to file
-    file.write_all(&compressed_bytes_vec).unwrap();
-    let added_file_metadata = fs::metadata(arg).expect("Failed to get added file metadata");
-    let mtime: u32 = added_file_metadata.mtime().try_into().unwrap();
-    let file_size: u32 = added_file_metadata.len().try_into().unwrap();
-    let mode: u32 = RWO;
-    let path = arg.to_string().into_bytes();
-    index::add_index_entry(mtime, file_size, mode, blob_hash.hash, path);
-    blob_hash.hash
+    index::add_index_entries(new_entries);
 }
 
 // Compute file hash and create blob object
@@ -62,8 +65,13 @@ pub fn compute_file_hash_and_blob(file_path: &str) -> Result<FileHashBlob, std::
             return Err(e);
         }
     };
+    Ok(hash_blob_content(file.as_bytes()))
+}
+
+/// Create the blob object of the given content and compute its hash
+pub fn hash_blob_content(content: &[u8]) -> FileHashBlob {
     // creation of blob object
-    let new_blob: Blob<Standard> = Blob::from(file.as_bytes());
+    let new_blob: Blob<Standard> = Blob::from(content);
     let blob_object: BlobObject = BlobObject {
         header: git_object_header("blob", new_blob.len()),
         content: new_blob.to_vec(),
@@ -75,11 +83,21 @@ pub fn compute_file_hash_and_blob(file_path: &str) -> Result<FileHashBlob, std::
     let new_hash: [u8; 20];
     let split_hash_result_hex: Vec<char>;
     (new_hash, split_hash_result_hex) = hash_sha1(&blob_object_concat);
-    Ok(FileHashBlob {
+    FileHashBlob {
         blob: blob_object_concat,
         hash: new_hash,
         hash_split: split_hash_result_hex,
-    })
+    }
+}
+
+/// Write a blob object in the local repository without touching the index, exit on failure
+pub fn write_blob(blob: &FileHashBlob) {
+    let compressed = compress_file(blob.blob.clone());
+    let result = new_file_dir(&blob.hash_split).and_then(|mut file| file.write_all(&compressed));
+    if let Err(e) = result {
+        lrncore::logs::error_log(&format!("Failed to write object: {e}"));
+        std::process::exit(1);
+    }
 }
 
 /// Open a blob file and return it's content
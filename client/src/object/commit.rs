@@ -1,108 +1,169 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::env;
+use std::path::PathBuf;
 use std::process::exit;
 use std::time::SystemTime;
 
-use chrono::{Local, Offset};
-use lrngitcore::fs::new_file_dir;
-use lrngitcore::objects::commit::{parse_commit, CommitContent, CommitUser, InitCommitContent};
-use lrngitcore::objects::utils::get_file_by_hash;
+use lrngitcore::config::Config;
+use lrngitcore::objects::commit::{
+    append_signature, commit_payload, read_commit, CommitContent, CommitUser,
+};
 
 use crate::config;
-use crate::object::utils::{compress_file, git_object_header};
+use crate::object::{signature::sign_payload, utils::write_object};
+use crate::utils::{local_timezone, parse_identity_date};
 use crate::refs::{init_refs, parse_current_branch};
 
-use super::utils::hash_sha1;
-
-/// Create a new commit object.
-/// Get the author and commiter from the git config.
-///
-///
-pub fn create_commit_object(root_tree_hash: [u8; 20], commit_message: &str) {
-    let global_config = config::parse_global_config();
-    let offset = Local::now().offset().fix().local_minus_utc();
-    let sign = if offset >= 0 { "+" } else { "-" };
-    let hours = offset.abs() / 3600;
-    let minutes = (offset.abs() % 3600) / 60;
-    let tz_str = format!("{sign}{hours:02}{minutes:02}");
-    let commiter: CommitUser = CommitUser {
-        name: global_config.user.name.as_bytes().to_vec(),
-        email: global_config.user.email.as_bytes().to_vec(),
-        timestamp: SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64,
-        timezone: tz_str.as_bytes().to_vec(),
-    };
-    let commiter_bytes: Vec<u8> =
-        bincode::serialize(&commiter).expect("Failed to serialize CommitUser struct");
+/// Create a new commit object on top of the current branch and move the branch to it.
+/// The commiter is taken from the env or the config.
+pub fn create_commit_object(root_tree_hash: [u8; 20], commit_message: &str, author: &CommitUser) {
     let parent_commit = parse_current_branch();
-    let commit_content_bytes: Vec<u8> = if parent_commit.is_empty() {
-        let init_commit_content: InitCommitContent = InitCommitContent {
-            tree: root_tree_hash,
-            author: commiter_bytes.clone(),
-            commiter: commiter_bytes.clone(),
-            message: commit_message.as_bytes().to_vec(),
-        };
-        bincode::serialize(&init_commit_content).expect("Failed to serialize commit content")
+    let commit_hash_string =
+        write_commit_object_as(root_tree_hash, &parent_commit, commit_message, author);
+    let subject = commit_message.lines().next().unwrap_or_default();
+    let reason = if parent_commit.is_empty() {
+        format!("commit (initial): {subject}")
     } else {
-        let commit_content: CommitContent = CommitContent {
-            tree: root_tree_hash,
-            // Inline field to copy parent bytes as buffer in a buffer of 20 bytes
-            parent: parent_commit.as_bytes().to_vec(),
-            author: commiter_bytes.clone(),
-            commiter: commiter_bytes,
-            message: commit_message.as_bytes().to_vec(),
-        };
-        bincode::serialize(&commit_content).expect("Failed to serialize commit content")
+        format!("commit: {subject}")
     };
-    let mut commit_bytes: Vec<u8> = Vec::new();
-    commit_bytes.extend_from_slice(&git_object_header("commit", commit_content_bytes.len()));
-    commit_bytes.extend_from_slice(&commit_content_bytes);
-    let commit_bytes_compressed = compress_file(commit_bytes.clone());
-    // hash tree content with SHA-1
-    let split_hash_result_hex: Vec<char>;
-    (_, split_hash_result_hex) = hash_sha1(&commit_bytes_compressed);
+    init_refs(commit_hash_string.as_bytes(), &reason);
+}
 
-    // Create folder and file in local repository
-    let mut file: File;
-    let file_result = new_file_dir(&split_hash_result_hex);
-    match file_result {
-        Ok(f) => file = f,
-        Err(e) => {
-            lrncore::logs::error_log(&format!("Error writing to tree file: {e}"));
-            return;
+/// Replace the tip commit of the current branch by a new commit with the given tree and message.
+/// The new commit keeps the parent of the replaced one.
+pub fn amend_commit_object(root_tree_hash: [u8; 20], commit_message: &str, author: &CommitUser) {
+    let tip = parse_commit_by_hash(&parse_current_branch());
+    let parent_commit = String::from_utf8_lossy(&tip.parent).to_string();
+    let commit_hash_string =
+        write_commit_object_as(root_tree_hash, &parent_commit, commit_message, author);
+    let subject = commit_message.lines().next().unwrap_or_default();
+    init_refs(commit_hash_string.as_bytes(), &format!("commit (amend): {subject}"));
+}
+
+/// Write a commit object with the given tree and parent, an empty parent creates a root commit.
+/// Every new commit goes through here and is signed when signing is configured, see
+/// `signing_key`. No ref is updated, return the hash of the new commit.
+pub fn write_commit_object(
+    root_tree_hash: [u8; 20],
+    parent_commit: &str,
+    commit_message: &str,
+) -> String {
+    let author = author_identity();
+    write_commit_object_as(root_tree_hash, parent_commit, commit_message, &author)
+}
+
+/// Write a commit object like `write_commit_object`, with the given author. The commiter is
+/// still the current user, used when commits are replayed.
+pub fn write_commit_object_as(
+    root_tree_hash: [u8; 20],
+    parent_commit: &str,
+    commit_message: &str,
+    author: &CommitUser,
+) -> String {
+    let commit = new_commit_content(root_tree_hash, parent_commit, commit_message, author);
+    let mut commit_content_bytes =
+        commit_payload(&commit).expect("Failed to serialize commit content");
+    if let Some(key_path) = signing_key() {
+        let signature = sign_payload(&key_path, &commit_content_bytes);
+        append_signature(&mut commit_content_bytes, &signature)
+            .expect("Failed to serialize commit signature");
+    }
+    write_object("commit", &commit_content_bytes)
+}
+
+/// Key file signing new commits, set with `signingkey` in the `[user]` section of the config.
+/// Commits are signed whenever a key is set unless `commit.gpgsign` is false.
+fn signing_key() -> Option<PathBuf> {
+    let config = Config::load(".lrngit");
+    if !config.get_bool_or("commit.gpgsign", true) {
+        return None;
+    }
+    config.get_path("user.signingkey")
+}
+
+fn new_commit_content(
+    root_tree_hash: [u8; 20],
+    parent_commit: &str,
+    commit_message: &str,
+    author: &CommitUser,
+) -> CommitContent {
+    let commiter = committer_identity();
+    CommitContent {
+        tree: root_tree_hash,
+        // An empty parent makes a root commit
+        parent: parent_commit.as_bytes().to_vec(),
+        author: bincode::serialize(author).expect("Failed to serialize CommitUser struct"),
+        commiter: bincode::serialize(&commiter).expect("Failed to serialize CommitUser struct"),
+        message: commit_message.as_bytes().to_vec(),
+        signature: vec![],
+    }
+}
+
+/// Identity of the author of new commits, from the `LRNGIT_AUTHOR_*` env vars or the config
+pub fn author_identity() -> CommitUser {
+    identity("AUTHOR")
+}
+
+/// Identity of the commiter of new commits and of the tagger of new tags, from the
+/// `LRNGIT_COMMITTER_*` env vars or the config
+pub fn committer_identity() -> CommitUser {
+    identity("COMMITTER")
+}
+
+/// Build an identity from `LRNGIT_<role>_NAME`, `LRNGIT_<role>_EMAIL` and `LRNGIT_<role>_DATE`,
+/// falling back to the `[user]` config for the name and email. The date falls back to
+/// `SOURCE_DATE_EPOCH` for reproducible builds, then to now.
+fn identity(role: &str) -> CommitUser {
+    let field = |key: &str| {
+        env::var(format!("LRNGIT_{role}_{}", key.to_uppercase()))
+            .ok()
+            .or_else(|| config::user_field(key))
+    };
+    let (Some(name), Some(email)) = (field("name"), field("email")) else {
+        lrncore::logs::error_log(
+            "Identity unknown, set name and email in the [user] section of ~/.lrngitconfig or .lrngit/config",
+        );
+        exit(1);
+    };
+    let (timestamp, timezone) = if let Ok(date) = env::var(format!("LRNGIT_{role}_DATE")) {
+        parse_date_or_exit(&date)
+    } else if let Ok(epoch) = env::var("SOURCE_DATE_EPOCH") {
+        match epoch.parse::<i64>() {
+            Ok(timestamp) => (timestamp, "+0000".to_owned()),
+            Err(_) => {
+                lrncore::logs::error_log(&format!("Invalid SOURCE_DATE_EPOCH: {epoch}"));
+                exit(1);
+            }
         }
+    } else {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        (now, local_timezone())
+    };
+    CommitUser {
+        name: name.into_bytes(),
+        email: email.into_bytes(),
+        timestamp,
+        timezone: timezone.into_bytes(),
     }
-    // write zlib compressed into file
-    let file_result = file.write_all(&commit_bytes_compressed);
-    match file_result {
-        Ok(_) => (),
-        Err(e) => {
-            lrncore::logs::error_log(&format!("Error writing to tree file: {e}"));
-            exit(1)
+}
+
+/// Parse a date given on the command line or in the env to a timestamp and a timezone
+pub fn parse_date_or_exit(date: &str) -> (i64, String) {
+    match parse_identity_date(date) {
+        Some(d) => d,
+        None => {
+            lrncore::logs::error_log(&format!("Invalid date format: {date}"));
+            exit(1);
         }
     }
-    let commit_hash_string: String = split_hash_result_hex.iter().collect();
-    let commit_hash_bytes = commit_hash_string.as_bytes();
-    init_refs(commit_hash_bytes);
 }
 
-/// Parse the commit object from is hash and return a readable commit object
-#[allow(dead_code)]
+/// Parse the commit object from is hash and return a readable commit object.
+/// The initial commit has no parent, it's returned with an empty `parent` field.
 pub fn parse_commit_by_hash(hash: &str) -> CommitContent {
-    let mut commit_object = get_file_by_hash(hash, ".lrngit");
-    let mut content_buf: Vec<u8> = Vec::new();
-    commit_object
-        .read_to_end(&mut content_buf)
-        .expect("Failed to read commit content");
-    // decode buffer using zlib
-    let mut d = flate2::read::ZlibDecoder::new(content_buf.as_slice());
-    let mut buffer = Vec::new();
-    // Read decoded file and populate buffer
-    d.read_to_end(&mut buffer).unwrap();
-
-    match parse_commit(buffer) {
+    match read_commit(hash, ".lrngit") {
         Ok(c) => c,
         Err(e) => {
             lrncore::logs::error_log(&format!("Error parsing commit: {e}"));
@@ -110,4 +171,3 @@ pub fn parse_commit_by_hash(hash: &str) -> CommitContent {
         }
     }
 }
-
@@ -1,37 +1,28 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::{self, File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufWriter, Write},
     os::unix::fs::MetadataExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
 };
 
-use lrngitcore::objects::{index::{IndexEntry, IndexHeader, IndexObject, TempIndex}, tree::RWO};
-
-use crate::{
-    object::{commit, utils::walk_root_tree_content},
-    refs::parse_current_branch,
+use lrngitcore::objects::{
+    index::{CachedTree, IndexEntry, IndexHeader, IndexObject, TempIndex},
+    tree::{tree_files, RWO},
 };
 
+use crate::object::commit;
+
 pub fn init_index() {
-    // header
-    let magic_number = b"DIRC";
-    let header: IndexHeader = IndexHeader {
-        magic_number: *magic_number,
-        version: 1,
-        entry_count: 0,
-    };
-    let mut header_bytes: Vec<u8> = Vec::new();
-    header_bytes.extend_from_slice(&header.magic_number);
-    header_bytes.push(header.version);
-    header_bytes.push(header.entry_count);
-    // index file
     let index: IndexObject = IndexObject {
-        header,
+        header: IndexHeader::new(0),
         entries: vec![],
+        cached_trees: vec![],
     };
-    let index_bytes: Vec<u8> =
-        bincode::serialize(&index).expect("Failed to serialize index struct into bytes");
+    let index_bytes: Vec<u8> = index
+        .to_bytes()
+        .expect("Failed to serialize index struct into bytes");
     let mut index_file = match File::create(".lrngit/index") {
         Ok(f) => f,
         Err(e) => {
@@ -51,8 +42,9 @@ pub fn init_index() {
 /// add a new indew entry to the index content
 pub fn add_index_entry(mtime: u32, file_size: u32, mode: u32, hash: [u8; 20], path: Vec<u8>) {
     let index = parse_index();
-    let mut header = index.header;
     let mut entries = index.entries;
+    let mut cached_trees = index.cached_trees;
+    invalidate_cached_trees(&mut cached_trees, &path);
     let new_entry: IndexEntry = IndexEntry {
         mtime,
         file_size,
@@ -63,11 +55,60 @@ pub fn add_index_entry(mtime: u32, file_size: u32, mode: u32, hash: [u8; 20], pa
     };
     entries.push(new_entry);
     entries.sort();
-    header.entry_count += 1;
-    let updated_index: IndexObject = IndexObject { header, entries };
+    let updated_index: IndexObject = IndexObject {
+        header: IndexHeader::new(entries.len()),
+        entries,
+        cached_trees,
+    };
+    update_index(updated_index);
+}
+
+/// Add a batch of index entries at once, replacing existing entries with the same path. Used when
+/// adding many files to avoid parsing and rewriting the index for each of them.
+pub fn add_index_entries(new_entries: Vec<IndexEntry>) {
+    let index = parse_index();
+    let mut entries: HashMap<Vec<u8>, IndexEntry> = index
+        .entries
+        .into_iter()
+        .map(|x| (x.path.clone(), x))
+        .collect();
+    let mut cached_trees = index.cached_trees;
+    for each in new_entries {
+        // Re-adding an unchanged file keeps the trees holding it
+        if entries.get(&each.path).is_none_or(|x| x.hash != each.hash) {
+            invalidate_cached_trees(&mut cached_trees, &each.path);
+        }
+        entries.insert(each.path.clone(), each);
+    }
+    let mut entries: Vec<IndexEntry> = entries.into_values().collect();
+    entries.sort();
+    let updated_index: IndexObject = IndexObject {
+        header: IndexHeader::new(entries.len()),
+        entries,
+        cached_trees,
+    };
     update_index(updated_index);
 }
 
+/// Drop the cached trees of the root and of every directory holding `path`
+fn invalidate_cached_trees(cached_trees: &mut Vec<CachedTree>, path: &[u8]) {
+    let path = String::from_utf8_lossy(path);
+    cached_trees.retain(|x| !x.path.is_empty() && !path.starts_with(&format!("{}/", x.path)));
+}
+
+/// Replace the cached-tree extension of the index, entries are left untouched
+pub fn write_cached_trees(cached_trees: Vec<CachedTree>) {
+    let index = parse_index();
+    if index.cached_trees == cached_trees {
+        return;
+    }
+    update_index(IndexObject {
+        header: index.header,
+        entries: index.entries,
+        cached_trees,
+    });
+}
+
 /// update index file with new index object
 fn update_index(index: IndexObject) {
     let f = OpenOptions::new()
@@ -76,99 +117,74 @@ fn update_index(index: IndexObject) {
         .truncate(true)
         .open(".lrngit/index")
         .expect("Unable to open file");
-    let index_as_bytes = bincode::serialize(&index).expect("Failed to serialize new indew file");
+    let index_as_bytes = index.to_bytes().expect("Failed to serialize new indew file");
     let mut f = BufWriter::new(f);
     f.write_all(&index_as_bytes).expect("Unable to write data");
 }
 
 /// parse index file and return structure
 pub fn parse_index() -> IndexObject {
-    // get buffer from file
-    let index_path = ".lrngit/index";
-    let file = File::open(index_path).expect("Failed to open index file");
-    let buffer = BufReader::new(file);
-    let mut bytes_vec: Vec<u8> = Vec::new();
-    for bytes in buffer.bytes() {
-        bytes_vec.push(bytes.unwrap());
-    }
-    // header
-    let header_size = std::mem::size_of::<IndexHeader>();
-    let header_bytes = &bytes_vec[..header_size];
-    let header: IndexHeader =
-        bincode::deserialize(header_bytes).expect("Failed to deserialize header bytes");
-    // contents
-    let content_bytes = &bytes_vec[header_size..];
-    let content: Vec<IndexEntry> =
-        bincode::deserialize(content_bytes).expect("Failed to deserialize content bytes");
-    // index
-    let index: IndexObject = IndexObject {
-        header,
-        entries: content,
-    };
-    index
+    let bytes = fs::read(".lrngit/index").expect("Failed to open index file");
+    IndexObject::from_bytes(&bytes).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to parse index file: {e}"));
+        exit(1)
+    })
 }
 
 /// Remove index entry by entry path
 /// used when adding a tracked file to avoid entry duplication
 pub fn remove_index_entry(entry_path: &str) {
-    let mut entries = parse_index().entries;
+    let index = parse_index();
+    let mut entries = index.entries;
+    let mut cached_trees = index.cached_trees;
     if let Some(pos) = entries
         .iter()
         .position(|x| str::from_utf8(&x.path).unwrap() == entry_path)
     {
         entries.remove(pos);
-        let magic_number = b"DIRC";
-        let header: IndexHeader = IndexHeader {
-            magic_number: *magic_number,
-            version: 1,
-            entry_count: 0,
+        invalidate_cached_trees(&mut cached_trees, entry_path.as_bytes());
+        let updated_index: IndexObject = IndexObject {
+            header: IndexHeader::new(entries.len()),
+            entries,
+            cached_trees,
         };
-        let updated_index: IndexObject = IndexObject { header, entries };
         update_index(updated_index);
     }
 }
 
-/// Recreate an index base on the specified branch.
-/// Parse the last commit and recreate a temporary index and compare it with the current index.
-///
-///
-pub fn build_temp_index(current_index: IndexObject) -> TempIndex {
-    let last_commit = parse_current_branch();
-    let parse_commit = commit::parse_commit_by_hash(&last_commit);
-    let root_tree = hex::encode(parse_commit.tree);
-    let mut temp_index: Vec<(PathBuf, [u8; 20])> = Vec::new();
-    walk_root_tree_content(&root_tree, &mut PathBuf::new(), &mut temp_index);
-    temp_index.sort();
-    temp_index.dedup();
-
+/// Compare the trees of two commits to find the files a checkout from `old_commit` to
+/// `new_commit` has to write or delete. An empty commit hash stands for an empty tree.
+/// Files identical in both commits are left out, so local changes to them are carried over.
+pub fn build_temp_index(old_commit: &str, new_commit: &str) -> TempIndex {
+    let old_files = commit_files(old_commit);
+    let new_files = commit_files(new_commit);
+    let temp_index: Vec<(PathBuf, [u8; 20])> = new_files
+        .iter()
+        .map(|(path, hash)| (PathBuf::from(path), *hash))
+        .collect();
     // Entries that is new in the specified branch
     let mut new_entries: Vec<(PathBuf, [u8; 20])> = Vec::new();
-    // Entries that has been modified between branches
+    // Entries that has been modified between branches, with the hash they have on the new one
     let mut modified_entries: Vec<IndexEntry> = Vec::new();
     // Entries that doesn't exist on the branch switch to
     let mut deleted_entries: Vec<PathBuf> = Vec::new();
-    // Compare current index entries with temp index entries
-    // find occurrence with different hash -> store in modified_entries vector
-    // find occurrence with same hash -> store in same_entries vector
-    // don't find occurrence -> store path in deleted_entries vector
-    // Loop to check if file has been modified or deleted
-    for each in &current_index.entries {
-        if let Some(entry) = temp_index
-            .iter()
-            .find(|x| x.0 == PathBuf::from(str::from_utf8(&each.path).unwrap()))
-        {
-            if entry.1 != each.hash {
-                modified_entries.push(each.clone());
-            }
-        } else {
-            deleted_entries.push(PathBuf::from(str::from_utf8(&each.path).unwrap()));
+    for (path, hash) in &new_files {
+        match old_files.get(path) {
+            None => new_entries.push((PathBuf::from(path), *hash)),
+            Some(old_hash) if old_hash != hash => modified_entries.push(IndexEntry {
+                mtime: 0,
+                file_size: 0,
+                mode: RWO,
+                hash: *hash,
+                flag: 0,
+                path: path.as_bytes().to_vec(),
+            }),
+            Some(_) => {}
         }
     }
-    for each in &temp_index {
-        if !current_index
-                    .entries
-                    .iter().any(|x| PathBuf::from(str::from_utf8(&x.path).unwrap()) == each.0) {
-            new_entries.push(each.clone());
+    for path in old_files.keys() {
+        if !new_files.contains_key(path) {
+            deleted_entries.push(PathBuf::from(path));
         }
     }
     TempIndex {
@@ -179,67 +195,92 @@ pub fn build_temp_index(current_index: IndexObject) -> TempIndex {
     }
 }
 
-/// Rebuild index from temporary index contents
-/// Used when switching refs
-pub fn rebuild_index(index: Vec<(PathBuf, [u8; 20])>) {
-    let mut entry_vec: Vec<IndexEntry> = Vec::new();
-    // Create index entry for each in temporary index
-    for each in index {
-        let metadata = fs::metadata(&each.0).expect("Failed to get file metadata");
-        let mtime: u32 = metadata
-            .mtime()
-            .try_into()
-            .expect("Failed to get file mtime");
-        let file_size: u32 = metadata
-            .len()
-            .try_into()
-            .expect("Failed to get the len of file");
-        let mode: u32 = RWO;
-        let path: Vec<u8> = each
-            .0
-            .into_os_string()
-            .to_str()
-            .expect("Failed to cast pathbuf as os string")
-            .as_bytes()
-            .to_owned();
-        let entry: IndexEntry = IndexEntry {
-            mtime,
-            file_size,
-            mode,
-            hash: each.1,
-            flag: 0,
-            path,
-        };
-        entry_vec.push(entry);
+/// Map every file of a commit to its blob hash
+pub fn commit_files(commit_hash: &str) -> BTreeMap<String, [u8; 20]> {
+    if commit_hash.is_empty() {
+        return BTreeMap::new();
+    }
+    let commit = commit::parse_commit_by_hash(commit_hash);
+    match tree_files(&hex::encode(commit.tree), ".lrngit") {
+        Ok(files) => files.into_iter().map(|(path, x)| (path, x.hash)).collect(),
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to read tree of {commit_hash}: {e}"));
+            exit(1)
+        }
+    }
+}
+
+/// Update the index after a checkout, files written by the checkout get a new entry from their
+/// state on disk, deleted files are removed and every other entry is kept as is
+pub fn apply_temp_index(temp_index: &TempIndex) {
+    let mut written: Vec<(PathBuf, [u8; 20])> = temp_index.new_files.clone();
+    written.extend(
+        temp_index
+            .changed_files
+            .iter()
+            .map(|x| (PathBuf::from(String::from_utf8_lossy(&x.path).to_string()), x.hash)),
+    );
+    let replaced: Vec<&Path> = written
+        .iter()
+        .map(|x| x.0.as_path())
+        .chain(temp_index.to_delete_files.iter().map(|x| x.as_path()))
+        .collect();
+    let index = parse_index();
+    let mut cached_trees = index.cached_trees;
+    for path in &replaced {
+        invalidate_cached_trees(&mut cached_trees, path.as_os_str().as_encoded_bytes());
+    }
+    let mut entries: Vec<IndexEntry> = index
+        .entries
+        .into_iter()
+        .filter(|x| !replaced.contains(&Path::new(str::from_utf8(&x.path).unwrap())))
+        .collect();
+    // Create index entry for each file written on disk
+    for (path, hash) in written {
+        entries.push(index_entry_from_disk(&path, hash));
+    }
+    entries.sort();
+    update_index(IndexObject {
+        header: IndexHeader::new(entries.len()),
+        entries,
+        cached_trees,
+    });
+}
+
+/// Replace the index with the files of the given commit, entries are created from the files on
+/// disk which must match the commit
+pub fn reset_index(commit_hash: &str) {
+    let mut entries: Vec<IndexEntry> = commit_files(commit_hash)
+        .into_iter()
+        .map(|(path, hash)| index_entry_from_disk(Path::new(&path), hash))
+        .collect();
+    entries.sort();
+    update_index(IndexObject {
+        header: IndexHeader::new(entries.len()),
+        entries,
+        cached_trees: vec![],
+    });
+}
+
+/// Create the index entry of a file from its metadata on disk
+pub fn index_entry_from_disk(path: &Path, hash: [u8; 20]) -> IndexEntry {
+    let metadata = fs::metadata(path).expect("Failed to get file metadata");
+    let mtime: u32 = metadata
+        .mtime()
+        .try_into()
+        .expect("Failed to get file mtime");
+    let file_size: u32 = metadata
+        .len()
+        .try_into()
+        .expect("Failed to get the len of file");
+    IndexEntry {
+        mtime,
+        file_size,
+        mode: RWO,
+        hash,
+        flag: 0,
+        path: path.to_str().expect("Failed to cast path as str").as_bytes().to_owned(),
     }
-    // header
-    let magic_number = b"DIRC";
-    let header: IndexHeader = IndexHeader {
-        magic_number: *magic_number,
-        version: 1,
-        entry_count: entry_vec.len() as u8,
-    };
-    let mut header_bytes: Vec<u8> = Vec::new();
-    header_bytes.extend_from_slice(&header.magic_number);
-    header_bytes.push(header.version);
-    header_bytes.push(header.entry_count);
-    // index file
-    let index: IndexObject = IndexObject {
-        header,
-        entries: entry_vec,
-    };
-    let index_bytes: Vec<u8> =
-        bincode::serialize(&index).expect("Failed to serialize index struct into bytes");
-    // Write in index file
-    let mut file = OpenOptions::new()
-        .read(false)
-        .write(true)
-        .create(false)
-        .append(false)
-        .open(".lrngit/index")
-        .expect("Failed to open index file");
-    file.write_all(&index_bytes)
-        .expect("Failed to write in index file");
 }
 
 /// Display the content of the index file
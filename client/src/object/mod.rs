@@ -2,4 +2,6 @@ pub mod index;
 pub mod tree;
 pub mod blob;
 pub mod commit;
+pub mod signature;
+pub mod tag;
 pub mod utils;
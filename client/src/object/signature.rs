@@ -0,0 +1,95 @@
+use std::{error::Error, fs, path::Path, process::exit};
+
+use lrngitcore::objects::commit::{CommitContent, commit_payload};
+use ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+
+use crate::config;
+
+/// Namespace of the SSH signatures, the same as git so `ssh-keygen -Y verify` can check them
+const NAMESPACE: &str = "git";
+
+/// Result of the verification of a commit signature
+pub enum Verification {
+    /// The signature is valid and made by a key of the allowed signers file
+    Good { principal: String, key: String },
+    /// The signature is valid but the key isn't in the allowed signers file
+    Untrusted { key: String },
+    /// The signature doesn't match the commit
+    Bad { key: String },
+    Unsigned,
+}
+
+/// Sign a commit payload with the private key file `key_path`, return the armored signature.
+/// Exit if the key can't be used.
+pub fn sign_payload(key_path: &Path, payload: &[u8]) -> Vec<u8> {
+    let signature = read_private_key(key_path).and_then(|key| {
+        let signature = key.sign(NAMESPACE, HashAlg::Sha512, payload)?;
+        Ok(signature.to_pem(LineEnding::LF)?)
+    });
+    match signature {
+        Ok(s) => s.into_bytes(),
+        Err(e) => {
+            let key_path = key_path.display();
+            lrncore::logs::error_log(&format!("Failed to sign the commit with {key_path}: {e}"));
+            exit(1);
+        }
+    }
+}
+
+fn read_private_key(key_path: &Path) -> Result<PrivateKey, Box<dyn Error>> {
+    let key = PrivateKey::read_openssh_file(key_path)?;
+    if key.is_encrypted() {
+        return Err("keys protected by a passphrase are not supported".into());
+    }
+    Ok(key)
+}
+
+/// Check the signature of a commit against the allowed signers file, set with `allowedsigners`
+/// in the `[user]` section of the config
+pub fn verify_signature(commit: &CommitContent) -> Result<Verification, Box<dyn Error>> {
+    if commit.signature.is_empty() {
+        return Ok(Verification::Unsigned);
+    }
+    let signature = SshSig::from_pem(&commit.signature)?;
+    let public_key = PublicKey::from(signature.public_key().clone());
+    let key = format!(
+        "{} key {}",
+        public_key.algorithm(),
+        public_key.fingerprint(HashAlg::Sha256)
+    );
+    if public_key
+        .verify(NAMESPACE, &commit_payload(commit)?, &signature)
+        .is_err()
+    {
+        return Ok(Verification::Bad { key });
+    }
+    let Some(allowed_signers) = config::user_path("allowedsigners") else {
+        return Err("no allowed signers file, set allowedsigners in the [user] section of the config".into());
+    };
+    Ok(match find_principal(&allowed_signers, &public_key)? {
+        Some(principal) => Verification::Good { principal, key },
+        None => Verification::Untrusted { key },
+    })
+}
+
+/// Find the principals of a key in an allowed signers file. Each line is
+/// `<principals> [<options>] <key type> <base64 key> [<comment>]`.
+fn find_principal(path: &Path, public_key: &PublicKey) -> Result<Option<String>, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read allowed signers file {}: {e}", path.display()))?;
+    for line in content.lines().map(|x| x.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        // The key is the first pair of tokens after the principals that parses as a key
+        let key = tokens
+            .windows(2)
+            .skip(1)
+            .find_map(|x| PublicKey::from_openssh(&format!("{} {}", x[0], x[1])).ok());
+        if key.is_some_and(|x| x.key_data() == public_key.key_data()) {
+            return Ok(Some(tokens[0].to_owned()));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,20 @@
+use lrngitcore::objects::tag::TagContent;
+
+use crate::object::{commit::committer_identity, utils::write_object};
+
+/// Write an annotated tag object pointing to the given object and return its hash, the tagger is
+/// the user from the git config
+pub fn write_tag_object(target: &str, target_type: &str, name: &str, message: &str) -> String {
+    let mut object = [0u8; 20];
+    hex::decode_to_slice(target, &mut object).expect("Tag target is not a valid hash");
+    let tag_content = TagContent {
+        object,
+        object_type: target_type.as_bytes().to_vec(),
+        tag: name.as_bytes().to_vec(),
+        tagger: bincode::serialize(&committer_identity())
+            .expect("Failed to serialize CommitUser struct"),
+        message: message.as_bytes().to_vec(),
+    };
+    let tag_bytes = bincode::serialize(&tag_content).expect("Failed to serialize tag content");
+    write_object("tag", &tag_bytes)
+}
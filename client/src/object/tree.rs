@@ -1,6 +1,6 @@
-use std::{fs::{self, File}, io::Write, os::unix::fs::PermissionsExt};
+use std::{collections::HashMap, fs::{self, File}, io::Write, os::unix::fs::PermissionsExt};
 
-use crate::{parser, types::{BatchIndexEntriesMap, BatchIndexEntriesTuple, BatchIndexEntriesVec}};
+use crate::{types::{BatchIndexEntriesMap, BatchIndexEntriesTuple, BatchIndexEntriesVec}};
 use lrngitcore::{fs::new_file_dir, objects::tree::{Tree, TreeEntry, DIR, EXE, RWO, SYM}};
 use crate::object::utils::{git_object_header, compress_file};
 
@@ -72,6 +72,7 @@ fn add_tree(entries: Vec<(String, u32, [u8; 20])>) -> [u8; 20] {
         };
         new_tree_entry_vec.push(new_tree_entry);
     }
+    sort_tree_entries(&mut new_tree_entry_vec);
 
     // creation of tree object
     let new_tree: Tree = Tree {
@@ -107,35 +108,49 @@ fn add_tree(entries: Vec<(String, u32, [u8; 20])>) -> [u8; 20] {
     new_hash
 }
 
+/// Sort tree entries like git, so the same content always gives the same tree. A directory
+/// compares as its name followed by `/`.
+fn sort_tree_entries(entries: &mut [TreeEntry]) {
+    entries.sort_by_cached_key(|x| {
+        let mut key = x.name.clone();
+        if x.mode == DIR {
+            key.push(b'/');
+        }
+        key
+    });
+}
+
 /// The `batch_tree_add` function batch create all needed tree from the hashmap
 ///
 /// Arguments:
 ///
 /// * `entity_hashmap`: entity_hashmap is a hashmap containing all entries from index file split
-///   and sort in separate tree's.
+///   and sort in separate tree's, keyed by the full path of their directory and its depth.
 /// * `hash`: The `hash` parameter represent the hash of the object contained in the new tree
 ///   object
+/// * `cached_trees`: tree hash of the directories left unchanged since they were last written,
+///   their tree is reused. On return it holds the tree of every directory.
 pub fn batch_tree_add(
     entity_hashmap: BatchIndexEntriesMap,
     root_tree_ptr: &mut [u8; 20],
+    cached_trees: &mut HashMap<String, [u8; 20]>,
 ) {
-    let mut entity_vec: BatchIndexEntriesVec = entity_hashmap
-        .iter()
-        .map(|(k, v)| (k.clone(), v.clone()))
-        .collect();
-    // Sorts the vector by the depth value of each tuple using default comparison
-    entity_vec.sort_by(|x, y| x.0.1.cmp(&y.0.1));
+    let mut entity_vec: BatchIndexEntriesVec = entity_hashmap.into_iter().collect();
+    // Sorts the vector by the depth value of each tuple, deepest first so subtrees are created
+    // before their parent
+    entity_vec.sort_by_key(|x| x.0.1);
     entity_vec.reverse();
-    let mut tree_hash_vec: Vec<(String, u32, [u8; 20])> = Vec::new();
+    let mut tree_hashes: HashMap<String, [u8; 20]> = HashMap::new();
     for each in entity_vec {
-        let (tree_name, hash) = sort_hashmap_entry_and_create_tree(each, tree_hash_vec.clone());
-        tree_hash_vec.push((tree_name, 1, hash));
-    }
-    let mut root_tree = (String::new(), 0, [0u8; 20]);
-    if let Some(i) = tree_hash_vec.iter().position(|x| x.0.is_empty()) {
-        root_tree = tree_hash_vec.remove(i);
+        let dir = each.0.0.clone();
+        let hash = match cached_trees.get(&dir) {
+            Some(hash) => *hash,
+            None => sort_hashmap_entry_and_create_tree(each, &tree_hashes),
+        };
+        tree_hashes.insert(dir, hash);
     }
-    *root_tree_ptr = root_tree.2;
+    *root_tree_ptr = tree_hashes.get("").copied().unwrap_or([0u8; 20]);
+    *cached_trees = tree_hashes;
 }
 
 /// Sort the hashmap depending on the buffer content and create the specific tree
@@ -143,36 +158,51 @@ pub fn batch_tree_add(
 /// Arguments:
 ///
 /// * `entry`: the hashmap with all entry inside
-/// * `tree_vec`: vector containing all tree that need to be created
+/// * `tree_hashes`: hash of the trees already created, by directory path
 fn sort_hashmap_entry_and_create_tree(
     entry: BatchIndexEntriesTuple,
-    tree_vec: Vec<(String, u32, [u8; 20])>,
-) -> (String, [u8; 20]) {
+    tree_hashes: &HashMap<String, [u8; 20]>,
+) -> [u8; 20] {
+    let dir = entry.0.0;
     let mut tree_entry_vec: Vec<(String, u32, [u8; 20])> = Vec::new();
     for each in entry.1 {
-        if each.2 != [0u8; 20] {
+        if each.1 != DIR {
             tree_entry_vec.push(each);
         } else {
-            let mut existing_tree_hash: [u8; 20] = [0u8; 20];
-            if let Some(i) = tree_vec.iter().position(|x| x.0 == each.0) {
-                existing_tree_hash = tree_vec.get(i).unwrap().2;
-            }
-            if !existing_tree_hash.is_empty() {
-                tree_entry_vec.push((each.0, each.1, existing_tree_hash));
+            let child = if dir.is_empty() {
+                each.0.clone()
+            } else {
+                format!("{dir}/{}", each.0)
+            };
+            if let Some(hash) = tree_hashes.get(&child) {
+                tree_entry_vec.push((each.0, each.1, *hash));
             }
         }
     }
-    let hash = add_tree(tree_entry_vec);
-    (entry.0.0, hash)
+    add_tree(tree_entry_vec)
 }
 
-/// Display the content of a tree file
-pub fn print_tree_content(buff: &[u8]) {
-    let parse_tree =
-        parser::parse_tree_entries_obj(buff.to_vec()).expect("Failed to parse tree object");
-    for each in parse_tree {
-        println!("{:?}", str::from_utf8(&each.name).unwrap());
-        println!("{:?}", hex::encode(each.hash));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_sorted_like_git() {
+        let entry = |name: &str, mode: u32| TreeEntry {
+            mode,
+            name: name.as_bytes().to_vec(),
+            hash: [0u8; 20],
+        };
+        let mut entries = vec![
+            entry("b", RWO),
+            entry("a", DIR),
+            entry("a.txt", RWO),
+            entry("a-b", EXE),
+            entry("a0", RWO),
+        ];
+        sort_tree_entries(&mut entries);
+        let names: Vec<&[u8]> = entries.iter().map(|x| x.name.as_slice()).collect();
+        // `a/` sorts after `a.txt` and before `a0`
+        assert_eq!(names, [&b"a-b"[..], b"a.txt", b"a", b"a0", b"b"]);
     }
 }
-
@@ -1,28 +1,15 @@
 use flate2::Compression;
 use flate2::write::ZlibEncoder;
-use lrncore::logs::error_log;
-use lrngitcore::objects::utils::split_hash;
+use lrngitcore::objects::utils::{self, split_hash};
 use sha1::{Digest, Sha1};
 use std::{
-    fs::{self, File},
+    fs::File,
     io::{Read, Write},
-    os::unix::fs::MetadataExt,
     path::PathBuf,
     process::exit,
 };
 
-use crate::{
-    parser,
-    refs::parse_current_branch,
-    status::{FileStatus, FileStatusEntry},
-};
-
-use super::{
-    blob::compute_file_hash_and_blob,
-    commit::parse_commit_by_hash,
-    index::{self, parse_index},
-    tree::print_tree_content,
-};
+use crate::parser;
 
 /**
 The function `git_object_header` generates a Git object header based on the filetype and content
@@ -48,6 +35,7 @@ pub fn git_object_header(file_type: &str, content_length: usize) -> Vec<u8> {
         "blob" => format!("blob {content_length}\0").as_bytes().to_vec(),
         "tree" => format!("tree {content_length}\0").as_bytes().to_vec(),
         "commit" => format!("commit {content_length}\0").as_bytes().to_vec(),
+        "tag" => format!("tag {content_length}\0").as_bytes().to_vec(),
         _ => vec![],
     }
 }
@@ -95,31 +83,17 @@ pub fn compress_file(vec: Vec<u8>) -> Vec<u8> {
     compressed_bytes_vec
 }
 
-/// The function `read_blob_file` reads a compressed file, decompresses it, and prints its contents as a
-/// string.
-pub fn read_blob_file(hash: &str) {
-    let hash_char: Vec<char> = hash.chars().collect();
-    let folder: String = format!("{}{}", hash_char[0], hash_char[1]);
-    let object: String = hash_char[2..].iter().collect();
-    let object_path = format!(".lrngit/objects/{}/{}", &folder, &object);
-    let mut read_file = fs::File::open(object_path).expect("Failed to open file");
-    let mut buf = Vec::new();
-    read_file
-        .read_to_end(&mut buf)
-        .expect("Failed to read file");
-    let mut d = flate2::read::ZlibDecoder::new(buf.as_slice());
-    let mut buffer = Vec::new();
-    d.read_to_end(&mut buffer).unwrap();
-    // let header = split_object_header(buffer);
-    // let header_string = String::from_utf8_lossy(&header[0]);
-    // let header_split: Vec<&str> = header_string.split(" ").collect();
-    // let magic = header_split[0];
-    print_tree_content(&buf);
-    // match magic {
-    // "tree" => print_tree_content(&buf),
-    // _ => (),
-    // }
-    //TO-Do parse buffer to tree or blob struct to display
+/// Write an object of the given type in the local repository and return its hash. Like trees
+/// and commits, the hash is computed on the compressed object. Exit if the object can't be
+/// written.
+pub fn write_object(object_type: &str, content: &[u8]) -> String {
+    match utils::write_object(object_type, content, ".lrngit") {
+        Ok(hash) => hash,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Error writing {object_type} object: {e}"));
+            exit(1);
+        }
+    }
 }
 
 /// The function `hash_sha1` calculates the SHA-1 hash of a given vector of bytes and returns the hash
@@ -165,7 +139,11 @@ pub fn target_walk_root_tree(root_tree: &str, target_path: &str, hash: &mut [u8;
         .position(|x| str::from_utf8(&x.name).unwrap() == split_target_path[i])
     {
         let entry = parse_root_tree.remove(pos);
-        if str::from_utf8(&entry.name).unwrap() != *split_target_path.last().unwrap() {
+        if split_target_path.len() > 1 {
+            // Only trees can be walked through
+            if entry.mode != 16384 {
+                return;
+            }
             split_target_path.remove(i);
             let path_joinded = split_target_path.join("/");
             target_walk_root_tree(&hex::encode(entry.hash), &path_joinded, hash);
@@ -195,16 +173,14 @@ pub fn walk_root_tree_content(
         .expect("Failed to read root tree content to buffer");
     let parse_root_tree =
         parser::parse_tree_entries_obj(file_buff).expect("Failed to parse root tree entries");
-    let mut new_path = current_path.clone();
     for each in parse_root_tree {
-        new_path.push(str::from_utf8(&each.name).unwrap());
+        current_path.push(str::from_utf8(&each.name).unwrap());
         if each.mode == 16384 {
-            walk_root_tree_content(&hex::encode(each.hash), &mut new_path, content);
+            walk_root_tree_content(&hex::encode(each.hash), current_path, content);
         } else {
-            current_path.pop();
-            content.push((new_path.clone(), each.hash));
-            new_path.pop();
+            content.push((current_path.clone(), each.hash));
         }
+        current_path.pop();
     }
 }
 
@@ -235,74 +211,3 @@ pub fn walk_root_tree_all_objects(
         }
     }
 }
-
-/// Check if a file is staged or just modified by comparing hash from last commit with the one from
-/// index
-fn check_file_staged(file_path: &str) -> FileStatusEntry {
-    let last_commit = parse_current_branch();
-    let parse_commit = parse_commit_by_hash(&last_commit);
-    let mut file_hash: [u8; 20] = [0u8; 20];
-    // Get the hash of the file from last commit to check if there's change on disk
-    // Don't use the root tree content, passing a simple empty vector without keeping ref to it
-    target_walk_root_tree(&hex::encode(parse_commit.tree), file_path, &mut file_hash);
-    let mut index = parse_index();
-    if let Some(pos) = index
-        .entries
-        .iter()
-        .position(|x| String::from_utf8_lossy(&x.path) == file_path)
-    {
-        let entry = index.entries.remove(pos);
-        let disk_hash =
-            compute_file_hash_and_blob(file_path).expect("Failed to get hash from file path");
-        // if entry.hash != file_hash && entry.hash == disk_hash
-        if entry.hash != file_hash && entry.hash == disk_hash.hash {
-            FileStatusEntry {
-                file: file_path.to_owned(),
-                status: FileStatus::Staged,
-            }
-        } else {
-            FileStatusEntry {
-                file: file_path.to_owned(),
-                status: FileStatus::Tracked,
-            }
-        }
-    } else {
-        error_log("Error checking file status");
-        exit(1)
-    }
-}
-
-/// Check in the index file if a file has been modified since it has been added to the index
-///
-/// Params:
-/// files_path: path to the file we want to check
-///
-/// Return a FileStatusEntry structure containing the file path and the status.
-pub fn check_modified_file(files_path: &str) -> FileStatusEntry {
-    let index = index::parse_index();
-    let mut index_entries = index.entries;
-    let mut file_status: FileStatusEntry = FileStatusEntry {
-        file: "".to_owned(),
-        status: FileStatus::Untracked,
-    };
-
-    let file_metadata = fs::metadata(files_path).expect("Failed to get file metadata");
-
-    if let Some(pos) = index_entries
-        .iter()
-        .position(|x| str::from_utf8(&x.path).unwrap() == files_path)
-    {
-        let entry = index_entries.remove(pos);
-        if file_metadata.mtime() as u32 != entry.mtime
-            || file_metadata.len() as u32 != entry.file_size
-        {
-            file_status = FileStatusEntry {
-                file: files_path.to_owned(),
-                status: FileStatus::Modify,
-            }
-        } else {
-            file_status = check_file_staged(files_path);
-        }
-    }
-    file_status
-}
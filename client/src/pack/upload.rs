@@ -1,36 +1,102 @@
 use std::{
+    collections::HashSet,
     fs::File,
     io::Read,
     path::PathBuf,
+    process::exit,
 };
 
 use lrngitcore::{
-    objects::utils::{get_all_object, get_file_by_hash, parse_hash_objects, parse_object_header},
+    objects::{
+        commit::read_commit,
+        tag::read_tag,
+        utils::{get_file_by_hash, parse_object_header},
+    },
     pack::upload::{ObjectsPackData, UploadPack},
+    revwalk::RevWalk,
 };
 
-use crate::{
-    object::{
-        commit,
-        utils::walk_root_tree_all_objects,
-    },
-    refs::parse_current_branch,
+use crate::object::{
+    commit,
+    utils::walk_root_tree_all_objects,
 };
 
-pub fn create_upload_pack() -> Vec<u8> {
-    // Get all objects in local repository
-    let last_commit = parse_current_branch();
-    let parse_commit = commit::parse_commit_by_hash(&last_commit);
-    let root_tree = hex::encode(parse_commit.tree);
-    let mut all_root_tree_objects: Vec<(&str, [u8; 20])> = Vec::new();
-    // Contain all objects ready to be send to in upload pack
-    walk_root_tree_all_objects(&root_tree, &mut PathBuf::new(), &mut all_root_tree_objects);
-    let mut object_vec: Vec<PathBuf> = Vec::new();
-    get_all_object(".lrngit/objects/", &mut object_vec);
-    let hash = parse_hash_objects(object_vec);
-    // Sort all objects from root tree and remove duplication
-    all_root_tree_objects.sort();
-    all_root_tree_objects.dedup();
+/// Create the upload pack sent on push. Only objects of commits the remote doesn't have are
+/// packed, the history is walked from the pushed commit and stops at the last remote commit.
+/// Pushed tags add their tag object and the history of the object they point to.
+pub fn create_upload_pack(
+    last_commit: &str,
+    last_remote_commit: &str,
+    tags: &[(String, String)],
+) -> Vec<u8> {
+    let mut walk = RevWalk::new(".lrngit");
+    walk.push(last_commit);
+    // Objects reached from tags without going through a commit
+    let mut tag_objects: HashSet<String> = HashSet::new();
+    for (name, hash) in tags {
+        // Every tag object of the chain is sent, down to the tagged object
+        let mut target = hash.clone();
+        loop {
+            let (target_type, _) = parse_object_header(&target);
+            if target_type != "tag" {
+                break;
+            }
+            tag_objects.insert(target.clone());
+            target = match read_tag(&target, ".lrngit") {
+                Ok(tag) => hex::encode(tag.object),
+                Err(e) => {
+                    lrncore::logs::error_log(&format!("Failed to read tag {name}: {e}"));
+                    exit(1);
+                }
+            };
+        }
+        let (target_type, _) = parse_object_header(&target);
+        match target_type.as_str() {
+            "commit" => walk.push(&target),
+            "tree" => {
+                tag_objects.insert(target.clone());
+                tree_objects(&target, &mut tag_objects);
+            }
+            _ => {
+                tag_objects.insert(target);
+            }
+        }
+    }
+    // Objects already on the remote, from the tree of its last commit
+    let mut remote_objects: HashSet<String> = HashSet::new();
+    // The remote commit may be unknown locally, then everything is sent
+    if let Ok(parse_commit) = read_commit(last_remote_commit, ".lrngit") {
+        walk.hide(last_remote_commit);
+        remote_objects.insert(hex::encode(parse_commit.tree));
+        tree_objects(&hex::encode(parse_commit.tree), &mut remote_objects);
+    }
+    let mut hash: Vec<String> = tag_objects
+        .iter()
+        .filter(|x| !remote_objects.contains(*x))
+        .cloned()
+        .collect();
+    let mut packed: HashSet<String> = tag_objects;
+    for commit_hash in walk {
+        let commit_hash = match commit_hash {
+            Ok(h) => h,
+            Err(e) => {
+                lrncore::logs::error_log(&format!("Failed to walk history: {e}"));
+                exit(1);
+            }
+        };
+        let parse_commit = commit::parse_commit_by_hash(&commit_hash);
+        let root_tree = hex::encode(parse_commit.tree);
+        let mut objects: HashSet<String> = HashSet::new();
+        objects.insert(commit_hash);
+        objects.insert(root_tree.clone());
+        tree_objects(&root_tree, &mut objects);
+        for each in objects {
+            if !remote_objects.contains(&each) && packed.insert(each.clone()) {
+                hash.push(each);
+            }
+        }
+    }
+    hash.sort();
     let mut object_vec: Vec<ObjectsPackData> = Vec::new();
     for each in hash {
         let mut file: File = get_file_by_hash(&each, ".lrngit");
@@ -42,11 +108,9 @@ pub fn create_upload_pack() -> Vec<u8> {
             header: b"OBJECT".as_slice().to_vec(),
             object_type: object_type.as_bytes().to_vec(),
             hash: {
+                // Raw SHA-1, the remote also reads the hex prefix older clients sent
                 let mut buffer = [0u8; 20];
-                let bytes = each.as_bytes();
-                // Copy max 20 bytes from string buffer
-                let len = bytes.len().min(20);
-                buffer[..len].copy_from_slice(&bytes[..len]);
+                hex::decode_to_slice(&each, &mut buffer).expect("Invalid object hash");
                 buffer
             },
             data: file_buff,
@@ -59,3 +123,10 @@ pub fn create_upload_pack() -> Vec<u8> {
     let upload_pack: Vec<u8> = bincode::serialize(&pack).expect("Failed to serialize upload pack");
     upload_pack
 }
+
+/// Fill the set with the hash of every tree and blob inside the given tree
+fn tree_objects(root_tree: &str, objects: &mut HashSet<String>) {
+    let mut all_root_tree_objects: Vec<(&str, [u8; 20])> = Vec::new();
+    walk_root_tree_all_objects(root_tree, &mut PathBuf::new(), &mut all_root_tree_objects);
+    objects.extend(all_root_tree_objects.iter().map(|x| hex::encode(x.1)));
+}
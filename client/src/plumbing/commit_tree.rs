@@ -0,0 +1,74 @@
+use std::{
+    env,
+    io::{self, Read},
+    process::exit,
+};
+
+use lrngitcore::revparse;
+
+use crate::{object::commit::write_commit_object, refs::resolve_commit_revision};
+
+/// `lrngit commit-tree <tree> [-p <parent>] [-m <message>]`, create a commit object and print its
+/// hash without updating any ref. The message is read from stdin when `-m` isn't given.
+pub fn commit_tree_command() {
+    let args: Vec<String> = env::args().collect();
+    let mut parent: Option<String> = None;
+    let mut messages: Vec<&str> = Vec::new();
+    let mut tree: Option<&str> = None;
+    let mut i = 2;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-p" | "-m" if i + 1 >= args.len() => {
+                lrncore::logs::error_log(&format!("{} expects a value", args[i]));
+                exit(1);
+            }
+            "-p" => {
+                if parent.is_some() {
+                    lrncore::logs::error_log("Only one parent is supported");
+                    exit(1);
+                }
+                i += 1;
+                parent = Some(resolve_commit_revision(&args[i]));
+            }
+            "-m" => {
+                i += 1;
+                messages.push(&args[i]);
+            }
+            arg if arg.starts_with('-') || tree.is_some() => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+            arg => tree = Some(arg),
+        }
+        i += 1;
+    }
+    let Some(tree) = tree else {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit commit-tree <tree> [-p <parent>] [-m <message>]",
+        );
+        return;
+    };
+    let tree = match revparse::resolve_tree(".lrngit", tree) {
+        Ok(t) => t,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    };
+    let message = if messages.is_empty() {
+        let mut message = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut message) {
+            lrncore::logs::error_log(&format!("Failed to read stdin: {e}"));
+            exit(1);
+        }
+        message
+    } else {
+        messages.join("\n\n")
+    };
+    let mut root_tree = [0u8; 20];
+    hex::decode_to_slice(&tree, &mut root_tree).expect("Resolved tree is not a valid hash");
+    let hash = write_commit_object(root_tree, &parent.unwrap_or_default(), &message);
+    println!("{hash}");
+    exit(0);
+}
@@ -0,0 +1,57 @@
+use std::{
+    env,
+    io::{self, Read},
+    process::exit,
+};
+
+use lrngitcore::objects::blob::FileHashBlob;
+
+use crate::object::blob::{compute_file_hash_and_blob, hash_blob_content, write_blob};
+
+/// `lrngit hash-object [-w] [--stdin] [<file>...]`, print the blob hash of each file and write
+/// the objects with `-w`
+pub fn hash_object_command() {
+    let args: Vec<String> = env::args().collect();
+    let mut write = false;
+    let mut stdin = false;
+    let mut files: Vec<&str> = Vec::new();
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-w" => write = true,
+            "--stdin" => stdin = true,
+            _ if arg.starts_with('-') => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+            _ => files.push(arg),
+        }
+    }
+    if !stdin && files.is_empty() {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit hash-object [-w] [--stdin] [<file>...]",
+        );
+    }
+    let mut blobs: Vec<FileHashBlob> = Vec::new();
+    if stdin {
+        let mut content: Vec<u8> = Vec::new();
+        if let Err(e) = io::stdin().read_to_end(&mut content) {
+            lrncore::logs::error_log(&format!("Failed to read stdin: {e}"));
+            exit(1);
+        }
+        blobs.push(hash_blob_content(&content));
+    }
+    for file in files {
+        match compute_file_hash_and_blob(file) {
+            Ok(blob) => blobs.push(blob),
+            Err(_) => exit(1),
+        }
+    }
+    for blob in blobs {
+        if write {
+            write_blob(&blob);
+        }
+        println!("{}", hex::encode(blob.hash));
+    }
+    exit(0);
+}
@@ -0,0 +1,69 @@
+use std::{env, process::exit};
+
+use lrngitcore::{
+    objects::tree::{DIR, read_tree, tree_files},
+    revparse,
+};
+
+/// `lrngit ls-tree [-r] [--name-only] <tree-ish>`, print one line per entry formatted as
+/// `<mode> <type> <hash>\t<path>`. With `-r` subtrees are expanded and only blobs are listed.
+pub fn ls_tree_command() {
+    let args: Vec<String> = env::args().collect();
+    let mut recursive = false;
+    let mut name_only = false;
+    let mut tree_ish: Option<&str> = None;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "-r" => recursive = true,
+            "--name-only" => name_only = true,
+            _ if arg.starts_with('-') || tree_ish.is_some() => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+            _ => tree_ish = Some(arg),
+        }
+    }
+    let Some(tree_ish) = tree_ish else {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit ls-tree [-r] [--name-only] <tree-ish>",
+        );
+        return;
+    };
+    let tree = match revparse::resolve_tree(".lrngit", tree_ish) {
+        Ok(t) => t,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    };
+    let entries = if recursive {
+        tree_files(&tree, ".lrngit").map(|x| x.into_iter().collect::<Vec<_>>())
+    } else {
+        read_tree(&tree, ".lrngit").map(|x| {
+            x.into_iter()
+                .map(|e| (String::from_utf8_lossy(&e.name).to_string(), e))
+                .collect()
+        })
+    };
+    let entries = match entries {
+        Ok(e) => e,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to read tree {tree}: {e}"));
+            exit(1);
+        }
+    };
+    for (path, entry) in entries {
+        if name_only {
+            println!("{path}");
+        } else {
+            let kind = if entry.mode == DIR { "tree" } else { "blob" };
+            println!(
+                "{:06o} {kind} {}\t{path}",
+                entry.mode,
+                hex::encode(entry.hash)
+            );
+        }
+    }
+    exit(0);
+}
@@ -0,0 +1,9 @@
+/*
+Plumbing commands, low level building blocks with a stable output meant for scripts
+*/
+
+pub mod commit_tree;
+pub mod hash_object;
+pub mod ls_tree;
+pub mod update_ref;
+pub mod write_tree;
@@ -0,0 +1,126 @@
+use std::{env, process::exit};
+
+use lrngitcore::{
+    objects::utils::{object_type, read_object},
+    reflog::NULL_HASH,
+    refs::{delete_ref, head_ref, is_valid_ref_name, read_ref, write_ref},
+};
+
+use crate::refs::{log_ref_update, resolve_revision};
+
+/// `lrngit update-ref [-m <reason>] <ref> <new> [<old>]` and `lrngit update-ref -d <ref> [<old>]`.
+/// When `<old>` is given the ref is only updated if it still has this value, the null hash means
+/// the ref must not exist. HEAD updates the branch it points to.
+pub fn update_ref_command() {
+    let args: Vec<String> = env::args().collect();
+    let mut delete = false;
+    let mut reason = String::from("update-ref");
+    let mut positional: Vec<&str> = Vec::new();
+    let mut i = 2;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-d" => delete = true,
+            "-m" => {
+                i += 1;
+                match args.get(i) {
+                    Some(m) => reason = m.to_owned(),
+                    None => {
+                        lrncore::logs::error_log("-m expects a reason");
+                        exit(1);
+                    }
+                }
+            }
+            arg if arg.starts_with('-') => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+            arg => positional.push(arg),
+        }
+        i += 1;
+    }
+    let expected_len = if delete { 1..=2 } else { 2..=3 };
+    if !expected_len.contains(&positional.len()) {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit update-ref [-m <reason>] <ref> <new> [<old>]\n    lrngit update-ref -d <ref> [<old>]",
+        );
+    }
+    let refname = target_ref(positional[0]);
+    let current = read_ref(".lrngit", &refname).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(1);
+    });
+    let old_arg = if delete {
+        positional.get(1)
+    } else {
+        positional.get(2)
+    };
+    if let Some(old) = old_arg {
+        let expected = if *old == NULL_HASH || old.is_empty() {
+            None
+        } else {
+            Some(resolve_revision(old))
+        };
+        if expected != current {
+            lrncore::logs::error_log(&format!(
+                "cannot update {refname}: expected {}, found {}",
+                expected.as_deref().unwrap_or(NULL_HASH),
+                current.as_deref().unwrap_or(NULL_HASH)
+            ));
+            exit(1);
+        }
+    }
+    let current = current.unwrap_or_default();
+    if delete {
+        if let Err(e) = delete_ref(".lrngit", &refname) {
+            lrncore::logs::error_log(&format!("Failed to delete {refname}: {e}"));
+            exit(1);
+        }
+        exit(0);
+    }
+    let new = resolve_revision(positional[1]);
+    check_new_value(&refname, &new);
+    if let Err(e) = write_ref(".lrngit", &refname, &new) {
+        lrncore::logs::error_log(&format!("Failed to update {refname}: {e}"));
+        exit(1);
+    }
+    log_ref_update(&refname, &current, &new, &reason);
+    exit(0);
+}
+
+/// Exit if the new value of a ref isn't an existing object, a branch or a detached HEAD must
+/// point to a commit
+fn check_new_value(refname: &str, hash: &str) {
+    let kind = match read_object(hash, ".lrngit") {
+        Ok(object) => object_type(&object),
+        Err(e) => {
+            lrncore::logs::error_log(&format!("cannot update {refname}: {e}"));
+            exit(128);
+        }
+    };
+    if kind != "commit" && (refname == "HEAD" || refname.starts_with("refs/heads/")) {
+        lrncore::logs::error_log(&format!(
+            "cannot update {refname}: trying to write non-commit object {hash} to a branch"
+        ));
+        exit(128);
+    }
+}
+
+/// Check the ref name and follow HEAD to the branch it points to
+fn target_ref(name: &str) -> String {
+    if !is_valid_ref_name(name) {
+        lrncore::logs::error_log(&format!("invalid ref name '{name}'"));
+        exit(128);
+    }
+    if name != "HEAD" {
+        return name.to_owned();
+    }
+    match head_ref(".lrngit") {
+        Ok(Some(branch)) => branch,
+        Ok(None) => "HEAD".to_owned(),
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use std::process::exit;
+
+use crate::commit::write_index_tree;
+
+/// `lrngit write-tree`, create the trees of the current index and print the root tree hash
+pub fn write_tree_command() {
+    let root_tree = write_index_tree();
+    if root_tree == [0u8; 20] {
+        lrncore::logs::error_log("Nothing to write, the index is empty");
+        exit(1);
+    }
+    println!("{}", hex::encode(root_tree));
+    exit(0);
+}
@@ -1,36 +1,140 @@
-use std::{env, io::{Read, Write}, process::exit};
+use std::{
+    env,
+    io::{self, Read},
+    net::TcpStream,
+    process::exit,
+};
 
-use crate::tcp;
+use lrngitcore::{
+    objects::utils::read_object,
+    pack::refs::parse_advertised_refs,
+    refs::write_ref,
+    remote::{FetchUpdate, Remote},
+    revwalk::is_ancestor,
+};
+
+use crate::{
+    refs::log_ref_update,
+    remote::{default_remote_name, remote_or_exit},
+    tcp,
+};
 
 pub fn pull_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        pull_remote_branch();
-        exit(0);
-    }
-    if args.len() == 3 {
-        exit(0);
-    }
-    match args[2].as_str() {
-        "" => {}
+    match args.get(2..).unwrap_or_default() {
+        [] => pull_remote_branch(&default_remote_name()),
+        [remote] if !remote.starts_with('-') => pull_remote_branch(remote),
         _ => {
-            lrncore::logs::warning_log("Unknown command");
+            lrncore::usage_exit::usage_and_exit("Invalid command", "lrngit pull [<remote>]");
+            exit(1);
+        }
+    }
+    exit(0);
+}
+
+/// Pull change from the given remote repository. The refs advertised by the remote update the
+/// tracking refs its fetch refspecs map them to.
+fn pull_remote_branch(name: &str) {
+    let remote = remote_or_exit(name);
+    let stream = tcp::tcp_connect_to_remote("lrngit-upload-pack", &remote);
+    let advertised = read_advertised_refs(stream);
+    let updates = remote
+        .fetch_updates(".lrngit", &advertised)
+        .unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("{e}"));
             exit(1);
+        });
+    if !update_tracking_refs(&remote, updates) {
+        exit(1);
+    }
+}
+
+/// Update the tracking refs of the remote. Updates that aren't fast-forwards are rejected unless
+/// the refspec forces them. Objects aren't transferred on fetch yet, so a ref whose commit is
+/// missing locally is left as is. Return false if an update was rejected or failed.
+fn update_tracking_refs(remote: &Remote, updates: Vec<FetchUpdate>) -> bool {
+    if updates.is_empty() {
+        return true;
+    }
+    println!("From {}", remote.url);
+    let mut success = true;
+    for update in updates {
+        let src = short_ref_name(&update.remote_ref);
+        let dst = short_ref_name(&update.tracking_ref);
+        if read_object(&update.new, ".lrngit").is_err() {
+            println!(" ! [missing objects] {src} -> {dst}");
+            success = false;
+            continue;
+        }
+        let fast_forward = update.old.is_empty()
+            || is_ancestor(".lrngit", &update.old, &update.new).unwrap_or(false);
+        if !update.force && !fast_forward {
+            println!(" ! [rejected]        {src} -> {dst} (non-fast-forward)");
+            success = false;
+            continue;
         }
+        if let Err(e) = write_ref(".lrngit", &update.tracking_ref, &update.new) {
+            lrncore::logs::error_log(&format!("Failed to update {}: {e}", update.tracking_ref));
+            success = false;
+            continue;
+        }
+        let (summary, reason) = match update.old.get(..7) {
+            None => (String::from(" * [new ref]"), "storing head"),
+            Some(old) if !fast_forward => (
+                format!(" + {old}...{}", &update.new[..7]),
+                "forced-update",
+            ),
+            Some(old) => (format!("   {old}..{}", &update.new[..7]), "fast-forward"),
+        };
+        println!("{summary:20} {src} -> {dst}");
+        log_ref_update(
+            &update.tracking_ref,
+            &update.old,
+            &update.new,
+            &format!("pull: {reason}"),
+        );
     }
+    success
+}
+
+/// Short name of a ref, `refs/remotes/origin/main` is `origin/main`
+fn short_ref_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|x| name.strip_prefix(x))
+        .unwrap_or(name)
 }
 
-/// Pull change from remote repository on current origin
-fn pull_remote_branch() {
-    let mut stream = tcp::tcp_connect_to_remote("lrngit-upload-pack");
-    stream.flush().expect("Failed to flush stream");
-    let mut buffer = [0u8; 1024];
+/// Read the refs advertised by the remote as `(name, hash)` pairs, exit if the remote reports
+/// an error
+fn read_advertised_refs(mut stream: TcpStream) -> Vec<(String, String)> {
+    let mut refs: Vec<(String, String)> = Vec::new();
+    let mut buffer: Vec<u8> = Vec::new();
     loop {
-        let n = stream.read(&mut buffer).unwrap();
-        if n == 0 {
-            println!("Connection closed.");
+        let mut stream_length = [0u8; 4];
+        // Read buffer length
+        if let Err(e) = stream.read_exact(&mut stream_length) {
+            if e.kind() != io::ErrorKind::UnexpectedEof {
+                lrncore::logs::error_log(&format!("Failed to read stream length: {e}"));
+                exit(1);
+            }
             break;
         }
-        println!("Received: {}", String::from_utf8_lossy(&buffer[..n]));
+        let length = u32::from_le_bytes(stream_length) as usize;
+        buffer.resize(length, 0);
+        if let Err(e) = stream.read_exact(&mut buffer) {
+            lrncore::logs::error_log(&format!("Failed to read framed stream: {e}"));
+            exit(1);
+        }
+        if let Some(content) = buffer.strip_prefix(b"LIST ") {
+            refs.extend(parse_advertised_refs(content));
+        } else if let Some(message) = buffer.strip_prefix(b"ERR") {
+            lrncore::logs::error_log(&format!(
+                "Remote error:{}",
+                String::from_utf8_lossy(message)
+            ));
+            exit(1);
+        }
     }
+    refs
 }
@@ -5,56 +5,328 @@ use std::{
     process::exit,
 };
 
-use lrngitcore::remote::origin::parse_origin_branch;
+use lrngitcore::{
+    pack::refs::build_tags_pack,
+    reflog::NULL_HASH,
+    refs::{list_refs, read_ref, write_ref},
+    remote::{
+        Remote,
+        refspec::{Refspec, parse_refspecs},
+    },
+    revwalk::is_ancestor,
+};
 
 use crate::{
+    branch::set_upstream,
+    hooks::run_hook_or_exit,
     pack::upload::create_upload_pack,
-    refs::{parse_current_branch, parse_head},
+    refs::{log_ref_update, parse_head, resolve_commit_revision},
+    remote::{default_remote_name, remote_or_exit},
     tcp,
 };
 
+const PUSH_USAGE: &str =
+    "lrngit push [-u | --set-upstream] [--tags] [--no-verify] [<remote> [<refspec>...]]";
+
+/// A ref update sent to the remote
+struct PushUpdate {
+    /// Local ref pushed, or the revision given as source
+    src: String,
+    /// Branch updated on the remote
+    dst: String,
+    local_commit: String,
+    /// Last commit of the destination known from its tracking ref, empty if unknown
+    remote_commit: String,
+    /// Local ref following the destination, from the fetch refspecs of the remote
+    tracking_ref: Option<String>,
+    force: bool,
+}
+
 pub fn push_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        push_remote_branch();
-        exit(0);
+    let mut with_tags = false;
+    let mut no_verify = false;
+    let mut set_upstream = false;
+    let mut positional: Vec<&str> = Vec::new();
+    for arg in args.iter().skip(2) {
+        match arg.as_str() {
+            "--tags" => with_tags = true,
+            "--no-verify" => no_verify = true,
+            "-u" | "--set-upstream" => set_upstream = true,
+            arg if !arg.starts_with('-') || arg.starts_with('^') => positional.push(arg),
+            _ => {
+                lrncore::usage_exit::usage_and_exit("Invalid command", PUSH_USAGE);
+                exit(1);
+            }
+        }
+    }
+    let remote = match positional.first() {
+        Some(name) => remote_or_exit(name),
+        None => remote_or_exit(&default_remote_name()),
+    };
+    let refspecs = positional.get(1..).unwrap_or_default();
+    let mut tags: Vec<(String, String)> = if with_tags {
+        list_refs(".lrngit", "refs/tags")
+            .unwrap_or_else(|e| {
+                lrncore::logs::error_log(&format!("Failed to list tags: {e}"));
+                exit(1);
+            })
+            .into_iter()
+            .map(|(name, hash)| (name.trim_start_matches("refs/tags/").to_owned(), hash))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let updates = push_updates(&remote, refspecs, &mut tags);
+    if !push_remote_branches(&remote, updates, &tags, no_verify, set_upstream) {
+        exit(1);
     }
-    if args.len() == 3 {
-        exit(0);
+    exit(0);
+}
+
+/// Resolve the refspecs given to push in the branch updates to send. Without refspec the current
+/// branch updates the branch of the same name. A refspec pushing a tag adds it to `tags`, the
+/// remote only accepts new tags.
+fn push_updates(
+    remote: &Remote,
+    refspecs: &[&str],
+    tags: &mut Vec<(String, String)>,
+) -> Vec<PushUpdate> {
+    let refspecs: Vec<Refspec> = if refspecs.is_empty() {
+        let head = parse_head();
+        if head == "HEAD" {
+            lrncore::logs::error_log(
+                "You are not currently on a branch, switch to a branch to push",
+            );
+            exit(1);
+        }
+        vec![Refspec {
+            force: false,
+            negative: false,
+            src: head,
+            dst: None,
+        }]
+    } else {
+        parse_refspecs(refspecs).unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(1);
+        })
+    };
+    let negatives: Vec<Refspec> = refspecs.iter().filter(|x| x.negative).cloned().collect();
+    let mut pushed: Vec<(String, String, String, bool)> = Vec::new();
+    for refspec in refspecs.iter().filter(|x| !x.negative) {
+        if refspec.src.is_empty() {
+            lrncore::logs::error_log(&format!("{refspec}: deleting remote refs is not supported"));
+            exit(1);
+        }
+        if refspec.is_glob() {
+            let refs = list_refs(".lrngit", "refs").unwrap_or_else(|e| {
+                lrncore::logs::error_log(&format!("Failed to list refs: {e}"));
+                exit(1);
+            });
+            for (name, hash) in refs {
+                if let Some(dst) = refspec.map(&name) {
+                    pushed.push((name, dst, hash, refspec.force));
+                }
+            }
+            continue;
+        }
+        let (src, hash) = resolve_push_source(&refspec.src);
+        let dst = match (&refspec.dst, src.starts_with("refs/")) {
+            (Some(dst), _) if dst.starts_with("refs/") => dst.clone(),
+            // A short destination is a branch or a tag like the source
+            (Some(dst), true) if src.starts_with("refs/tags/") => format!("refs/tags/{dst}"),
+            (Some(dst), _) => format!("refs/heads/{dst}"),
+            (None, true) => src.clone(),
+            (None, false) => {
+                lrncore::logs::error_log(&format!(
+                    "{refspec}: the destination is required to push a revision"
+                ));
+                exit(1);
+            }
+        };
+        pushed.push((src, dst, hash, refspec.force));
     }
-    match args[2].as_str() {
-        "" => {}
-        _ => {
-            lrncore::logs::warning_log("Unknown command");
+    let mut updates: Vec<PushUpdate> = Vec::new();
+    for (src, dst, local_commit, force) in pushed {
+        if negatives.iter().any(|x| x.matches(&src)) {
+            continue;
+        }
+        if let Some(name) = dst.strip_prefix("refs/tags/") {
+            if !tags.iter().any(|(x, _)| x == name) {
+                tags.push((name.to_owned(), local_commit));
+            }
+            continue;
+        }
+        if !dst.starts_with("refs/heads/") {
+            lrncore::logs::error_log(&format!(
+                "Cannot push to {dst}, only branches and tags can be pushed"
+            ));
             exit(1);
         }
+        let tracking_ref = remote.tracking_ref(&dst).unwrap_or_else(|e| {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(1);
+        });
+        let remote_commit = tracking_ref
+            .as_ref()
+            .and_then(|x| read_ref(".lrngit", x).ok().flatten())
+            .unwrap_or_default();
+        updates.push(PushUpdate {
+            src,
+            dst,
+            local_commit,
+            remote_commit,
+            tracking_ref,
+            force,
+        });
+    }
+    updates
+}
+
+/// Resolve the source of a refspec to a full ref and its commit. A short name is a branch or a
+/// tag, `HEAD` is the current branch and anything else is resolved as a revision.
+fn resolve_push_source(src: &str) -> (String, String) {
+    let head = parse_head();
+    let candidates = if src == "HEAD" && head != "HEAD" {
+        vec![head]
+    } else if src.starts_with("refs/") {
+        vec![src.to_owned()]
+    } else {
+        vec![format!("refs/heads/{src}"), format!("refs/tags/{src}")]
+    };
+    for name in candidates {
+        if let Ok(Some(hash)) = read_ref(".lrngit", &name) {
+            return (name, hash);
+        }
     }
+    (src.to_owned(), resolve_commit_revision(src))
 }
 
-/// Push the local change to the remote repository. Get the last commit and refs, enable connection
-/// between client and remote host and send object through an upload pack.
-fn push_remote_branch() {
-    let last_commit = parse_current_branch();
-    let last_remote_commit = parse_origin_branch();
-    let refs = &parse_head();
-    let mut stream = tcp::tcp_connect_to_remote("lrngit-receive-pack");
+/// Push the branch updates to the remote repository, one connection per branch. Updates that
+/// aren't fast-forwards are rejected unless forced. The tags are sent with the first branch. The
+/// pre-push hook runs before connecting unless `no_verify`. With `set_upstream` the pushed
+/// branches track their destination. Return false if an update was rejected or failed.
+fn push_remote_branches(
+    remote: &Remote,
+    updates: Vec<PushUpdate>,
+    tags: &[(String, String)],
+    no_verify: bool,
+    set_upstream: bool,
+) -> bool {
+    if updates.is_empty() {
+        if !tags.is_empty() {
+            lrncore::logs::error_log("Tags can only be pushed along a branch");
+            return false;
+        }
+        println!("Everything up-to-date");
+        return true;
+    }
+    if !no_verify {
+        run_pre_push_hook(remote, &updates, tags);
+    }
+    println!("To {}", remote.url);
+    let mut success = true;
+    let mut tags = tags;
+    for update in updates {
+        let src = short_ref_name(&update.src);
+        let dst = short_ref_name(&update.dst);
+        if update.local_commit == update.remote_commit && tags.is_empty() {
+            println!(" = [up to date]      {src} -> {dst}");
+            if set_upstream {
+                track_destination(remote, &update);
+            }
+            continue;
+        }
+        if !update.force && !is_fast_forward(&update) {
+            println!(" ! [rejected]        {src} -> {dst} (non-fast-forward)");
+            success = false;
+            continue;
+        }
+        if !send_update(remote, &update, tags) {
+            println!(" ! [remote rejected] {src} -> {dst}");
+            success = false;
+            continue;
+        }
+        tags = &[];
+        let summary = match update.remote_commit.get(..7) {
+            _ if update.local_commit == update.remote_commit => String::from(" = [up to date]"),
+            None => String::from(" * [new branch]     "),
+            Some(old) if update.force && !is_fast_forward(&update) => {
+                format!(" + {old}...{}", &update.local_commit[..7])
+            }
+            Some(old) => format!("   {old}..{}", &update.local_commit[..7]),
+        };
+        println!("{summary:20} {src} -> {dst}");
+        if set_upstream {
+            track_destination(remote, &update);
+        }
+        if let Some(tracking_ref) = &update.tracking_ref
+            && update.local_commit != update.remote_commit
+        {
+            if let Err(e) = write_ref(".lrngit", tracking_ref, &update.local_commit) {
+                lrncore::logs::warning_log(&format!("Failed to update {tracking_ref}: {e}"));
+                continue;
+            }
+            log_ref_update(
+                tracking_ref,
+                &update.remote_commit,
+                &update.local_commit,
+                "update by push",
+            );
+        }
+    }
+    success
+}
+
+/// Make the pushed branch track the branch it was pushed to, a pushed revision has nothing to
+/// track
+fn track_destination(remote: &Remote, update: &PushUpdate) {
+    let Some(branch_name) = update.src.strip_prefix("refs/heads/") else {
+        return;
+    };
+    set_upstream(branch_name, &remote.name, &update.dst);
+    println!(
+        "branch '{branch_name}' set up to track '{}/{}'.",
+        remote.name,
+        short_ref_name(&update.dst)
+    );
+}
+
+/// Check if the remote commit is an ancestor of the pushed one, an unknown remote commit is a new
+/// branch
+fn is_fast_forward(update: &PushUpdate) -> bool {
+    update.remote_commit.is_empty()
+        || is_ancestor(".lrngit", &update.remote_commit, &update.local_commit).unwrap_or(false)
+}
+
+/// Short name of a ref, `refs/heads/main` is `main`
+fn short_ref_name(name: &str) -> &str {
+    name.strip_prefix("refs/heads/")
+        .or_else(|| name.strip_prefix("refs/tags/"))
+        .unwrap_or(name)
+}
+
+/// Send a branch update to the remote repository. Enable connection between client and remote
+/// host and send object through an upload pack. Return true if the remote accepted it.
+fn send_update(remote: &Remote, update: &PushUpdate, tags: &[(String, String)]) -> bool {
+    let mut stream = tcp::tcp_connect_to_remote("lrngit-receive-pack", remote);
     // Reference to last local commit and last remote commit pack
     let mut ref_buff: Vec<u8> = Vec::new();
-    ref_buff.extend_from_slice(&b"REFS ".to_vec());
-    ref_buff.extend_from_slice(&refs.as_bytes());
-    ref_buff.extend_from_slice(&b" ".to_vec());
-    ref_buff.extend_from_slice(&last_commit.as_bytes());
-    ref_buff.extend_from_slice(&b" ".to_vec());
-    ref_buff.extend_from_slice(&last_remote_commit.as_bytes());
+    ref_buff.extend_from_slice(b"REFS ");
+    ref_buff.extend_from_slice(update.dst.as_bytes());
+    ref_buff.extend_from_slice(b" ");
+    ref_buff.extend_from_slice(update.local_commit.as_bytes());
+    ref_buff.extend_from_slice(b" ");
+    ref_buff.extend_from_slice(update.remote_commit.as_bytes());
     let ref_buff_len: u32 = ref_buff.len() as u32;
     let mut ref_pack: Vec<u8> = Vec::new();
     ref_pack.extend_from_slice(&ref_buff_len.to_le_bytes());
     ref_pack.extend_from_slice(&ref_buff);
-    ref_pack.extend_from_slice(last_remote_commit.as_bytes());
     // Pack object
-    let pack = create_upload_pack();
+    let pack = create_upload_pack(&update.local_commit, &update.remote_commit, tags);
     let mut upload_pack: Vec<u8> = Vec::new();
-    upload_pack.extend_from_slice(&b"PACK ".to_vec());
+    upload_pack.extend_from_slice(b"PACK ");
     upload_pack.extend_from_slice(&pack);
     let pack_length: u32 = upload_pack.len() as u32;
     let mut stream_framed: Vec<u8> = Vec::new();
@@ -66,16 +338,53 @@ fn push_remote_branch() {
         .write_all(&ref_pack)
         .expect("Failed to stream references to remote host");
     stream.flush().expect("Failed to flush references stream");
+    // Tags packet, before the pack so the remote creates them once objects are written
+    if !tags.is_empty() {
+        let tags_buff = build_tags_pack(tags);
+        let mut tags_pack: Vec<u8> = Vec::new();
+        tags_pack.extend_from_slice(&(tags_buff.len() as u32).to_le_bytes());
+        tags_pack.extend_from_slice(&tags_buff);
+        stream
+            .write_all(&tags_pack)
+            .expect("Failed to stream tags to remote host");
+        stream.flush().expect("Failed to flush tags stream");
+    }
     // Upload pack
     stream
         .write_all(&stream_framed)
         .expect("Failed to stream upload pack to remote host");
     stream.flush().expect("Failed to flush upload pack stream");
-    handle_server(stream);
+    handle_server(stream)
+}
+
+/// Run the pre-push hook with the remote name and url as arguments. Each pushed ref is given on
+/// stdin as `<local ref> <local hash> <remote ref> <remote hash>`, a ref missing on the remote
+/// has the null hash.
+fn run_pre_push_hook(remote: &Remote, updates: &[PushUpdate], tags: &[(String, String)]) {
+    let mut input = String::new();
+    for update in updates {
+        let remote_commit = if update.remote_commit.is_empty() {
+            NULL_HASH
+        } else {
+            &update.remote_commit
+        };
+        input.push_str(&format!(
+            "{} {} {} {remote_commit}\n",
+            update.src, update.local_commit, update.dst
+        ));
+    }
+    for (name, hash) in tags {
+        input.push_str(&format!(
+            "refs/tags/{name} {hash} refs/tags/{name} {NULL_HASH}\n"
+        ));
+    }
+    run_hook_or_exit("pre-push", &[&remote.name, &remote.url], input.as_bytes());
 }
 
-/// Handle connection with remote host and read incoming stream
-fn handle_server(mut stream: TcpStream) {
+/// Handle connection with remote host and read incoming stream, return true if the remote
+/// acknowledged the push
+fn handle_server(mut stream: TcpStream) -> bool {
+    let mut acked = false;
     let mut buffer = vec![0u8; 1024];
     // Loop over the stream to read all incoming packets
     loop {
@@ -107,6 +416,7 @@ fn handle_server(mut stream: TcpStream) {
         let received: &str =
             str::from_utf8(&buffer[..length as usize]).expect("Failed to cast buffer to str");
         if received == "ACK" {
+            acked = true;
             break;
         }
         println!("remote: {}", received);
@@ -114,4 +424,5 @@ fn handle_server(mut stream: TcpStream) {
     stream
         .shutdown(std::net::Shutdown::Write)
         .expect("Failed to shutdown stream");
+    acked
 }
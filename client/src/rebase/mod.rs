@@ -0,0 +1,558 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    process::exit,
+};
+
+use lrngitcore::{
+    objects::commit::{CommitContent, parse_commit_author},
+    refs::write_ref,
+    revwalk::{RevWalk, Sort, is_ancestor},
+};
+
+use crate::{
+    commit::write_index_tree,
+    fs::update_workdir,
+    object::{
+        commit::{parse_commit_by_hash, write_commit_object_as},
+        index::{apply_temp_index, build_temp_index},
+    },
+    refs::{init_refs, log_ref_update, parse_current_branch, parse_head, resolve_commit_revision},
+    sequencer::{Action, ApplyResult, apply_commit, check_resolved, hard_reset, subject},
+    status,
+    utils::{edit_file, strip_comments},
+};
+
+// State of a rebase in progress
+const REBASE_DIR: &str = ".lrngit/rebase-merge";
+
+const TODO_HELP: &str = "
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's log message
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+# If you remove a line here THAT COMMIT WILL BE LOST.
+# However, if you remove everything, the rebase will be aborted.
+";
+
+/// Command of a line of the todo list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TodoCommand {
+    Pick,
+    Reword,
+    Squash,
+    Fixup,
+    Drop,
+}
+
+impl TodoCommand {
+    fn parse(s: &str) -> Option<TodoCommand> {
+        match s {
+            "p" | "pick" => Some(TodoCommand::Pick),
+            "r" | "reword" => Some(TodoCommand::Reword),
+            "s" | "squash" => Some(TodoCommand::Squash),
+            "f" | "fixup" => Some(TodoCommand::Fixup),
+            "d" | "drop" => Some(TodoCommand::Drop),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            TodoCommand::Pick => "pick",
+            TodoCommand::Reword => "reword",
+            TodoCommand::Squash => "squash",
+            TodoCommand::Fixup => "fixup",
+            TodoCommand::Drop => "drop",
+        }
+    }
+}
+
+/// One line of the todo list
+#[derive(Debug, Clone)]
+struct TodoLine {
+    command: TodoCommand,
+    hash: String,
+}
+
+pub fn rebase_command() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(2).map(|x| x.as_str()) {
+        Some("--continue") => rebase_continue(),
+        Some("--skip") => rebase_skip(),
+        Some("--abort") => rebase_abort(),
+        Some("-i") => match args.get(3) {
+            Some(upstream) => rebase_start(upstream, true),
+            None => rebase_usage(),
+        },
+        Some(arg) if arg.starts_with('-') => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+        Some(upstream) => rebase_start(upstream, false),
+        None => rebase_usage(),
+    }
+    exit(0);
+}
+
+fn rebase_usage() {
+    lrncore::usage_exit::usage_and_exit(
+        "Invalid command",
+        "lrngit rebase [-i] <upstream> | --continue | --skip | --abort",
+    );
+    exit(1);
+}
+
+/// Replay the commits of the current branch not in upstream on top of it
+fn rebase_start(upstream: &str, interactive: bool) {
+    if Path::new(REBASE_DIR).exists() {
+        lrncore::logs::error_log("a rebase is already in progress");
+        println!("hint: use 'lrngit rebase --continue', '--skip' or '--abort'");
+        exit(128);
+    }
+    let files_status = status::get_files_status();
+    if !files_status.modified.is_empty()
+        || !files_status.staged.is_empty()
+        || !files_status.deleted.is_empty()
+    {
+        lrncore::logs::error_log("cannot rebase: You have local changes, commit or stash them");
+        exit(1);
+    }
+    let onto = resolve_commit_revision(upstream);
+    let orig_head = parse_current_branch();
+    if orig_head.is_empty() {
+        lrncore::logs::error_log("You do not have the initial commit yet");
+        exit(128);
+    }
+    // Commits of the branch oldest first
+    let mut walk = RevWalk::new(".lrngit");
+    walk.push(&orig_head);
+    walk.hide(&onto);
+    walk.set_sorting(Sort::Topological);
+    walk.simplify_first_parent();
+    let mut commits: Vec<String> = walk.collect::<Result<_, _>>().unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(1);
+    });
+    commits.reverse();
+    // Nothing to replay when the branch is already on top of upstream
+    let up_to_date = is_ancestor(".lrngit", &onto, &orig_head).unwrap_or(false);
+    if orig_head == onto || (up_to_date && !interactive) {
+        println!("Current branch is up to date.");
+        return;
+    }
+    let mut todo: Vec<TodoLine> = commits
+        .into_iter()
+        .map(|hash| TodoLine {
+            command: TodoCommand::Pick,
+            hash,
+        })
+        .collect();
+    fs::create_dir_all(REBASE_DIR).expect("Failed to create rebase directory");
+    if interactive {
+        todo = edit_todo(&todo);
+        if todo.is_empty() {
+            let _ = fs::remove_dir_all(REBASE_DIR);
+            println!("Nothing to do");
+            return;
+        }
+    }
+    let head_name = parse_head();
+    let head_name = if head_name == "HEAD" {
+        "detached HEAD".to_owned()
+    } else {
+        head_name
+    };
+    write_state("head-name", &head_name);
+    write_state("orig-head", &orig_head);
+    write_state("onto", &onto);
+    write_todo(&todo);
+    // Detach HEAD on the upstream, the branch is only moved when the rebase is done
+    let temp_index = build_temp_index(&orig_head, &onto);
+    update_workdir(temp_index.clone());
+    apply_temp_index(&temp_index);
+    fs::write(".lrngit/HEAD", &onto).expect("Failed to write HEAD");
+    log_ref_update(
+        "HEAD",
+        &orig_head,
+        &onto,
+        &format!("rebase (start): checkout {upstream}"),
+    );
+    run_todo();
+}
+
+/// Write the todo list with the help in the rebase directory, open the editor and parse it back
+fn edit_todo(todo: &[TodoLine]) -> Vec<TodoLine> {
+    let path = format!("{REBASE_DIR}/git-rebase-todo");
+    let mut content = String::new();
+    for line in todo {
+        let commit = parse_commit_by_hash(&line.hash);
+        content.push_str(&format!(
+            "{} {} {}\n",
+            line.command.as_str(),
+            &line.hash[..7],
+            subject(&commit)
+        ));
+    }
+    content.push_str(TODO_HELP);
+    fs::write(&path, content).expect("Failed to write rebase todo");
+    edit_file(&path);
+    let edited = fs::read_to_string(&path).expect("Failed to read rebase todo");
+    let mut lines: Vec<TodoLine> = Vec::new();
+    for line in edited.lines().map(|x| x.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let command = words.next().and_then(TodoCommand::parse);
+        let (Some(command), Some(rev)) = (command, words.next()) else {
+            let _ = fs::remove_dir_all(REBASE_DIR);
+            lrncore::logs::error_log(&format!("invalid line in the todo list: {line}"));
+            exit(1);
+        };
+        if lines.iter().all(|x| x.command == TodoCommand::Drop)
+            && matches!(command, TodoCommand::Squash | TodoCommand::Fixup)
+        {
+            let _ = fs::remove_dir_all(REBASE_DIR);
+            lrncore::logs::error_log(&format!(
+                "cannot '{}' without a previous commit",
+                command.as_str()
+            ));
+            exit(1);
+        }
+        lines.push(TodoLine {
+            command,
+            hash: resolve_commit_revision(rev),
+        });
+    }
+    lines
+}
+
+/// Run the lines left in the todo list, stop on the first conflict
+fn run_todo() {
+    let mut todo = read_todo();
+    while let Some(line) = todo.first().cloned() {
+        if line.command != TodoCommand::Drop {
+            match apply_commit(Action::Pick, &line.hash) {
+                ApplyResult::Clean(tree) => {
+                    let message = line_message(&line);
+                    finish_line(&line, tree, &message);
+                }
+                ApplyResult::Empty => {
+                    println!("The changes of {} are already applied, skipping", &line.hash[..7]);
+                }
+                ApplyResult::Conflict(paths) => stop_on_conflict(&line, &paths),
+            }
+        }
+        todo.remove(0);
+        write_todo(&todo);
+        append_done(&line);
+    }
+    finish_rebase();
+}
+
+/// Save the state needed to continue and exit
+fn stop_on_conflict(line: &TodoLine, paths: &[String]) {
+    write_state("message", &line_message(line));
+    write_state("stopped-sha", &line.hash);
+    write_state("conflicts", &paths.join("\n"));
+    for each in paths {
+        println!("CONFLICT (content): Merge conflict in {each}");
+    }
+    let commit = parse_commit_by_hash(&line.hash);
+    lrncore::logs::error_log(&format!(
+        "could not apply {}... {}",
+        &line.hash[..7],
+        subject(&commit)
+    ));
+    println!("hint: Resolve all conflicts manually, mark them as resolved with 'lrngit add <paths>'");
+    println!("hint: then run 'lrngit rebase --continue', skip this commit with 'lrngit rebase --skip'");
+    println!("hint: or stop and go back to the original branch with 'lrngit rebase --abort'");
+    exit(1);
+}
+
+/// Message of the commit created for a line, squash and fixup meld into the current HEAD one
+fn line_message(line: &TodoLine) -> String {
+    let commit = parse_commit_by_hash(&line.hash);
+    let message = String::from_utf8_lossy(&commit.message).trim_end().to_owned();
+    let head_message = || {
+        let head = parse_commit_by_hash(&parse_current_branch());
+        String::from_utf8_lossy(&head.message).trim_end().to_owned()
+    };
+    match line.command {
+        TodoCommand::Squash => {
+            let (count, sections) = combination();
+            format!(
+                "# This is a combination of {} commits.\n{sections}\n{}",
+                count + 1,
+                message_section(count + 1, &message, false)
+            )
+        }
+        TodoCommand::Fixup => head_message(),
+        _ => message,
+    }
+}
+
+/// Number of commits melded into HEAD by the squash and fixup lines run just before, with the
+/// sections of their messages. Saved along the HEAD they were melded into, any other HEAD is a
+/// single commit.
+fn combination() -> (usize, String) {
+    let head = parse_current_branch();
+    if read_state("squash-head") == head {
+        let count = read_state("squash-count").parse().unwrap_or(1);
+        return (count, read_state("message-squash"));
+    }
+    let head_commit = parse_commit_by_hash(&head);
+    (1, message_section(1, &String::from_utf8_lossy(&head_commit.message), false))
+}
+
+/// Section of a commit message in a combination, the message of a fixup is commented out
+fn message_section(number: usize, message: &str, skipped: bool) -> String {
+    let message = message.trim_end();
+    if skipped {
+        let commented: String = message
+            .lines()
+            .map(|x| if x.is_empty() { String::from("#\n") } else { format!("# {x}\n") })
+            .collect();
+        return format!("# The commit message #{number} will be skipped:\n\n{commented}");
+    }
+    if number == 1 {
+        return format!("# This is the 1st commit message:\n\n{message}\n");
+    }
+    format!("# This is the commit message #{number}:\n\n{message}\n")
+}
+
+/// Commit the tree of a line, a new commit on HEAD or a replacement of HEAD for squash and fixup
+fn finish_line(line: &TodoLine, tree: [u8; 20], message: &str) {
+    // Read before HEAD moves to the melded commit
+    let combined = matches!(line.command, TodoCommand::Squash | TodoCommand::Fixup)
+        .then(combination);
+    let message = match line.command {
+        TodoCommand::Reword | TodoCommand::Squash => {
+            // The line is continued from the index if the message is left empty
+            write_state("stopped-sha", &line.hash);
+            let message = edit_message(message);
+            clear_stopped();
+            message
+        }
+        _ => message.to_owned(),
+    };
+    let head = parse_current_branch();
+    let (parent, author_commit, reason): (String, CommitContent, &str) = match line.command {
+        TodoCommand::Squash | TodoCommand::Fixup => {
+            let head_commit = parse_commit_by_hash(&head);
+            let parent = String::from_utf8_lossy(&head_commit.parent).to_string();
+            let reason = if line.command == TodoCommand::Squash {
+                "rebase (squash)"
+            } else {
+                "rebase (fixup)"
+            };
+            (parent, head_commit, reason)
+        }
+        TodoCommand::Reword => (head, parse_commit_by_hash(&line.hash), "rebase (reword)"),
+        _ => (head, parse_commit_by_hash(&line.hash), "rebase (pick)"),
+    };
+    let author = parse_commit_author(author_commit.author.clone());
+    let hash = write_commit_object_as(tree, &parent, &message, &author);
+    let subject = message.lines().next().unwrap_or_default();
+    init_refs(hash.as_bytes(), &format!("{reason}: {subject}"));
+    if let Some((count, sections)) = combined {
+        let commit = parse_commit_by_hash(&line.hash);
+        let section = message_section(
+            count + 1,
+            &String::from_utf8_lossy(&commit.message),
+            line.command == TodoCommand::Fixup,
+        );
+        write_state("squash-head", &hash);
+        write_state("squash-count", &(count + 1).to_string());
+        write_state("message-squash", &format!("{sections}\n{section}"));
+    }
+}
+
+/// Let the user edit a commit message, exit on an empty message
+fn edit_message(message: &str) -> String {
+    let path = format!("{REBASE_DIR}/message");
+    fs::write(&path, format!("{message}\n")).expect("Failed to write commit message");
+    edit_file(&path);
+    let edited = strip_comments(&fs::read_to_string(&path).unwrap_or_default());
+    if edited.is_empty() {
+        lrncore::logs::error_log("Aborting commit due to empty commit message");
+        println!("hint: fix the message with 'lrngit rebase --continue' or stop with 'lrngit rebase --abort'");
+        exit(1);
+    }
+    edited
+}
+
+/// Commit the resolved conflicts of the stopped line and run the rest of the todo list
+fn rebase_continue() {
+    if !Path::new(REBASE_DIR).exists() {
+        lrncore::logs::error_log("No rebase in progress?");
+        exit(128);
+    }
+    let mut todo = read_todo();
+    if let Some(line) = todo.first().cloned()
+        && Path::new(&format!("{REBASE_DIR}/stopped-sha")).exists()
+    {
+        let conflicts = read_state("conflicts");
+        check_resolved(conflicts.lines());
+        let message = read_state("message");
+        let tree = write_index_tree();
+        let head_tree = hex::encode(parse_commit_by_hash(&parse_current_branch()).tree);
+        if hex::encode(tree) != head_tree || matches!(line.command, TodoCommand::Squash | TodoCommand::Fixup) {
+            finish_line(&line, tree, &message);
+        }
+        clear_stopped();
+        todo.remove(0);
+        write_todo(&todo);
+        append_done(&line);
+    }
+    run_todo();
+}
+
+/// Drop the changes of the stopped line and run the rest of the todo list
+fn rebase_skip() {
+    if !Path::new(REBASE_DIR).exists() {
+        lrncore::logs::error_log("No rebase in progress?");
+        exit(128);
+    }
+    let head = parse_current_branch();
+    hard_reset(&head, "rebase: skip");
+    let mut todo = read_todo();
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    clear_stopped();
+    write_todo(&todo);
+    run_todo();
+}
+
+/// Stop the rebase and go back to the original branch as it was
+fn rebase_abort() {
+    if !Path::new(REBASE_DIR).exists() {
+        lrncore::logs::error_log("No rebase in progress?");
+        exit(128);
+    }
+    let head_name = read_state("head-name");
+    let orig_head = read_state("orig-head");
+    let current = parse_current_branch();
+    restore_head(&head_name, &orig_head);
+    hard_reset(&orig_head, "rebase: abort");
+    log_ref_update(
+        "HEAD",
+        &current,
+        &orig_head,
+        &format!("rebase (abort): returning to {head_name}"),
+    );
+    let _ = fs::remove_dir_all(REBASE_DIR);
+}
+
+/// Move the original branch to the rebased commits and check it out again
+fn finish_rebase() {
+    let head_name = read_state("head-name");
+    let orig_head = read_state("orig-head");
+    let onto = read_state("onto");
+    let new_head = parse_current_branch();
+    if head_name.starts_with("refs/") {
+        if let Err(e) = write_ref(".lrngit", &head_name, &new_head) {
+            lrncore::logs::error_log(&format!("Failed to update {head_name}: {e}"));
+            exit(1);
+        }
+        log_ref_update(
+            &head_name,
+            &orig_head,
+            &new_head,
+            &format!("rebase (finish): {head_name} onto {onto}"),
+        );
+    }
+    restore_head(&head_name, &new_head);
+    log_ref_update(
+        "HEAD",
+        &new_head,
+        &new_head,
+        &format!("rebase (finish): returning to {head_name}"),
+    );
+    let _ = fs::remove_dir_all(REBASE_DIR);
+    println!("Successfully rebased and updated {head_name}.");
+}
+
+/// Point HEAD back to the branch the rebase started on, or to the commit if it was detached
+fn restore_head(head_name: &str, commit: &str) {
+    let content = if head_name.starts_with("refs/") {
+        format!("ref: {head_name}")
+    } else {
+        commit.to_owned()
+    };
+    fs::write(".lrngit/HEAD", content).expect("Failed to write HEAD");
+}
+
+fn clear_stopped() {
+    for each in ["stopped-sha", "conflicts", "message"] {
+        let _ = fs::remove_file(format!("{REBASE_DIR}/{each}"));
+    }
+}
+
+fn read_state(name: &str) -> String {
+    fs::read_to_string(format!("{REBASE_DIR}/{name}")).unwrap_or_default()
+}
+
+fn write_state(name: &str, content: &str) {
+    fs::write(format!("{REBASE_DIR}/{name}"), content).expect("Failed to write rebase state");
+}
+
+fn read_todo() -> Vec<TodoLine> {
+    read_state("git-rebase-todo")
+        .lines()
+        .filter_map(|x| {
+            let (command, hash) = x.split_once(' ')?;
+            Some(TodoLine {
+                command: TodoCommand::parse(command)?,
+                hash: hash.to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn write_todo(todo: &[TodoLine]) {
+    let content: String = todo
+        .iter()
+        .map(|x| format!("{} {}\n", x.command.as_str(), x.hash))
+        .collect();
+    write_state("git-rebase-todo", &content);
+}
+
+/// Record a line run in the `done` file
+fn append_done(line: &TodoLine) {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(format!("{REBASE_DIR}/done"))
+        .expect("Failed to open rebase done file");
+    writeln!(file, "{} {}", line.command.as_str(), line.hash).expect("Failed to write rebase done file");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_sections_are_numbered() {
+        assert_eq!(
+            message_section(1, "first\n\nbody\n", false),
+            "# This is the 1st commit message:\n\nfirst\n\nbody\n"
+        );
+        assert_eq!(
+            message_section(3, "third", false),
+            "# This is the commit message #3:\n\nthird\n"
+        );
+        assert_eq!(
+            message_section(2, "fixup\n\nbody", true),
+            "# The commit message #2 will be skipped:\n\n# fixup\n#\n# body\n"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::{env, process::exit};
+
+use lrngitcore::reflog::read_reflog;
+
+pub fn reflog_command() {
+    let args: Vec<String> = env::args().collect();
+    let mut refname = "HEAD";
+    match args.get(2).map(|x| x.as_str()) {
+        None => {}
+        Some("show") => {
+            if let Some(name) = args.get(3) {
+                refname = name;
+            }
+        }
+        Some(name) if !name.starts_with('-') => refname = name,
+        Some(_) => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+    }
+    show_reflog(refname);
+    exit(0);
+}
+
+/// Print the reflog of a ref, newest first, like `e3f1c2a HEAD@{0}: commit: message`
+fn show_reflog(name: &str) {
+    let refname = full_refname(name);
+    let entries = match read_reflog(".lrngit", &refname) {
+        Ok(e) => e,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        println!("{} {name}@{{{i}}}: {}", &entry.new[..7], entry.message);
+    }
+}
+
+/// Branch names are shown as given but their reflog is under `refs/heads`
+fn full_refname(name: &str) -> String {
+    if name == "HEAD" || name.starts_with("refs/") {
+        return name.to_owned();
+    }
+    format!("refs/heads/{name}")
+}
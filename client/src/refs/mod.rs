@@ -2,8 +2,17 @@ use std::{
     fs::File,
     io::{Read, Write},
     path::Path,
+    process::exit,
+    time::SystemTime,
 };
 
+use lrngitcore::{
+    reflog::{NULL_HASH, ReflogEntry, append_reflog},
+    revparse,
+};
+
+use crate::{config, utils::local_timezone};
+
 pub mod origin;
 
 pub fn init_head() {
@@ -12,17 +21,20 @@ pub fn init_head() {
         .expect("Failed to write in HEAD file");
 }
 
-/// get content of the HEAD file, ref of the current branch
+/// get content of the HEAD file, ref of the current branch.
+/// Return `HEAD` when HEAD is detached, the file then holds the commit hash itself.
 pub fn parse_head() -> String {
     let mut head = File::open(".lrngit/HEAD").expect("Failed to open HEAD file");
     let mut content: String = String::new();
     head.read_to_string(&mut content)
         .expect("Failed to read HEAD file content");
-    let split_content: Vec<&str> = content.split("ref: ").collect();
-    split_content[1].to_string()
+    match content.trim().strip_prefix("ref: ") {
+        Some(refname) => refname.to_string(),
+        None => "HEAD".to_string(),
+    }
 }
 
-/// get last commit from the current HEAD
+/// get last commit from the current HEAD, read from HEAD itself when it's detached
 pub fn parse_current_branch() -> String {
     let head = parse_head();
     let branch_string = ".lrngit/".to_string() + &head;
@@ -39,10 +51,62 @@ pub fn parse_current_branch() -> String {
     content
 }
 
-pub fn init_refs(commit_hash: &[u8]) {
+/// Move the current branch to the given commit, `reason` is recorded in the reflog
+pub fn init_refs(commit_hash: &[u8], reason: &str) {
     let head_content = parse_head();
+    let old = parse_current_branch();
     let mut file = File::create(".lrngit/".to_string() + head_content.as_str())
         .expect("Failed to create refs/head file");
     file.write_all(commit_hash)
         .expect("Failed to write commit to branch file");
+    log_ref_update(&head_content, &old, &String::from_utf8_lossy(commit_hash), reason);
+}
+
+/// Record a ref update in its reflog, and in the HEAD reflog too when the ref is the current
+/// branch. An empty old or new value means the ref didn't exist.
+pub fn log_ref_update(refname: &str, old: &str, new: &str, reason: &str) {
+    let user = config::try_parse_user();
+    let entry = ReflogEntry {
+        old: if old.is_empty() { NULL_HASH } else { old }.to_owned(),
+        new: if new.is_empty() { NULL_HASH } else { new }.to_owned(),
+        name: user.as_ref().map(|x| x.name.clone()).unwrap_or_default(),
+        email: user.map(|x| x.email).unwrap_or_default(),
+        timestamp: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64,
+        timezone: local_timezone(),
+        message: reason.to_owned(),
+    };
+    let mut refs = vec![refname];
+    if refname != "HEAD" && parse_head() == refname {
+        refs.push("HEAD");
+    }
+    for each in refs {
+        if let Err(e) = append_reflog(".lrngit", each, &entry) {
+            lrncore::logs::warning_log(&format!("Failed to update reflog of {each}: {e}"));
+        }
+    }
+}
+
+/// Resolve a revision given on the command line to an object hash, exit if it can't be resolved
+pub fn resolve_revision(spec: &str) -> String {
+    match revparse::resolve(".lrngit", spec) {
+        Ok(hash) => hash,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    }
+}
+
+/// Resolve a revision given on the command line to a commit hash, exit if it isn't a commit
+pub fn resolve_commit_revision(spec: &str) -> String {
+    match revparse::resolve_commit(".lrngit", spec) {
+        Ok(hash) => hash,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    }
 }
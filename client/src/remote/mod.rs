@@ -1,20 +1,239 @@
-use std::{env, process::exit};
+/*
+Module handling the remote command, manage the remotes of the local config and their tracking refs
+*/
 
-use crate::config::update_remote_url_local_config;
+use std::{env, fs, io::ErrorKind, path::Path, process::exit};
+
+use lrngitcore::{
+    config::{self as core_config, Config, ConfigScope},
+    refs::list_refs,
+    remote::{
+        DEFAULT_REMOTE, Remote, default_fetch, find_remote, is_valid_remote_name, list_remotes,
+        migrate_legacy_remote,
+    },
+};
+
+use crate::refs::parse_head;
+
+const LOCAL_CONFIG: &str = ".lrngit/config";
+
+const REMOTE_USAGE: &str = "lrngit remote [-v]
+lrngit remote add <name> <url>
+lrngit remote remove <name>
+lrngit remote rename <old> <new>
+lrngit remote set-url <name> <url>
+lrngit remote get-url <name>
+lrngit remote show <name>...";
 
 pub fn remote_command() {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        lrncore::usage_exit::usage_and_exit("Invalid command", "use -m flag");
-    }
-    match args[2].as_str() {
-        "add" => {
-            let remote = args[3].as_str();
-            update_remote_url_local_config(remote);
+    let args: Vec<&str> = args.iter().skip(2).map(|x| x.as_str()).collect();
+    match args.as_slice() {
+        [] | ["show"] => list_all_remotes(false),
+        ["-v" | "--verbose"] => list_all_remotes(true),
+        ["add", name, url] => add_remote(name, url),
+        ["remove" | "rm", name] => remove_remote(name),
+        ["rename", old, new] => rename_remote(old, new),
+        ["set-url", name, url] => set_remote_url(name, url),
+        ["get-url", name] => println!("{}", remote_or_exit(name).url),
+        ["show", names @ ..] => {
+            for name in names {
+                show_remote(name);
+            }
         }
         _ => {
-            lrncore::logs::warning_log("Unknown command");
+            lrncore::usage_exit::usage_and_exit("Invalid command", REMOTE_USAGE);
             exit(1);
         }
     }
+    exit(0);
+}
+
+/// Get a remote of the config, exit with 2 like git if it doesn't exist
+pub fn remote_or_exit(name: &str) -> Remote {
+    match find_remote(&Config::load(".lrngit"), name) {
+        Some(remote) => remote,
+        None => {
+            lrncore::logs::error_log(&format!("No such remote '{name}'"));
+            exit(2);
+        }
+    }
+}
+
+/// Remote of the current branch upstream, origin when it has none or tracks a local branch
+pub fn default_remote_name() -> String {
+    let head = parse_head();
+    let branch_name = head.strip_prefix("refs/heads/").unwrap_or(&head);
+    Config::load(".lrngit")
+        .get(&format!("branch.{branch_name}.remote"))
+        .filter(|x| *x != ".")
+        .unwrap_or(DEFAULT_REMOTE)
+        .to_owned()
+}
+
+/// Branches of the local config whose upstream is on the given remote
+fn branches_tracking(name: &str) -> Vec<String> {
+    Config::load_scope(ConfigScope::Local, ".lrngit")
+        .entries()
+        .iter()
+        .filter(|x| x.value == name)
+        .filter_map(|x| x.key.strip_prefix("branch.")?.strip_suffix(".remote"))
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+/// Exit if the name can't be used for a new remote
+fn check_new_remote_name(name: &str) {
+    if !is_valid_remote_name(name) {
+        lrncore::logs::error_log(&format!("'{name}' is not a valid remote name"));
+        exit(128);
+    }
+    if find_remote(&Config::load(".lrngit"), name).is_some() {
+        lrncore::logs::error_log(&format!("remote {name} already exists."));
+        exit(3);
+    }
+}
+
+/// Get a remote to edit in the local config, the legacy `[remote]` section is moved to
+/// `[remote "origin"]` first
+fn editable_remote_or_exit(name: &str) -> Remote {
+    let remote = remote_or_exit(name);
+    if name == DEFAULT_REMOTE
+        && let Err(e) = migrate_legacy_remote(Path::new(LOCAL_CONFIG))
+    {
+        lrncore::logs::error_log(&format!("Failed to migrate the remote section: {e}"));
+        exit(1);
+    }
+    remote
+}
+
+fn set_local_value(key: &str, value: &str) {
+    if let Err(e) = core_config::set_value(Path::new(LOCAL_CONFIG), key, value) {
+        lrncore::logs::error_log(&format!("Failed to set {key}: {e}"));
+        exit(1);
+    }
+}
+
+/// Print the remotes, with their fetch and push urls in verbose mode
+fn list_all_remotes(verbose: bool) {
+    for remote in list_remotes(&Config::load(".lrngit")) {
+        if verbose {
+            println!("{}\t{} (fetch)", remote.name, remote.url);
+            println!("{}\t{} (push)", remote.name, remote.url);
+        } else {
+            println!("{}", remote.name);
+        }
+    }
+}
+
+/// Add a remote tracking every branch of the remote under `refs/remotes/<name>/`
+fn add_remote(name: &str, url: &str) {
+    check_new_remote_name(name);
+    set_local_value(&format!("remote.{name}.url"), url);
+    set_local_value(&format!("remote.{name}.fetch"), &default_fetch(name));
+    if let Err(e) = fs::create_dir_all(format!(".lrngit/refs/remotes/{name}")) {
+        lrncore::logs::error_log(&format!("Failed to create the refs of remote {name}: {e}"));
+        exit(1);
+    }
+}
+
+/// Remove a remote from the config with its tracking refs and their reflogs
+fn remove_remote(name: &str) {
+    let remote = editable_remote_or_exit(name);
+    if let Err(e) = core_config::remove_section(Path::new(LOCAL_CONFIG), &format!("remote.{name}"))
+    {
+        lrncore::logs::error_log(&format!("Failed to remove remote {name}: {e}"));
+        exit(1);
+    }
+    // Branches tracking the remote lose their upstream
+    for branch_name in branches_tracking(name) {
+        for key in ["remote", "merge"] {
+            let key = format!("branch.{branch_name}.{key}");
+            if let Err(e) = core_config::unset_value(Path::new(LOCAL_CONFIG), &key) {
+                lrncore::logs::warning_log(&format!("Failed to unset {key}: {e}"));
+            }
+        }
+    }
+    let prefix = remote.refs_prefix();
+    for dir in [
+        format!(".lrngit/{prefix}"),
+        format!(".lrngit/logs/{prefix}"),
+    ] {
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                lrncore::logs::error_log(&format!("Failed to remove {dir}: {e}"));
+                exit(1);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rename a remote, its tracking refs and reflogs are moved along. The default fetch refspec
+/// follows the new name, a custom one is kept as is.
+fn rename_remote(old: &str, new: &str) {
+    let remote = editable_remote_or_exit(old);
+    check_new_remote_name(new);
+    let path = Path::new(LOCAL_CONFIG);
+    if let Err(e) =
+        core_config::rename_section(path, &format!("remote.{old}"), &format!("remote.{new}"))
+    {
+        lrncore::logs::error_log(&format!("Failed to rename remote {old}: {e}"));
+        exit(1);
+    }
+    if remote.fetch == [default_fetch(old)] {
+        set_local_value(&format!("remote.{new}.fetch"), &default_fetch(new));
+    }
+    for branch_name in branches_tracking(old) {
+        set_local_value(&format!("branch.{branch_name}.remote"), new);
+    }
+    let old_prefix = remote.refs_prefix();
+    let new_prefix = format!("refs/remotes/{new}");
+    for base in [".lrngit", ".lrngit/logs"] {
+        let from = format!("{base}/{old_prefix}");
+        if !Path::new(&from).is_dir() {
+            continue;
+        }
+        if let Err(e) = fs::rename(&from, format!("{base}/{new_prefix}")) {
+            lrncore::logs::error_log(&format!("Failed to move {from}: {e}"));
+            exit(1);
+        }
+    }
+    // A symbolic ref like HEAD points to a branch of the remote under its old name
+    let head = format!(".lrngit/{new_prefix}/HEAD");
+    if let Ok(content) = fs::read_to_string(&head)
+        && let Some(branch) = content.trim().strip_prefix(&format!("ref: {old_prefix}/"))
+        && let Err(e) = fs::write(&head, format!("ref: {new_prefix}/{branch}"))
+    {
+        lrncore::logs::error_log(&format!("Failed to update {head}: {e}"));
+        exit(1);
+    }
+}
+
+fn set_remote_url(name: &str, url: &str) {
+    editable_remote_or_exit(name);
+    set_local_value(&format!("remote.{name}.url"), url);
+}
+
+/// Print the urls of a remote and the branches known from its tracking refs
+fn show_remote(name: &str) {
+    let remote = remote_or_exit(name);
+    println!("* remote {}", remote.name);
+    println!("  Fetch URL: {}", remote.url);
+    println!("  Push  URL: {}", remote.url);
+    let prefix = remote.refs_prefix();
+    let branches: Vec<String> = list_refs(".lrngit", &prefix)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(x, _)| x[prefix.len() + 1..].to_owned())
+        .filter(|x| x != "HEAD")
+        .collect();
+    if branches.is_empty() {
+        println!("  Remote branches: none");
+        return;
+    }
+    println!("  Remote branches:");
+    for branch in branches {
+        println!("    {branch} tracked");
+    }
 }
@@ -0,0 +1,28 @@
+use std::{env, process::exit};
+
+use crate::refs::resolve_revision;
+
+pub fn rev_parse_command() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() <= 2 {
+        lrncore::usage_exit::usage_and_exit(
+            "Invalid command",
+            "lrngit rev-parse [--short] <revision>...",
+        );
+    }
+    let mut short = false;
+    for arg in &args[2..] {
+        match arg.as_str() {
+            "--short" => short = true,
+            _ => {
+                let hash = resolve_revision(arg);
+                if short {
+                    println!("{}", &hash[..7]);
+                } else {
+                    println!("{hash}");
+                }
+            }
+        }
+    }
+    exit(0);
+}
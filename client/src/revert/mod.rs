@@ -0,0 +1,9 @@
+use std::env;
+
+use crate::sequencer::{Action, sequencer_command};
+
+/// `lrngit revert <rev>...`, commit the inverse of the changes of each commit
+pub fn revert_command() {
+    let args: Vec<String> = env::args().collect();
+    sequencer_command(Action::Revert, &args[2..]);
+}
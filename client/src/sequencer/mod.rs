@@ -0,0 +1,411 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use lrngitcore::{
+    merge::{MergeLabels, MergeResult, has_conflict_markers, merge_trees},
+    objects::commit::{CommitContent, commit_parents, parse_commit_author},
+};
+
+use crate::{
+    commit::write_index_tree,
+    fs::{checkout_blob, delete_path, write_files},
+    object::{
+        blob::{hash_blob_content, write_blob},
+        commit::{author_identity, parse_commit_by_hash, write_commit_object_as},
+        index::{self, add_index_entries, commit_files, index_entry_from_disk, parse_index},
+    },
+    refs::{init_refs, parse_current_branch, parse_head, resolve_commit_revision},
+    status,
+};
+
+// State of a cherry-pick or revert in progress
+const SEQUENCER_DIR: &str = ".lrngit/sequencer";
+// Message of the commit stopped on conflicts, used by --continue
+const MERGE_MSG: &str = ".lrngit/MERGE_MSG";
+
+/// What is done with each commit of the sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pick,
+    Revert,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    /// Name of the command running the action
+    fn command(&self) -> &'static str {
+        match self {
+            Action::Pick => "cherry-pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    /// File holding the commit being applied when stopped on conflicts
+    fn head_file(&self) -> &'static str {
+        match self {
+            Action::Pick => ".lrngit/CHERRY_PICK_HEAD",
+            Action::Revert => ".lrngit/REVERT_HEAD",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Action> {
+        match s {
+            "pick" => Some(Action::Pick),
+            "revert" => Some(Action::Revert),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of merging the changes of a commit in the index and the working directory
+pub enum ApplyResult {
+    /// The changes were merged cleanly, with the tree of the index
+    Clean([u8; 20]),
+    /// The changes were already on HEAD, nothing was committed
+    Empty,
+    /// The changes clash with HEAD, the files are left with conflict markers
+    Conflict(Vec<String>),
+}
+
+/// Run the cherry-pick or revert command with its arguments
+pub fn sequencer_command(action: Action, args: &[String]) {
+    match args.first().map(|x| x.as_str()) {
+        Some("--continue") => sequencer_continue(action),
+        Some("--abort") => sequencer_abort(action),
+        Some(arg) if arg.starts_with('-') => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+        Some(_) => sequencer_start(action, args),
+        None => {
+            lrncore::usage_exit::usage_and_exit(
+                "Invalid command",
+                &format!("lrngit {} <rev>... | --continue | --abort", action.command()),
+            );
+        }
+    }
+    exit(0);
+}
+
+fn sequencer_start(action: Action, revs: &[String]) {
+    if Path::new(SEQUENCER_DIR).exists() {
+        lrncore::logs::error_log("a cherry-pick or revert is already in progress");
+        println!("hint: use 'lrngit {} --continue' or '--abort'", action.command());
+        exit(128);
+    }
+    let head = parse_current_branch();
+    if head.is_empty() {
+        lrncore::logs::error_log("You do not have the initial commit yet");
+        exit(128);
+    }
+    if !status::get_files_status().staged.is_empty() {
+        lrncore::logs::error_log("your local changes would be overwritten, commit or stash them first");
+        exit(128);
+    }
+    let todo: Vec<(Action, String)> = revs
+        .iter()
+        .map(|x| (action, resolve_commit_revision(x)))
+        .collect();
+    fs::create_dir_all(SEQUENCER_DIR).expect("Failed to create sequencer directory");
+    fs::write(format!("{SEQUENCER_DIR}/head"), &head).expect("Failed to write sequencer head");
+    write_todo(&todo);
+    run_todo();
+}
+
+/// Apply the commits left in the todo list, stop on the first conflict
+fn run_todo() {
+    let mut todo = read_todo();
+    while let Some((action, hash)) = todo.first().cloned() {
+        match apply_commit(action, &hash) {
+            ApplyResult::Clean(tree) => {
+                let commit = parse_commit_by_hash(&hash);
+                commit_on_head(action, &commit, tree, &action_message(action, &hash, &commit));
+            }
+            ApplyResult::Empty => {
+                println!("The changes of {} are already applied, skipping", &hash[..7]);
+            }
+            ApplyResult::Conflict(paths) => {
+                let commit = parse_commit_by_hash(&hash);
+                fs::write(MERGE_MSG, action_message(action, &hash, &commit))
+                    .expect("Failed to write MERGE_MSG");
+                fs::write(action.head_file(), &hash).expect("Failed to write sequencer state");
+                fs::write(format!("{SEQUENCER_DIR}/conflicts"), paths.join("\n"))
+                    .expect("Failed to write sequencer conflicts");
+                for each in &paths {
+                    println!("CONFLICT (content): Merge conflict in {each}");
+                }
+                lrncore::logs::error_log(&format!(
+                    "could not {} {}... {}",
+                    if action == Action::Pick { "apply" } else { "revert" },
+                    &hash[..7],
+                    subject(&commit)
+                ));
+                println!(
+                    "hint: after resolving the conflicts, mark the corrected paths with 'lrngit add <paths>' and run 'lrngit {} --continue'",
+                    action.command()
+                );
+                exit(1);
+            }
+        }
+        todo.remove(0);
+        write_todo(&todo);
+    }
+    let _ = fs::remove_dir_all(SEQUENCER_DIR);
+}
+
+/// Commit the resolved conflicts and apply the remaining commits
+fn sequencer_continue(action: Action) {
+    if !Path::new(SEQUENCER_DIR).exists() {
+        lrncore::logs::error_log(&format!("no {} in progress", action.command()));
+        exit(128);
+    }
+    let todo = read_todo();
+    if let Some((action, hash)) = todo.first().cloned()
+        && Path::new(action.head_file()).exists()
+    {
+        let conflicts = fs::read_to_string(format!("{SEQUENCER_DIR}/conflicts")).unwrap_or_default();
+        check_resolved(conflicts.lines());
+        // A conflict resolved by deleting the file must not come back from the index
+        for path in conflicts.lines().filter(|x| !x.is_empty()) {
+            if !Path::new(path).exists() {
+                index::remove_index_entry(path);
+            }
+        }
+        let message = fs::read_to_string(MERGE_MSG).expect("Failed to read MERGE_MSG");
+        let commit = parse_commit_by_hash(&hash);
+        let tree = write_index_tree();
+        commit_on_head(action, &commit, tree, &message);
+        let _ = fs::remove_file(MERGE_MSG);
+        let _ = fs::remove_file(action.head_file());
+        let mut todo = todo;
+        todo.remove(0);
+        write_todo(&todo);
+    }
+    run_todo();
+}
+
+/// Put HEAD, the index and the working directory back as they were before the sequence
+fn sequencer_abort(action: Action) {
+    let Ok(head) = fs::read_to_string(format!("{SEQUENCER_DIR}/head")) else {
+        lrncore::logs::error_log(&format!("no {} in progress", action.command()));
+        exit(128);
+    };
+    hard_reset(&head, &format!("{}: abort", action.command()));
+    for each in [Action::Pick, Action::Revert] {
+        let _ = fs::remove_file(each.head_file());
+    }
+    let _ = fs::remove_file(MERGE_MSG);
+    let _ = fs::remove_dir_all(SEQUENCER_DIR);
+}
+
+/// Exit if a conflicted file isn't marked as resolved, added to the index without any conflict
+/// marker left
+pub fn check_resolved<'a>(paths: impl Iterator<Item = &'a str>) {
+    let files_status = status::get_files_status();
+    let modified: HashSet<String> = files_status.modified.into_iter().map(|x| x.file).collect();
+    let unresolved: Vec<&str> = paths
+        .filter(|x| !x.is_empty())
+        .filter(|x| {
+            modified.contains(*x)
+                || fs::read(x).map(|c| has_conflict_markers(&c)).unwrap_or(false)
+        })
+        .collect();
+    if unresolved.is_empty() {
+        return;
+    }
+    lrncore::logs::error_log("you need to resolve your current conflicts first");
+    for each in unresolved {
+        println!("\tboth modified: {each}");
+    }
+    println!("hint: fix the conflicts and run 'lrngit add <paths>' to mark them resolved");
+    exit(1);
+}
+
+/// Apply the changes of a commit, or their inverse with `Action::Revert`, on top of HEAD with a
+/// three-way merge in the index and the working directory, nothing is committed.
+/// Exit if local changes or untracked files are in the way.
+pub fn apply_commit(action: Action, hash: &str) -> ApplyResult {
+    let commit = parse_commit_by_hash(hash);
+    let parent = commit_parents(&commit).into_iter().next().unwrap_or_default();
+    let commit_tree = hex::encode(commit.tree);
+    let parent_tree = (!parent.is_empty()).then(|| hex::encode(parse_commit_by_hash(&parent).tree));
+    let head = parse_current_branch();
+    let head_tree = hex::encode(parse_commit_by_hash(&head).tree);
+    let short = format!("{}... {}", &hash[..7], subject(&commit));
+    let (base, theirs, theirs_label) = match action {
+        Action::Pick => (parent_tree.clone(), Some(commit_tree), short),
+        Action::Revert => (Some(commit_tree), parent_tree, format!("parent of {short}")),
+    };
+    let labels = MergeLabels {
+        ours: "HEAD",
+        theirs: &theirs_label,
+    };
+    let changes = merge_trees(
+        ".lrngit",
+        base.as_deref(),
+        Some(&head_tree),
+        theirs.as_deref(),
+        &labels,
+    )
+    .unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to merge {hash}: {e}"));
+        exit(1);
+    });
+    check_worktree(changes.iter().map(|x| x.path.as_str()), &head);
+    let mut conflicts: Vec<String> = Vec::new();
+    let mut written = Vec::new();
+    for change in changes {
+        let path = change.path;
+        match change.result {
+            MergeResult::Blob(blob) => {
+                checkout_blob(&blob, &path);
+                written.push(index_entry_from_disk(Path::new(&path), blob));
+            }
+            MergeResult::Merged(content) => {
+                let blob = hash_blob_content(&content);
+                write_blob(&blob);
+                write_files(&content, &path);
+                written.push(index_entry_from_disk(Path::new(&path), blob.hash));
+            }
+            MergeResult::Deleted => {
+                if Path::new(&path).exists() {
+                    delete_path(&PathBuf::from(&path));
+                }
+                index::remove_index_entry(&path);
+            }
+            MergeResult::Conflict(content) => {
+                write_files(&content, &path);
+                conflicts.push(path);
+            }
+        }
+    }
+    add_index_entries(written);
+    if !conflicts.is_empty() {
+        return ApplyResult::Conflict(conflicts);
+    }
+    let tree = write_index_tree();
+    if hex::encode(tree) == head_tree {
+        return ApplyResult::Empty;
+    }
+    ApplyResult::Clean(tree)
+}
+
+/// Exit before touching anything if a file to update has local changes, or is a new file in the
+/// way of an untracked one
+fn check_worktree<'a>(paths: impl Iterator<Item = &'a str>, head: &str) {
+    let files_status = status::get_files_status();
+    let local_changes: HashSet<String> = files_status
+        .modified
+        .into_iter()
+        .chain(files_status.staged)
+        .chain(files_status.deleted)
+        .map(|x| x.file)
+        .collect();
+    let head_files = commit_files(head);
+    let tracked: HashSet<String> = parse_index()
+        .entries
+        .into_iter()
+        .map(|x| String::from_utf8_lossy(&x.path).to_string())
+        .collect();
+    let blocked: Vec<&str> = paths
+        .filter(|x| {
+            local_changes.contains(*x)
+                || (!head_files.contains_key(*x) && !tracked.contains(*x) && Path::new(x).exists())
+        })
+        .collect();
+    if blocked.is_empty() {
+        return;
+    }
+    println!("error: Your local changes to the following files would be overwritten:");
+    for each in blocked {
+        println!("\t{each}");
+    }
+    println!("Please commit your changes or stash them first.");
+    println!("Aborting");
+    exit(1);
+}
+
+/// Commit the index tree on HEAD, a picked commit keeps its author
+fn commit_on_head(action: Action, commit: &CommitContent, tree: [u8; 20], message: &str) {
+    let author = match action {
+        Action::Pick => parse_commit_author(commit.author.clone()),
+        Action::Revert => author_identity(),
+    };
+    let head = parse_current_branch();
+    let hash = write_commit_object_as(tree, &head, message, &author);
+    let subject = message.lines().next().unwrap_or_default();
+    init_refs(hash.as_bytes(), &format!("{}: {subject}", action.command()));
+    let head_ref = parse_head();
+    let branch = head_ref.strip_prefix("refs/heads/").unwrap_or("detached HEAD");
+    println!("[{branch} {}] {subject}", &hash[..7]);
+}
+
+/// Message of the commit created by the action, recording the source commit
+fn action_message(action: Action, hash: &str, commit: &CommitContent) -> String {
+    match action {
+        Action::Pick => format!(
+            "{}\n\n(cherry picked from commit {hash})",
+            String::from_utf8_lossy(&commit.message).trim_end()
+        ),
+        Action::Revert => format!(
+            "Revert \"{}\"\n\nThis reverts commit {hash}.",
+            subject(commit)
+        ),
+    }
+}
+
+pub fn subject(commit: &CommitContent) -> String {
+    String::from_utf8_lossy(&commit.message)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Move HEAD to the given commit and make the index and the working directory match it, local
+/// changes to tracked files are lost
+pub fn hard_reset(commit_hash: &str, reason: &str) {
+    let target = commit_files(commit_hash);
+    for entry in parse_index().entries {
+        let path = String::from_utf8_lossy(&entry.path).to_string();
+        if !target.contains_key(&path) && Path::new(&path).exists() {
+            delete_path(&PathBuf::from(&path));
+        }
+    }
+    for (path, hash) in &target {
+        checkout_blob(hash, path);
+    }
+    if parse_current_branch() != commit_hash {
+        init_refs(commit_hash.as_bytes(), reason);
+    }
+    index::reset_index(commit_hash);
+}
+
+fn read_todo() -> Vec<(Action, String)> {
+    fs::read_to_string(format!("{SEQUENCER_DIR}/todo"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| {
+            let (action, hash) = x.split_once(' ')?;
+            Some((Action::parse(action)?, hash.to_owned()))
+        })
+        .collect()
+}
+
+fn write_todo(todo: &[(Action, String)]) {
+    let content: String = todo
+        .iter()
+        .map(|(action, hash)| format!("{} {hash}\n", action.as_str()))
+        .collect();
+    fs::write(format!("{SEQUENCER_DIR}/todo"), content).expect("Failed to write sequencer todo");
+}
@@ -0,0 +1,100 @@
+use std::{
+    env,
+    io::{self, Write},
+    process::exit,
+};
+
+use lrngitcore::{
+    diff::{diff_trees, format_patch},
+    objects::{
+        blob::read_blob,
+        commit::{commit_parents, parse_commit_author},
+        tag::read_tag,
+        tree::{DIR, read_tree},
+        utils::{object_type, read_object},
+    },
+};
+
+use crate::{
+    log::print_commit, object::commit::parse_commit_by_hash, refs::resolve_revision,
+    utils::{format_date, DateFormat},
+};
+
+pub fn show_command() {
+    let args: Vec<String> = env::args().collect();
+    let revisions: Vec<&str> = if args.len() <= 2 {
+        vec!["HEAD"]
+    } else {
+        args[2..].iter().map(|x| x.as_str()).collect()
+    };
+    for revision in revisions {
+        show_object(revision);
+    }
+    exit(0);
+}
+
+/// Print a commit with its diff against its first parent, the entries of a tree or the content
+/// of a blob
+fn show_object(revision: &str) {
+    let hash = resolve_revision(revision);
+    let buffer = match read_object(&hash, ".lrngit") {
+        Ok(b) => b,
+        Err(e) => {
+            lrncore::logs::error_log(&format!("{e}"));
+            exit(128);
+        }
+    };
+    let result = match object_type(&buffer).as_str() {
+        "tag" => show_tag(&hash),
+        "commit" => show_commit(&hash),
+        "tree" => show_tree(revision, &hash),
+        _ => read_blob(&hash, ".lrngit").and_then(|x| Ok(io::stdout().write_all(&x)?)),
+    };
+    if let Err(e) = result {
+        lrncore::logs::error_log(&format!("Failed to show {revision}: {e}"));
+        exit(1);
+    }
+}
+
+/// Print the tag then the object it points to
+fn show_tag(hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tag = read_tag(hash, ".lrngit")?;
+    let tagger = parse_commit_author(tag.tagger);
+    println!("tag {}", String::from_utf8_lossy(&tag.tag));
+    println!(
+        "tagger: {} {}",
+        String::from_utf8_lossy(&tagger.name),
+        String::from_utf8_lossy(&tagger.email)
+    );
+    println!(
+        "date: {}",
+        format_date(tagger.timestamp, &tagger.timezone, DateFormat::Default)
+    );
+    println!("\n\t{}\n", String::from_utf8_lossy(&tag.message));
+    show_object(&hex::encode(tag.object));
+    Ok(())
+}
+
+fn show_commit(hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let commit = parse_commit_by_hash(hash);
+    print_commit(hash, &commit, DateFormat::Default, false);
+    let parent_tree = commit_parents(&commit)
+        .first()
+        .map(|x| hex::encode(parse_commit_by_hash(x).tree));
+    let tree = hex::encode(commit.tree);
+    let mut out = io::stdout().lock();
+    for change in diff_trees(".lrngit", parent_tree.as_deref(), Some(&tree))? {
+        out.write_all(format_patch(".lrngit", &change)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn show_tree(revision: &str, hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "tree {revision}\n")?;
+    for entry in read_tree(hash, ".lrngit")? {
+        let slash = if entry.mode == DIR { "/" } else { "" };
+        writeln!(out, "{}{slash}", String::from_utf8_lossy(&entry.name))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    env,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use lrngitcore::{
+    diff::{diff_trees, format_patch},
+    objects::commit::commit_parents,
+    refs::{delete_ref, read_ref, write_ref},
+    reflog::{read_reflog, write_reflog},
+};
+
+use crate::{
+    commit::{write_index_tree, write_tree},
+    fs::{checkout_blob, delete_path, update_workdir},
+    object::{
+        blob::{compute_file_hash_and_blob, write_blob},
+        commit::{parse_commit_by_hash, write_commit_object},
+        index::{self, add_index_entries, commit_files, index_entry_from_disk, parse_index},
+    },
+    refs::{log_ref_update, parse_current_branch, parse_head, resolve_commit_revision},
+    status::{self, FileStatusEntry},
+};
+
+const STASH_REF: &str = "refs/stash";
+
+/// A stash entry is a worktree commit whose parents are the commit the stash was made on, the
+/// index commit and, when made with -u, a root commit holding the untracked files
+struct Stash {
+    worktree: String,
+    untracked: Option<String>,
+    base: String,
+}
+
+pub fn stash_command() {
+    let args: Vec<String> = env::args().collect();
+    let subcommand = args.get(2).map(|x| x.as_str()).unwrap_or("push");
+    let options = args.get(3..).unwrap_or_default();
+    // Stash entry selected by the subcommands, `stash@{<n>}` or `<n>`
+    let selected = || stash_index(options.first().map(|x| x.as_str()));
+    match subcommand {
+        "push" => stash_push(options),
+        "list" => stash_list(),
+        "show" => {
+            let patch = options.iter().any(|x| x == "-p");
+            let n = stash_index(options.iter().find(|x| *x != "-p").map(|x| x.as_str()));
+            stash_show(n, patch);
+        }
+        "apply" => stash_apply(selected()),
+        "pop" => {
+            let n = selected();
+            stash_apply(n);
+            stash_drop(n);
+        }
+        "drop" => stash_drop(selected()),
+        _ => {
+            lrncore::logs::warning_log("Unknown command");
+            exit(1);
+        }
+    }
+    exit(0);
+}
+
+/// Save the local changes in a new stash entry and reset the working directory to HEAD
+fn stash_push(args: &[String]) {
+    let mut include_untracked = false;
+    let mut message: Option<&str> = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-u" => include_untracked = true,
+            "-m" => {
+                i += 1;
+                let Some(m) = args.get(i) else {
+                    lrncore::logs::error_log("-m expects a message");
+                    exit(1);
+                };
+                message = Some(m);
+            }
+            _ => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+        }
+        i += 1;
+    }
+    let head = parse_current_branch();
+    if head.is_empty() {
+        lrncore::logs::error_log("You do not have the initial commit yet");
+        exit(1);
+    }
+    let files_status = status::get_files_status();
+    let untracked: Vec<FileStatusEntry> = if include_untracked {
+        files_status.untracked
+    } else {
+        Vec::new()
+    };
+    if files_status.modified.is_empty()
+        && files_status.staged.is_empty()
+        && files_status.deleted.is_empty()
+        && untracked.is_empty()
+    {
+        println!("No local changes to save");
+        return;
+    }
+    let head_ref = parse_head();
+    let branch = head_ref.strip_prefix("refs/heads/").unwrap_or("(no branch)");
+    let head_commit = parse_commit_by_hash(&head);
+    let subject = String::from_utf8_lossy(&head_commit.message)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+    let description = format!("{branch}: {} {subject}", &head[..7]);
+    // Index commit, on top of HEAD
+    let index_commit = write_commit_object(
+        write_index_tree(),
+        &head,
+        &format!("index on {description}"),
+    );
+    let mut parents = vec![head.clone(), index_commit];
+    // Untracked files commit, without parent
+    if !untracked.is_empty() {
+        let files: Vec<(String, [u8; 20])> =
+            untracked.iter().map(|x| (x.file.clone(), store_file(&x.file))).collect();
+        parents.push(write_commit_object(
+            write_tree(files),
+            "",
+            &format!("untracked files on {description}"),
+        ));
+    }
+    // Worktree commit, the index with the content of the files modified or deleted on disk
+    let mut worktree_files: BTreeMap<String, [u8; 20]> = parse_index()
+        .entries
+        .into_iter()
+        .map(|x| (String::from_utf8_lossy(&x.path).to_string(), x.hash))
+        .collect();
+    for each in &files_status.modified {
+        worktree_files.insert(each.file.clone(), store_file(&each.file));
+    }
+    for each in &files_status.deleted {
+        worktree_files.remove(&each.file);
+    }
+    let stash_message = match message {
+        Some(m) => format!("On {branch}: {m}"),
+        None => format!("WIP on {description}"),
+    };
+    let worktree_commit = write_commit_object(
+        write_tree(worktree_files.into_iter().collect()),
+        &parents.join(" "),
+        &stash_message,
+    );
+    let old = read_ref(".lrngit", STASH_REF).ok().flatten().unwrap_or_default();
+    if let Err(e) = write_ref(".lrngit", STASH_REF, &worktree_commit) {
+        lrncore::logs::error_log(&format!("Failed to update {STASH_REF}: {e}"));
+        exit(1);
+    }
+    log_ref_update(STASH_REF, &old, &worktree_commit, &stash_message);
+    // Put the working directory and the index back to HEAD
+    update_workdir(index::build_temp_index(&worktree_commit, &head));
+    index::reset_index(&head);
+    for each in &untracked {
+        delete_path(&PathBuf::from(&each.file));
+    }
+    println!("Saved working directory and index state {stash_message}");
+}
+
+/// Write the blob of a file of the working directory and return its hash
+fn store_file(path: &str) -> [u8; 20] {
+    match compute_file_hash_and_blob(path) {
+        Ok(blob) => {
+            write_blob(&blob);
+            blob.hash
+        }
+        Err(_) => exit(1),
+    }
+}
+
+fn stash_list() {
+    let entries = read_reflog(".lrngit", STASH_REF).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(1);
+    });
+    for (i, entry) in entries.iter().enumerate() {
+        println!("stash@{{{i}}}: {}", entry.message);
+    }
+}
+
+/// Show the files changed by a stash entry compared to the commit it was made on
+fn stash_show(n: usize, patch: bool) {
+    let stash = read_stash(n);
+    let base_tree = hex::encode(parse_commit_by_hash(&stash.base).tree);
+    let stash_tree = hex::encode(parse_commit_by_hash(&stash.worktree).tree);
+    let changes = diff_trees(".lrngit", Some(&base_tree), Some(&stash_tree)).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(1);
+    });
+    for change in changes {
+        if patch {
+            match format_patch(".lrngit", &change) {
+                Ok(out) => print!("{out}"),
+                Err(e) => {
+                    lrncore::logs::error_log(&format!("{e}"));
+                    exit(1);
+                }
+            }
+            continue;
+        }
+        let status = match (change.old, change.new) {
+            (None, _) => "A",
+            (_, None) => "D",
+            _ => "M",
+        };
+        println!("{status}\t{}", change.path);
+    }
+}
+
+/// Apply the changes of a stash entry on the working directory. Files changed by the stash must
+/// be unchanged since the stash base, in HEAD and on disk, else nothing is applied.
+/// New files are added to the index, the staged state isn't restored.
+fn stash_apply(n: usize) {
+    let stash = read_stash(n);
+    let base_files = commit_files(&stash.base);
+    let stash_files = commit_files(&stash.worktree);
+    let head_files = commit_files(&parse_current_branch());
+    let files_status = status::get_files_status();
+    let local_changes: HashSet<String> = files_status
+        .modified
+        .into_iter()
+        .chain(files_status.staged)
+        .chain(files_status.deleted)
+        .map(|x| x.file)
+        .collect();
+    let paths: HashSet<&String> = base_files.keys().chain(stash_files.keys()).collect();
+    let mut to_write: Vec<(String, [u8; 20])> = Vec::new();
+    let mut to_delete: Vec<String> = Vec::new();
+    let mut conflicts: Vec<String> = Vec::new();
+    for path in paths {
+        let base = base_files.get(path);
+        let stashed = stash_files.get(path);
+        let current = head_files.get(path);
+        if base == stashed || current == stashed {
+            continue;
+        }
+        if current != base || local_changes.contains(path) {
+            conflicts.push(path.clone());
+            continue;
+        }
+        match stashed {
+            Some(hash) => to_write.push((path.clone(), *hash)),
+            None => to_delete.push(path.clone()),
+        }
+    }
+    let untracked_files = stash
+        .untracked
+        .as_ref()
+        .map(|x| commit_files(x))
+        .unwrap_or_default();
+    for path in untracked_files.keys() {
+        if Path::new(path).exists() {
+            conflicts.push(path.clone());
+        }
+    }
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        println!("error: Your local changes to the following files would be overwritten by stash apply:");
+        for each in conflicts {
+            println!("\t{each}");
+        }
+        println!("Please commit your changes or stash them before applying the stash.");
+        println!("Aborting");
+        exit(1);
+    }
+    for (path, hash) in &to_write {
+        checkout_blob(hash, path);
+    }
+    for (path, hash) in &untracked_files {
+        checkout_blob(hash, path);
+    }
+    // Files new in the stash are tracked again, deleted ones are removed from the index
+    add_index_entries(
+        to_write
+            .iter()
+            .filter(|(path, _)| !head_files.contains_key(path))
+            .map(|(path, hash)| index_entry_from_disk(Path::new(path), *hash))
+            .collect(),
+    );
+    for path in to_delete {
+        delete_path(&PathBuf::from(&path));
+        index::remove_index_entry(&path);
+    }
+}
+
+/// Remove a stash entry, the entries above it move down by one
+fn stash_drop(n: usize) {
+    let mut entries = read_reflog(".lrngit", STASH_REF).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("{e}"));
+        exit(1);
+    });
+    if n >= entries.len() {
+        lrncore::logs::error_log(&format!("stash@{{{n}}} is not a valid stash entry"));
+        exit(1);
+    }
+    let dropped = entries.remove(n);
+    let result = match entries.first() {
+        Some(newest) => write_ref(".lrngit", STASH_REF, &newest.new)
+            .and_then(|_| write_reflog(".lrngit", STASH_REF, &entries)),
+        None => delete_ref(".lrngit", STASH_REF),
+    };
+    if let Err(e) = result {
+        lrncore::logs::error_log(&format!("Failed to drop stash@{{{n}}}: {e}"));
+        exit(1);
+    }
+    println!("Dropped stash@{{{n}}} ({})", dropped.new);
+}
+
+/// Read the commits of the nth stash entry from the parents of its worktree commit
+fn read_stash(n: usize) -> Stash {
+    let worktree = resolve_commit_revision(&format!("{STASH_REF}@{{{n}}}"));
+    let mut parents = commit_parents(&parse_commit_by_hash(&worktree)).into_iter();
+    let (Some(base), Some(_index)) = (parents.next(), parents.next()) else {
+        lrncore::logs::error_log(&format!("stash@{{{n}}} is not a valid stash entry"));
+        exit(1);
+    };
+    Stash {
+        worktree,
+        untracked: parents.next(),
+        base,
+    }
+}
+
+/// Index of the stash entry given as `stash@{<n>}` or `<n>`, the newest one by default
+fn stash_index(arg: Option<&str>) -> usize {
+    let Some(arg) = arg else {
+        return 0;
+    };
+    let n = arg
+        .strip_prefix("stash@{")
+        .and_then(|x| x.strip_suffix('}'))
+        .unwrap_or(arg);
+    n.parse().unwrap_or_else(|_| {
+        lrncore::logs::error_log(&format!("'{arg}' is not a stash reference"));
+        exit(1);
+    })
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Write},
+    os::unix::{fs::MetadataExt, net::UnixStream},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fs::walkdir, status::WorkdirFile, utils::parallel_map};
+
+// Socket of the lrngit-fsmonitor process watching the repository
+const SOCKET_PATH: &str = ".lrngit/fsmonitor.sock";
+// Files seen on the last status and the token they are valid for
+const CACHE_PATH: &str = ".lrngit/fsmonitor-cache";
+// Prefix of the cookie file created in `.lrngit` for each query
+const COOKIE_PREFIX: &str = "fsmonitor-cookie-";
+
+#[derive(Serialize, Deserialize)]
+struct FsmonitorCache {
+    token: String,
+    files: Vec<WorkdirFile>,
+}
+
+/// Get all the files of the working directory with their metadata.
+/// When a filesystem monitor is running, only the paths changed since the last call are examined
+/// and the rest comes from the cache, otherwise the whole working directory is walked.
+pub fn workdir_files(workdir: &Path) -> Vec<WorkdirFile> {
+    let cache = load_cache();
+    let token = cache.as_ref().map(|x| x.token.as_str()).unwrap_or_default();
+    let Some((new_token, dirty)) = query_monitor(token) else {
+        // Without monitor the cache would silently get stale
+        let _ = fs::remove_file(CACHE_PATH);
+        return full_walk(workdir);
+    };
+    let files = match (cache, dirty) {
+        (Some(cache), Some(dirty)) => apply_dirty_paths(cache.files, dirty, workdir),
+        _ => full_walk(workdir),
+    };
+    save_cache(&FsmonitorCache {
+        token: new_token,
+        files: files.clone(),
+    });
+    files
+}
+
+/// Ask the monitor for paths changed since the given token.
+/// Return None if no monitor is running, else the new token and the changed paths, or None in
+/// place of the paths if the monitor can't tell and a full walk is needed.
+fn query_monitor(token: &str) -> Option<(String, Option<Vec<String>>)> {
+    let mut stream = UnixStream::connect(SOCKET_PATH).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    // The monitor answers once it has seen the cookie, so the changes made before it are counted
+    let cookie = format!("{COOKIE_PREFIX}{}", std::process::id());
+    let cookie_path = format!(".lrngit/{cookie}");
+    File::create(&cookie_path).ok()?;
+    let mut response = String::new();
+    let sent = stream
+        .write_all(format!("{token} {cookie}\n").as_bytes())
+        .and_then(|_| stream.read_to_string(&mut response));
+    let _ = fs::remove_file(cookie_path);
+    sent.ok()?;
+    let mut lines = response.lines();
+    let new_token = lines.next()?.to_owned();
+    match lines.next()? {
+        "FULL" => Some((new_token, None)),
+        "DIRTY" => Some((new_token, Some(lines.map(|x| x.to_owned()).collect()))),
+        _ => None,
+    }
+}
+
+/// Walk the whole working directory and get metadata of each file
+fn full_walk(workdir: &Path) -> Vec<WorkdirFile> {
+    let mut file_vec: Vec<PathBuf> = Vec::new();
+    let _ = walkdir(&workdir.to_path_buf(), &mut file_vec);
+    parallel_map(&file_vec, |each| workdir_file(workdir, each))
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Update the cached files with the paths changed on disk
+fn apply_dirty_paths(
+    cached: Vec<WorkdirFile>,
+    dirty: Vec<String>,
+    workdir: &Path,
+) -> Vec<WorkdirFile> {
+    let mut files: HashMap<String, WorkdirFile> =
+        cached.into_iter().map(|x| (x.path.clone(), x)).collect();
+    for path in dirty {
+        // The path may be a deleted or replaced directory, drop everything under it
+        let dir_prefix = path.clone() + "/";
+        files.retain(|k, _| *k != path && !k.starts_with(&dir_prefix));
+        let absolute = workdir.join(&path);
+        if absolute.is_dir() {
+            let mut file_vec: Vec<PathBuf> = Vec::new();
+            let _ = walkdir(&absolute, &mut file_vec);
+            for each in file_vec {
+                if let Some(file) = workdir_file(workdir, &each) {
+                    files.insert(file.path.clone(), file);
+                }
+            }
+        } else if let Some(file) = workdir_file(workdir, &absolute) {
+            files.insert(file.path.clone(), file);
+        }
+    }
+    let mut files: Vec<WorkdirFile> = files.into_values().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+fn workdir_file(workdir: &Path, path: &Path) -> Option<WorkdirFile> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    let relative = path.strip_prefix(workdir).unwrap_or(path);
+    Some(WorkdirFile {
+        path: relative.to_str()?.to_owned(),
+        mtime: metadata.mtime() as u32,
+        file_size: metadata.len() as u32,
+    })
+}
+
+fn load_cache() -> Option<FsmonitorCache> {
+    let mut file = File::open(CACHE_PATH).ok()?;
+    let mut buff: Vec<u8> = Vec::new();
+    file.read_to_end(&mut buff).ok()?;
+    bincode::deserialize(&buff).ok()
+}
+
+fn save_cache(cache: &FsmonitorCache) {
+    let buff = bincode::serialize(cache).expect("Failed to serialize fsmonitor cache");
+    if let Err(e) = fs::write(CACHE_PATH, buff) {
+        lrncore::logs::warning_log(&format!("Failed to write fsmonitor cache: {e}"));
+    }
+}
@@ -1,15 +1,22 @@
 use std::{
+    collections::HashMap,
     env::{self, current_dir},
-    fs, io,
     path::{Path, PathBuf},
     process::exit,
 };
 
+mod fsmonitor;
 mod helper;
 use helper::sort_file_status_vec;
 use lrngitcore::objects::index::IndexEntry;
+use serde::{Deserialize, Serialize};
 
-use crate::{object::utils::check_modified_file, vec_of_path};
+use crate::{
+    branch::upstream_status,
+    object::{blob::compute_file_hash_and_blob, commit::parse_commit_by_hash, utils::walk_root_tree_content},
+    refs::{parse_current_branch, parse_head},
+    utils::parallel_map,
+};
 
 use crate::object::index;
 
@@ -41,6 +48,14 @@ pub struct FileStatusEntry {
     pub status: FileStatus,
 }
 
+/// File found in the working directory, path relative to the workdir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkdirFile {
+    pub path: String,
+    pub mtime: u32,
+    pub file_size: u32,
+}
+
 pub fn status_command() {
     let args: Vec<String> = env::args().collect();
     if args.len() <= 2 {
@@ -60,21 +75,18 @@ pub fn status_command() {
 
 pub fn get_files_status() -> FileStatusSort {
     let index = index::parse_index();
-    let index_entries = index.entries;
     let workdir = current_dir().expect("Failed to get the current working directory");
-    // Vec containing all files path
-    let mut file_vec: Vec<PathBuf> = Vec::new();
-    // Fill the file_vec with all files path inside the repository
-    let _ = walkdir(&workdir, &mut file_vec);
+    // All files inside the repository, only changed paths are examined if a monitor is running
+    let file_vec = fsmonitor::workdir_files(&workdir);
     // Vector containing all files with their status
-    let status = check_file_status(index_entries, file_vec.to_owned(), &workdir);
+    let status = check_file_status(index.entries, file_vec, &head_tree_content());
     // Sort all file path by status
     sort_file_status_vec(status.entries)
 }
 
 // print the repository status, files tracked, untracked and modified
 fn workdir_status() {
-    let workdir = current_dir().expect("Failed to get the current working directory");
+    branch_status();
     let sort_files_status = get_files_status();
     println!("Changes to be committed:");
     for each in sort_files_status.staged {
@@ -84,12 +96,7 @@ fn workdir_status() {
     println!("  (use 'git add <file>...' to update what will be committed)");
     println!("  (use 'git restore <file>...' to discard changes in working directory)");
     for each in sort_files_status.untracked {
-        let split: Vec<&str> = each
-            .file
-            .split(&(workdir.to_str().unwrap().to_owned() + "/"))
-            .collect();
-
-        println!("\t{}", split[1]);
+        println!("\t{}", each.file);
     }
     println!("\nChanges not staged for commit:");
     for each in sort_files_status.modified {
@@ -100,91 +107,140 @@ fn workdir_status() {
     }
 }
 
-/// Recursive function to get all files in current workdir
-///
-/// # Errors
-///
-/// This function will return an error if the function cannot access a directory.
-fn walkdir(workdir: &PathBuf, file_vec: &mut Vec<PathBuf>) -> io::Result<()> {
-    let avoid_path_sufx: Vec<&Path> = vec_of_path!(".lrngit", ".git", "target");
-    if workdir.is_dir() {
-        for entry in fs::read_dir(workdir)? {
-            let entry = entry?;
-            let path = entry.path();
-            // avoid all unwanted path
-            if !avoid_path_sufx
-                .iter()
-                .any(|&suffix| entry.file_name() == suffix)
-            {
-                if path.is_dir() {
-                    if let Err(e) = walkdir(&path, file_vec) {
-                        eprintln!("Error walking directory {path:?}: {e}");
-                    }
-                } else if path.is_file() {
-                    file_vec.push(path);
-                }
+/// Print the current branch and how far it is from its upstream
+fn branch_status() {
+    let head = parse_head();
+    let last_commit = parse_current_branch();
+    let Some(branch_name) = head.strip_prefix("refs/heads/") else {
+        println!("HEAD detached at {}\n", &last_commit[..7.min(last_commit.len())]);
+        return;
+    };
+    println!("On branch {branch_name}");
+    if let Some(upstream) = upstream_status(&head, &last_commit) {
+        let name = &upstream.name;
+        match upstream.counts {
+            None => {
+                println!("Your branch is based on '{name}', but the upstream is gone.");
+                println!("  (use \"lrngit branch --unset-upstream\" to fixup)");
+            }
+            Some((0, 0)) => println!("Your branch is up to date with '{name}'."),
+            Some((ahead, 0)) => {
+                println!("Your branch is ahead of '{name}' by {}.", commits(ahead));
+                println!("  (use \"lrngit push\" to publish your local commits)");
+            }
+            Some((0, behind)) => {
+                println!(
+                    "Your branch is behind '{name}' by {}, and can be fast-forwarded.",
+                    commits(behind)
+                );
+                println!("  (use \"lrngit pull\" to update your local branch)");
+            }
+            Some((ahead, behind)) => {
+                println!("Your branch and '{name}' have diverged,");
+                println!("and have {ahead} and {behind} different commits each, respectively.");
             }
         }
     }
+    println!();
+}
+
+fn commits(count: usize) -> String {
+    match count {
+        1 => String::from("1 commit"),
+        n => format!("{n} commits"),
+    }
+}
 
-    Ok(())
+/// Map every blob path of the last commit to its hash. Empty when the current branch has no
+/// commit yet.
+fn head_tree_content() -> HashMap<PathBuf, [u8; 20]> {
+    let last_commit = parse_current_branch();
+    if last_commit.is_empty() {
+        return HashMap::new();
+    }
+    let parse_commit = parse_commit_by_hash(&last_commit);
+    let mut content: Vec<(PathBuf, [u8; 20])> = Vec::new();
+    walk_root_tree_content(&hex::encode(parse_commit.tree), &mut PathBuf::new(), &mut content);
+    content.into_iter().collect()
 }
 
 /// Create a RepositoryStatus struct containing all files inside the repository with their status.
+/// The index is matched against the files on disk through a hash map, only files whose metadata
+/// differ from their index entry are hashed, and the hashing is spread across worker threads.
+///
 /// Params:
 /// Vec<IndexEntry> Containing all entries of the index file
-/// Vec<PathBuf> Containing all files inside the repository
-/// &Path Path reference of the current workdir
+/// Vec<WorkdirFile> Containing all files inside the repository
+/// &HashMap Content of the last commit tree, path to blob hash
 fn check_file_status(
     index_entries: Vec<IndexEntry>,
-    mut files: Vec<PathBuf>,
-    workdir: &Path,
+    files: Vec<WorkdirFile>,
+    head_tree: &HashMap<PathBuf, [u8; 20]>,
 ) -> RepositoryStatus {
     let mut files_status_vec: Vec<FileStatusEntry> = Vec::new();
-    let mut found_index_entries_vec: Vec<IndexEntry> = Vec::new();
-    // check if file tracked
-    for entries in &index_entries {
-        let entry_path_str = str::from_utf8(&entries.path).expect("Failed to parse buffer to str");
-        let mut i = 0;
-        while i < files.len() {
-            let workdir_owned = workdir.to_str().unwrap();
-            let files_path_concat = workdir_owned.to_owned() + "/" + entry_path_str;
-            if files_path_concat == *files[i].to_str().unwrap() {
-                found_index_entries_vec.push(entries.clone());
-                let file_status: FileStatusEntry = check_modified_file(entry_path_str);
-                files_status_vec.push(file_status);
-                files.remove(i);
-            } else {
-                i += 1;
-            }
+    let mut index_map: HashMap<String, IndexEntry> = index_entries
+        .into_iter()
+        .map(|x| (String::from_utf8_lossy(&x.path).to_string(), x))
+        .collect();
+    // Tracked files whose mtime or size differ from the index, need to be hashed
+    let mut candidates: Vec<(String, IndexEntry)> = Vec::new();
+    for each in files {
+        let path = each.path;
+        let Some(entry) = index_map.remove(&path) else {
+            // All files not tracked is untracked
+            files_status_vec.push(FileStatusEntry {
+                file: path,
+                status: FileStatus::Untracked,
+            });
+            continue;
+        };
+        if each.mtime != entry.mtime || each.file_size != entry.file_size {
+            candidates.push((path, entry));
+        } else {
+            files_status_vec.push(staged_or_tracked(path, &entry, head_tree));
         }
     }
-    // Check differences between found file in index and disk
-    let mut difference = vec![];
-    for i in index_entries {
-        if !found_index_entries_vec.contains(&i) {
-            difference.push(i);
+    // Hash candidates across the worker pool, a file only counts as modified if its content
+    // really differ from the one recorded in the index
+    let disk_hashes = parallel_map(&candidates, |(path, _)| {
+        compute_file_hash_and_blob(path).map(|x| x.hash).ok()
+    });
+    for ((path, entry), disk_hash) in candidates.into_iter().zip(disk_hashes) {
+        if disk_hash == Some(entry.hash) {
+            files_status_vec.push(staged_or_tracked(path, &entry, head_tree));
+        } else {
+            files_status_vec.push(FileStatusEntry {
+                file: path,
+                status: FileStatus::Modify,
+            });
         }
     }
-    // For each differences, create a new file_status for a deleted file
-    for each in difference {
-        let file_status = FileStatusEntry {
-            file: str::from_utf8(&each.path).unwrap().to_owned(),
+    // Index entries left in the map were not found on disk
+    for (path, _) in index_map {
+        files_status_vec.push(FileStatusEntry {
+            file: path,
             status: FileStatus::Deleted,
-        };
-        files_status_vec.push(file_status);
-    }
-    // All files not tracked is untracked
-    for each in files {
-        let file_status: FileStatusEntry = FileStatusEntry {
-            file: each.to_str().unwrap().to_owned(),
-            status: FileStatus::Untracked,
-        };
-        files_status_vec.push(file_status);
+        });
     }
+    files_status_vec.sort_by(|a, b| a.file.cmp(&b.file));
 
     let repo_status: RepositoryStatus = RepositoryStatus {
         entries: files_status_vec,
     };
     repo_status
 }
+
+/// Check if an unmodified file is staged by comparing the index hash with the one from the last
+/// commit
+fn staged_or_tracked(
+    path: String,
+    entry: &IndexEntry,
+    head_tree: &HashMap<PathBuf, [u8; 20]>,
+) -> FileStatusEntry {
+    let status = if head_tree.get(Path::new(&path)) != Some(&entry.hash) {
+        FileStatus::Staged
+    } else {
+        FileStatus::Tracked
+    };
+    FileStatusEntry { file: path, status }
+}
@@ -1,13 +1,17 @@
 use std::{
+    collections::HashSet,
     env,
-    fs::{self, OpenOptions},
-    io::Write,
+    fs,
+    path::Path,
     process::exit,
 };
 
 use lrncore::logs::error_log;
+use lrngitcore::{objects::index::TempIndex, revparse};
 
-use crate::{fs::update_workdir, object::index::{self, parse_index, rebuild_index}};
+use crate::branch::{check_new_branch, write_new_branch};
+use crate::{fs::update_workdir, object::index::{self, apply_temp_index}};
+use crate::refs::{log_ref_update, parse_current_branch, parse_head, resolve_commit_revision};
 use crate::status;
 
 pub fn switch_command() {
@@ -16,55 +20,160 @@ pub fn switch_command() {
         eprintln!("Enter a branch name");
         exit(0);
     }
-    if args.len() == 3 {
-        switch_ref(&args[2]);
-        exit(0);
-    }
     match args[2].as_str() {
-        "" => {}
-        _ => {
+        "-c" => {
+            let Some(branch_name) = args.get(3) else {
+                lrncore::usage_exit::usage_and_exit(
+                    "Invalid command",
+                    "lrngit switch -c <new-branch> [<start-point>]",
+                );
+                exit(1);
+            };
+            let start_point = args.get(4).map(|x| x.as_str());
+            check_new_branch(branch_name);
+            let (old_branch, old_commit) = (parse_head(), parse_current_branch());
+            let target = match start_point {
+                Some(rev) => resolve_commit_revision(rev),
+                None => old_commit.clone(),
+            };
+            // The branch is created once the working directory is updated, so a switch
+            // refused for local changes or untracked files doesn't leave it behind. On an
+            // unborn branch the new branch is unborn too, only HEAD moves.
+            if !target.is_empty() {
+                checkout_tree(&old_commit, &target);
+                write_new_branch(branch_name, &target, start_point.unwrap_or("HEAD"));
+            }
+            move_head(
+                &old_branch,
+                &old_commit,
+                &target,
+                &format!("ref: refs/heads/{branch_name}"),
+                branch_name,
+            );
+        }
+        "--detach" => {
+            let Some(rev) = args.get(3) else {
+                lrncore::usage_exit::usage_and_exit("Invalid command", "lrngit switch --detach <rev>");
+                exit(1);
+            };
+            detach_head(rev);
+        }
+        arg if arg.starts_with('-') => {
             lrncore::logs::warning_log("Unknown command");
             exit(1);
         }
+        branch_name => switch_ref(branch_name),
     }
+    exit(0);
 }
 
 /// Switch current head to specified branch
 fn switch_ref(branch_name: &str) {
-    // Check modified and staged files that are not commit yet
-    let files_status = status::get_files_status();
-    if !files_status.modified.is_empty() || !files_status.staged.is_empty() {
-        println!(
-            "error: Your local changes to the following files would be overwritten by checkout:"
-        );
-        for each in files_status.modified {
-            println!("\t{}", each.file);
-        }
-        for each in files_status.staged {
-            println!("\t{}", each.file);
+    if !Path::new(&format!(".lrngit/refs/heads/{branch_name}")).is_file() {
+        // Give a hint when a commit was given in place of a branch
+        if let Ok(hash) = revparse::resolve_commit(".lrngit", branch_name) {
+            error_log(&format!("a branch is expected, got commit {hash}"));
+            println!("To check out the commit without a branch, use 'lrngit switch --detach {branch_name}'");
+        } else {
+            error_log("Branch does not exist");
         }
-        println!("Please commit your changes or stash them before you switch branches.");
-        println!("Aborting");
-        exit(1);
-    }
-    if !fs::exists(format!(".lrngit/refs/heads/{branch_name}")).unwrap() {
-        error_log("Branch does not exist");
         exit(1)
     }
-    let mut head = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(".lrngit/HEAD")
-        .expect("Unable to open file");
-    let current_index = parse_index();
-    let update_head = format!("ref: refs/heads/{branch_name}");
-    head.write_all(update_head.as_bytes())
-        .expect("Failed to write buffer in HEAD");
-    let temp_index = index::build_temp_index(current_index);
+    let target = fs::read_to_string(format!(".lrngit/refs/heads/{branch_name}"))
+        .expect("Failed to read branch file");
+    checkout(&target, &format!("ref: refs/heads/{branch_name}"), branch_name);
+}
+
+/// Detach HEAD on the given commit, HEAD holds the commit hash instead of a branch
+fn detach_head(rev: &str) {
+    let target = resolve_commit_revision(rev);
+    checkout(&target, &target, &target);
+}
+
+/// Update the working directory and the index from the current commit to the target one, then
+/// write HEAD. Local changes are carried over when the files aren't different between the two
+/// commits, else nothing is touched.
+fn checkout(target: &str, head_content: &str, destination: &str) {
+    let (old_branch, old_commit) = (parse_head(), parse_current_branch());
+    checkout_tree(&old_commit, target);
+    move_head(&old_branch, &old_commit, target, head_content, destination);
+}
+
+/// Update the working directory and the index from a commit to another, exit before touching
+/// anything if local changes or untracked files are in the way
+fn checkout_tree(old_commit: &str, target: &str) {
+    let temp_index = index::build_temp_index(old_commit, target);
+    check_local_changes(&temp_index);
     // Update the working directory on the disk
-    update_workdir(temp_index.clone());    
-    // Update the index file to match the HEAD
-    rebuild_index(temp_index.temp_index);
+    update_workdir(temp_index.clone());
+    // Update the index file to match the new HEAD
+    apply_temp_index(&temp_index);
 }
 
+/// Write HEAD and log the move from the previous branch, or commit when detached. Nothing is
+/// logged when HEAD moves to an unborn branch, there's no commit to record.
+fn move_head(
+    old_branch: &str,
+    old_commit: &str,
+    target: &str,
+    head_content: &str,
+    destination: &str,
+) {
+    if let Err(e) = fs::write(".lrngit/HEAD", head_content) {
+        error_log(&format!("Failed to write HEAD: {e}"));
+        exit(1);
+    }
+    if target.is_empty() {
+        return;
+    }
+    let from = match old_branch.strip_prefix("refs/heads/") {
+        Some(name) => name,
+        None => old_commit,
+    };
+    log_ref_update(
+        "HEAD",
+        old_commit,
+        target,
+        &format!("checkout: moving from {from} to {destination}"),
+    );
+}
+
+/// Abort if a file with local changes, staged or not, is different on the target commit
+fn check_local_changes(temp_index: &TempIndex) {
+    let touched: HashSet<String> = temp_index
+        .new_files
+        .iter()
+        .map(|x| x.0.to_string_lossy().to_string())
+        .chain(
+            temp_index
+                .changed_files
+                .iter()
+                .map(|x| String::from_utf8_lossy(&x.path).to_string()),
+        )
+        .chain(
+            temp_index
+                .to_delete_files
+                .iter()
+                .map(|x| x.to_string_lossy().to_string()),
+        )
+        .collect();
+    let files_status = status::get_files_status();
+    let conflicts: Vec<String> = files_status
+        .modified
+        .into_iter()
+        .chain(files_status.staged)
+        .chain(files_status.deleted)
+        .map(|x| x.file)
+        .filter(|x| touched.contains(x))
+        .collect();
+    if conflicts.is_empty() {
+        return;
+    }
+    println!("error: Your local changes to the following files would be overwritten by checkout:");
+    for each in conflicts {
+        println!("\t{each}");
+    }
+    println!("Please commit your changes or stash them before you switch branches.");
+    println!("Aborting");
+    exit(1);
+}
@@ -0,0 +1,195 @@
+use std::{env, process::exit};
+
+use lrngitcore::{
+    objects::utils::{object_type, read_object},
+    refs::{delete_ref, is_valid_ref_name, list_refs, read_ref, write_ref},
+};
+
+use crate::{object::tag::write_tag_object, refs::resolve_revision};
+
+/// Options of the tag command
+#[derive(Default)]
+struct TagOptions<'a> {
+    annotate: bool,
+    force: bool,
+    delete: bool,
+    list: bool,
+    message: Option<&'a str>,
+    positional: Vec<&'a str>,
+}
+
+pub fn tag_command() {
+    let args: Vec<String> = env::args().collect();
+    let options = parse_tag_args(&args[2..]);
+    if options.delete && options.list {
+        lrncore::logs::error_log("-d and -l are incompatible");
+        exit(1);
+    }
+    if options.delete {
+        if options.positional.is_empty() {
+            lrncore::usage_exit::usage_and_exit("Invalid command", "lrngit tag -d <name>...");
+        }
+        for name in &options.positional {
+            delete_tag(name);
+        }
+    } else if options.list || options.positional.is_empty() {
+        list_tags(&options.positional);
+    } else {
+        create_tag(&options);
+    }
+    exit(0);
+}
+
+fn parse_tag_args(args: &[String]) -> TagOptions<'_> {
+    let mut options = TagOptions::default();
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-a" => options.annotate = true,
+            "-f" => options.force = true,
+            "-d" => options.delete = true,
+            "-l" => options.list = true,
+            "-m" => {
+                i += 1;
+                let Some(message) = args.get(i) else {
+                    lrncore::logs::error_log("-m expects a message");
+                    exit(1);
+                };
+                options.message = Some(message);
+                // A message always creates an annotated tag
+                options.annotate = true;
+            }
+            arg if arg.starts_with('-') => {
+                lrncore::logs::warning_log("Unknown command");
+                exit(1);
+            }
+            arg => options.positional.push(arg),
+        }
+        i += 1;
+    }
+    options
+}
+
+/// Create a lightweight tag, a ref to the object, or an annotated tag, a ref to a tag object
+fn create_tag(options: &TagOptions) {
+    let name = options.positional[0];
+    let refname = tag_ref(name);
+    let target = resolve_revision(options.positional.get(1).copied().unwrap_or("HEAD"));
+    if !options.force && read_ref(".lrngit", &refname).ok().flatten().is_some() {
+        lrncore::logs::error_log(&format!("tag '{name}' already exists"));
+        exit(128);
+    }
+    let hash = if options.annotate {
+        let Some(message) = options.message else {
+            lrncore::logs::error_log("an annotated tag needs a message, use -m <message>");
+            exit(128);
+        };
+        let target_type = match read_object(&target, ".lrngit") {
+            Ok(buffer) => object_type(&buffer),
+            Err(e) => {
+                lrncore::logs::error_log(&format!("{e}"));
+                exit(128);
+            }
+        };
+        write_tag_object(&target, &target_type, name, message)
+    } else {
+        target
+    };
+    if let Err(e) = write_ref(".lrngit", &refname, &hash) {
+        lrncore::logs::error_log(&format!("Failed to create tag '{name}': {e}"));
+        exit(1);
+    }
+}
+
+fn delete_tag(name: &str) {
+    let refname = tag_ref(name);
+    let Ok(Some(hash)) = read_ref(".lrngit", &refname) else {
+        lrncore::logs::error_log(&format!("tag '{name}' not found"));
+        exit(1);
+    };
+    if let Err(e) = delete_ref(".lrngit", &refname) {
+        lrncore::logs::error_log(&format!("Failed to delete tag '{name}': {e}"));
+        exit(1);
+    }
+    println!("Deleted tag '{name}' (was {})", &hash[..7]);
+}
+
+/// List the tags, only the ones matching one of the patterns if any are given
+fn list_tags(patterns: &[&str]) {
+    let tags = list_refs(".lrngit", "refs/tags").unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to list tags: {e}"));
+        exit(1);
+    });
+    for (refname, _) in tags {
+        let name = refname.trim_start_matches("refs/tags/");
+        if patterns.is_empty() || patterns.iter().any(|x| glob_match(x, name)) {
+            println!("{name}");
+        }
+    }
+}
+
+/// Match a name against a shell pattern, `*` matches any characters and `?` a single one
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it matched up to, to backtrack
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
+fn tag_ref(name: &str) -> String {
+    let refname = format!("refs/tags/{name}");
+    if !is_valid_ref_name(&refname) {
+        lrncore::logs::error_log(&format!("'{name}' is not a valid tag name"));
+        exit(128);
+    }
+    refname
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("v1", "v1"));
+        assert!(!glob_match("v1", "v10"));
+        assert!(glob_match("v1*", "v1"));
+        assert!(glob_match("v1*", "v1.2.3"));
+        assert!(glob_match("*-rc?", "v2.0-rc1"));
+        assert!(!glob_match("*-rc?", "v2.0-rc12"));
+        assert!(glob_match("v*.*.0", "v1.2.0"));
+        assert!(!glob_match("v*.*.0", "v1.2.1"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn list_option_keeps_patterns() {
+        let args: Vec<String> = ["-l", "v1*", "v2"].iter().map(|x| x.to_string()).collect();
+        let options = parse_tag_args(&args);
+        assert!(options.list);
+        assert_eq!(options.positional, ["v1*", "v2"]);
+    }
+}
@@ -1,16 +1,16 @@
 use std::{io::Write, net::TcpStream, process::exit};
 
-use lrngitcore::remote::parse_local_config_url;
+use lrngitcore::remote::{Remote, parse_local_config_url};
 
-use crate::config::parse_local_config;
-
-/// Connect to remote host and return stream
-pub fn tcp_connect_to_remote(service: &str) -> TcpStream {
+/// Connect to the host of a remote and return stream
+pub fn tcp_connect_to_remote(service: &str, remote: &Remote) -> TcpStream {
     if service != "lrngit-receive-pack" && service != "lrngit-upload-pack" {
         exit(1)
     }
-    let local_config = parse_local_config();
-    let url = parse_local_config_url(&local_config.remotes.url);
+    let url = parse_local_config_url(&remote.url).unwrap_or_else(|e| {
+        lrncore::logs::error_log(&format!("Failed to read the url of remote {}: {e}", remote.name));
+        exit(1);
+    });
     let mut stream = TcpStream::connect(url.url).expect("Failed to connect to remote server");
     let service_string = format!("{} {}", service, url.path);
     let service_bytes: &[u8] = service_string.as_bytes();
@@ -1,23 +1,216 @@
-use std::
-    env
-;
+use std::{
+    env,
+    process::{Command, exit},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
 
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, Utc};
 
 pub fn change_wkdir(dir: &str) {
     env::set_current_dir(dir).expect("Failed to change directory");
 }
 
-// convert a timestamp to readable datetime
-pub fn timestamp_to_datetime(timestamp: i64) -> String {
-    // Create a NaiveDateTime from the timestamp
-    #[allow(deprecated)]
-    let naive = NaiveDateTime::from_timestamp(timestamp, 0);
+/// How dates are rendered by log and show
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `2024-01-02 03:04:05 +0200`, in the offset recorded with the date
+    #[default]
+    Default,
+    /// `2024-01-02T03:04:05+02:00`
+    Iso,
+    /// `3 days ago`
+    Relative,
+    /// Seconds since the epoch
+    Unix,
+}
 
-    // Create a normal DateTime from the NaiveDateTime
-    let datetime: DateTime<Utc> = DateTime::from_naive_utc_and_offset(naive, Utc);
+impl DateFormat {
+    /// Parse the value of `--date=<format>`
+    pub fn parse(format: &str) -> Option<DateFormat> {
+        match format {
+            "default" => Some(DateFormat::Default),
+            "iso" => Some(DateFormat::Iso),
+            "relative" => Some(DateFormat::Relative),
+            "unix" => Some(DateFormat::Unix),
+            _ => None,
+        }
+    }
+}
 
-    // Format the datetime how you want
-    datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+/// Render a timestamp in the timezone it was recorded with, like `+0200`. An unreadable
+/// timezone falls back to UTC.
+pub fn format_date(timestamp: i64, timezone: &[u8], format: DateFormat) -> String {
+    let timezone = String::from_utf8_lossy(timezone);
+    let offset = parse_timezone(&timezone).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    let datetime = match DateTime::from_timestamp(timestamp, 0) {
+        Some(d) => d.with_timezone(&offset),
+        None => return timestamp.to_string(),
+    };
+    match format {
+        DateFormat::Default => datetime.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::Iso => datetime.to_rfc3339(),
+        DateFormat::Relative => relative_date(Utc::now().timestamp() - timestamp),
+        DateFormat::Unix => timestamp.to_string(),
+    }
 }
 
+/// Describe an elapsed number of seconds like `5 minutes ago`
+fn relative_date(elapsed: i64) -> String {
+    let plural = |n: i64, unit: &str| {
+        let s = if n == 1 { "" } else { "s" };
+        format!("{n} {unit}{s} ago")
+    };
+    match elapsed {
+        ..0 => "in the future".to_owned(),
+        0..90 => plural(elapsed, "second"),
+        90..5400 => plural((elapsed + 30) / 60, "minute"),
+        5400..129_600 => plural((elapsed + 1800) / 3600, "hour"),
+        129_600..1_209_600 => plural((elapsed + 43_200) / 86_400, "day"),
+        1_209_600..5_184_000 => plural((elapsed + 302_400) / 604_800, "week"),
+        5_184_000..31_536_000 => plural((elapsed + 1_296_000) / 2_592_000, "month"),
+        _ => plural(elapsed / 31_536_000, "year"),
+    }
+}
+
+/// Parse a timezone offset like `+0200` or `-0530`
+pub fn parse_timezone(timezone: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match timezone.as_bytes().first()? {
+        b'+' => (1, &timezone[1..]),
+        b'-' => (-1, &timezone[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|x| x.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parse the date of an identity to a timestamp and a timezone like `+0200`. Accept
+/// `<timestamp> [<timezone>]` with an optional `@`, RFC 3339, RFC 2822 and
+/// `YYYY-MM-DD[ HH:MM:SS][ <timezone>]`. Dates without a timezone are in the local one.
+pub fn parse_identity_date(date: &str) -> Option<(i64, String)> {
+    let date = date.trim();
+    let (raw, timezone) = match date.rsplit_once(' ') {
+        Some((raw, tz)) if parse_timezone(tz).is_some() => (raw, Some(tz)),
+        _ => (date, None),
+    };
+    if let Ok(timestamp) = raw.strip_prefix('@').unwrap_or(raw).parse::<i64>() {
+        let timezone = timezone.map(|x| x.to_owned()).unwrap_or_else(local_timezone);
+        return Some((timestamp, timezone));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date).or_else(|_| DateTime::parse_from_rfc2822(date)) {
+        return Some((datetime.timestamp(), format_offset(datetime.offset().local_minus_utc())));
+    }
+    let naive = NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(raw, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+    match timezone {
+        Some(tz) => {
+            let offset = parse_timezone(tz)?;
+            let datetime = naive.and_local_timezone(offset).single()?;
+            Some((datetime.timestamp(), tz.to_owned()))
+        }
+        None => {
+            let datetime = naive.and_local_timezone(Local).earliest()?;
+            Some((datetime.timestamp(), format_offset(datetime.offset().local_minus_utc())))
+        }
+    }
+}
+
+/// Offset of the local timezone formatted like `+0200`
+pub fn local_timezone() -> String {
+    format_offset(Local::now().offset().fix().local_minus_utc())
+}
+
+/// Format an offset in seconds from UTC like `+0200`
+fn format_offset(offset: i32) -> String {
+    let sign = if offset >= 0 { "+" } else { "-" };
+    let hours = offset.abs() / 3600;
+    let minutes = (offset.abs() % 3600) / 60;
+    format!("{sign}{hours:02}{minutes:02}")
+}
+
+/// Apply `f` to every item across a pool of worker threads and return the results in the same
+/// order as `items`. Workers pull the next item from a shared counter so a few large files don't
+/// leave the other threads idle.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done: Vec<(usize, R)> = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            break;
+                        }
+                        done.push((i, f(&items[i])));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("Worker thread panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Open the user editor on a file and wait for it to close. The editor is taken from
+/// `$LRNGIT_EDITOR`, then `$EDITOR`, `vi` by default. Exit if the editor fails.
+pub fn edit_file(path: &str) {
+    let editor = env::var("LRNGIT_EDITOR")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    // Go through the shell so the editor can be given with arguments, like `code --wait`
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .status();
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(_) => {
+            lrncore::logs::error_log(&format!("There was a problem with the editor '{editor}'"));
+            exit(1);
+        }
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to run the editor '{editor}': {e}"));
+            exit(1);
+        }
+    }
+}
+
+/// Remove the comment lines, starting with `#`, and the blank lines around a message. Blank
+/// lines left in a row, like around removed comments, are kept as one.
+pub fn strip_comments(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines().filter(|x| !x.starts_with('#')).map(|x| x.trim_end()) {
+        if line.is_empty() && lines.last().is_some_and(|x| x.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_owned()
+}
@@ -0,0 +1,61 @@
+use std::{env, process::exit};
+
+use lrngitcore::objects::commit::CommitContent;
+
+use crate::{
+    object::{
+        commit::parse_commit_by_hash,
+        signature::{Verification, verify_signature},
+    },
+    refs::resolve_commit_revision,
+};
+
+/// `lrngit verify-commit <commit>...`, check the signature of the given commits. Exit with an
+/// error if one of them isn't signed by an allowed signer.
+pub fn verify_commit_command() {
+    let args: Vec<String> = env::args().collect();
+    let revisions = args.get(2..).unwrap_or_default();
+    if revisions.is_empty() {
+        lrncore::usage_exit::usage_and_exit("Invalid command", "lrngit verify-commit <commit>...");
+        exit(1);
+    }
+    let mut verified = true;
+    for revision in revisions {
+        let hash = resolve_commit_revision(revision);
+        let commit = parse_commit_by_hash(&hash);
+        if commit.signature.is_empty() {
+            lrncore::logs::error_log(&format!("no signature found in commit {hash}"));
+            verified = false;
+            continue;
+        }
+        verified &= print_signature(&commit);
+    }
+    exit(if verified { 0 } else { 1 });
+}
+
+/// Print the result of the verification of a commit signature, return true if it's good
+pub fn print_signature(commit: &CommitContent) -> bool {
+    match verify_signature(commit) {
+        Ok(Verification::Good { principal, key }) => {
+            println!("Good \"git\" signature for {principal} with {key}");
+            true
+        }
+        Ok(Verification::Untrusted { key }) => {
+            println!("Good \"git\" signature with {key}");
+            println!("No principal matched.");
+            false
+        }
+        Ok(Verification::Bad { key }) => {
+            println!("BAD signature with {key}");
+            false
+        }
+        Ok(Verification::Unsigned) => {
+            println!("No signature");
+            false
+        }
+        Err(e) => {
+            lrncore::logs::error_log(&format!("Failed to verify the signature: {e}"));
+            false
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::{BTreeSet, HashSet, VecDeque},
+    process,
+    time::SystemTime,
+};
+
+// Maximum number of changes kept in memory, older tokens need a full walk
+const MAX_CHANGES: usize = 100_000;
+
+/// Journal of all the paths changed since the monitor started.
+/// Each change get a sequence number, a token is the monitor instance and the last sequence number
+/// a client has seen.
+pub struct Journal {
+    // Identify the monitor instance, tokens from another instance always need a full walk
+    instance: String,
+    seq: u64,
+    // Highest sequence number dropped from the journal
+    trimmed: u64,
+    changes: VecDeque<(u64, String)>,
+    // Cookie files seen by the watcher and not yet waited for by a client
+    cookies: HashSet<String>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            instance: new_instance_id(),
+            seq: 0,
+            trimmed: 0,
+            changes: VecDeque::new(),
+            cookies: HashSet::new(),
+        }
+    }
+
+    /// Record a changed path, relative to the working directory
+    pub fn record(&mut self, path: String) {
+        self.seq += 1;
+        self.changes.push_back((self.seq, path));
+        if self.changes.len() > MAX_CHANGES
+            && let Some((seq, _)) = self.changes.pop_front()
+        {
+            self.trimmed = seq;
+        }
+    }
+
+    /// Forget everything, used when events have been lost. All existing tokens will need a full
+    /// walk.
+    pub fn invalidate(&mut self) {
+        self.instance = new_instance_id();
+        self.seq = 0;
+        self.trimmed = 0;
+        self.changes.clear();
+        self.cookies.clear();
+    }
+
+    /// Record that the watcher saw a cookie file, every event queued before it is in the journal
+    pub fn see_cookie(&mut self, name: String) {
+        self.cookies.insert(name);
+    }
+
+    /// Return true and forget the cookie if the watcher saw it
+    pub fn take_cookie(&mut self, name: &str) -> bool {
+        self.cookies.remove(name)
+    }
+
+    pub fn token(&self) -> String {
+        format!("{}:{}", self.instance, self.seq)
+    }
+
+    /// Return all paths changed since the given token, None if the journal can't tell and the
+    /// client must walk the whole working directory.
+    pub fn changes_since(&self, token: &str) -> Option<Vec<String>> {
+        let (instance, seq) = token.rsplit_once(':')?;
+        let seq: u64 = seq.parse().ok()?;
+        if instance != self.instance || seq < self.trimmed || seq > self.seq {
+            return None;
+        }
+        let paths: BTreeSet<&String> = self
+            .changes
+            .iter()
+            .filter(|(x, _)| *x > seq)
+            .map(|(_, path)| path)
+            .collect();
+        Some(paths.into_iter().cloned().collect())
+    }
+}
+
+fn new_instance_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("{}-{now}", process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal_with(paths: &[&str]) -> Journal {
+        let mut journal = Journal::new();
+        for each in paths {
+            journal.record(each.to_string());
+        }
+        journal
+    }
+
+    #[test]
+    fn changes_since_token() {
+        let mut journal = journal_with(&["b", "a"]);
+        let token = journal.token();
+        assert!(token.ends_with(":2"));
+        assert_eq!(journal.changes_since(&token), Some(vec![]));
+        journal.record(String::from("c"));
+        journal.record(String::from("a"));
+        journal.record(String::from("c"));
+        // Sorted and deduplicated
+        assert_eq!(journal.changes_since(&token).unwrap(), ["a", "c"]);
+        let first = token.replace(":2", ":0");
+        assert_eq!(journal.changes_since(&first).unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn unknown_tokens_need_full_walk() {
+        let journal = journal_with(&["a"]);
+        let token = journal.token();
+        assert_eq!(journal.changes_since(""), None);
+        assert_eq!(journal.changes_since("garbage"), None);
+        assert_eq!(journal.changes_since("other-1:1"), None);
+        // A token from the future can't come from this instance
+        assert_eq!(journal.changes_since(&token.replace(":1", ":5")), None);
+    }
+
+    #[test]
+    fn invalidate_and_trim() {
+        let mut journal = journal_with(&["a"]);
+        let token = journal.token();
+        journal.invalidate();
+        assert_eq!(journal.changes_since(&token), None);
+        assert!(journal.token().ends_with(":0"));
+        let start = journal.token();
+        for i in 0..=MAX_CHANGES {
+            journal.record(format!("f{i}"));
+        }
+        // The first change was dropped, a client which hasn't seen it must walk
+        assert_eq!(journal.changes_since(&start), None);
+        let after_trim = start.replace(":0", ":1");
+        assert_eq!(journal.changes_since(&after_trim).unwrap().len(), MAX_CHANGES);
+    }
+
+    #[test]
+    fn cookies() {
+        let mut journal = Journal::new();
+        assert!(!journal.take_cookie("cookie-1"));
+        journal.see_cookie(String::from("cookie-1"));
+        assert!(journal.take_cookie("cookie-1"));
+        assert!(!journal.take_cookie("cookie-1"));
+        journal.see_cookie(String::from("cookie-2"));
+        journal.invalidate();
+        assert!(!journal.take_cookie("cookie-2"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    env::{self, set_current_dir},
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    process::exit,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::Builder,
+    time::Duration,
+};
+
+use journal::Journal;
+use watch::Watcher;
+
+mod journal;
+mod watch;
+
+// Socket used by the client to query changed paths, relative to the repository root
+const SOCKET_PATH: &str = ".lrngit/fsmonitor.sock";
+// Prefix of the cookie files the clients create in `.lrngit` before a query
+pub const COOKIE_PREFIX: &str = "fsmonitor-cookie-";
+// How long a query waits for its cookie, the client gets a full walk past it
+const COOKIE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// State shared by the watcher thread and the clients
+pub struct Monitor {
+    pub journal: Mutex<Journal>,
+    /// Notified when the watcher sees a cookie file
+    pub cookie_seen: Condvar,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if let Some(repo_path) = args.get(1) {
+        set_current_dir(repo_path).expect("Failed to change current dir");
+    }
+    if !Path::new(".lrngit").is_dir() {
+        eprintln!("fatal: not a lrngit repository");
+        exit(1);
+    }
+    let monitor = Arc::new(Monitor {
+        journal: Mutex::new(Journal::new()),
+        cookie_seen: Condvar::new(),
+    });
+    // Watches must be in place before the socket accept clients, or changes could be missed
+    let watcher = Watcher::new().expect("Failed to init inotify");
+    let watcher_monitor = monitor.clone();
+    Builder::new()
+        .name("watcher".to_string())
+        .spawn(move || watcher.run(watcher_monitor))
+        .expect("Failed to create watcher thread");
+    // Remove socket left by a previous monitor
+    let _ = fs::remove_file(SOCKET_PATH);
+    let listener = UnixListener::bind(SOCKET_PATH).expect("Failed to bind monitor socket");
+    println!("fsmonitor listening on {SOCKET_PATH}");
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => handle_client(stream, &monitor),
+            Err(e) => {
+                eprintln!("Connection failed: {e}");
+            }
+        }
+    }
+}
+
+/// Read the client token and answer with a new token followed by the changed paths. The client
+/// may send the name of a cookie file it created after the token, the answer waits until the
+/// watcher has seen it so the changes made before the query are in the journal.
+fn handle_client(stream: UnixStream, monitor: &Monitor) {
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    if let Err(e) = reader.read_line(&mut line) {
+        eprintln!("Failed to read client token: {e}");
+        return;
+    }
+    let line = line.trim_end_matches('\n');
+    let (token, cookie) = match line.split_once(' ') {
+        Some((token, cookie)) => (token, Some(cookie)),
+        None => (line, None),
+    };
+    let response = {
+        let journal = monitor.journal.lock().unwrap();
+        let (journal, synced) = match cookie {
+            Some(cookie) => wait_cookie(monitor, journal, cookie),
+            None => (journal, true),
+        };
+        let mut response = journal.token() + "\n";
+        match journal.changes_since(token).filter(|_| synced) {
+            Some(paths) => {
+                response.push_str("DIRTY\n");
+                for each in paths {
+                    response.push_str(&each);
+                    response.push('\n');
+                }
+            }
+            None => response.push_str("FULL\n"),
+        }
+        response
+    };
+    let mut stream = &stream;
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        eprintln!("Failed to answer client: {e}");
+    }
+}
+
+/// Wait until the watcher has seen the cookie file, return false if it didn't in time
+fn wait_cookie<'a>(
+    monitor: &Monitor,
+    journal: MutexGuard<'a, Journal>,
+    cookie: &str,
+) -> (MutexGuard<'a, Journal>, bool) {
+    let (journal, timeout) = monitor
+        .cookie_seen
+        .wait_timeout_while(journal, COOKIE_TIMEOUT, |x| !x.take_cookie(cookie))
+        .unwrap();
+    (journal, !timeout.timed_out())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, thread, time::Instant};
+
+    use super::*;
+
+    fn new_monitor(paths: &[&str]) -> Arc<Monitor> {
+        let mut journal = Journal::new();
+        for each in paths {
+            journal.record(each.to_string());
+        }
+        Arc::new(Monitor {
+            journal: Mutex::new(journal),
+            cookie_seen: Condvar::new(),
+        })
+    }
+
+    /// Send a query line to the monitor and return the lines of its answer
+    fn query(monitor: &Monitor, line: &str) -> Vec<String> {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(line.as_bytes()).unwrap();
+        handle_client(server, monitor);
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response.lines().map(|x| x.to_owned()).collect()
+    }
+
+    #[test]
+    fn answer_changes_since_token() {
+        let monitor = new_monitor(&["a"]);
+        let token = monitor.journal.lock().unwrap().token();
+        assert_eq!(query(&monitor, "\n"), [token.as_str(), "FULL"]);
+        monitor.journal.lock().unwrap().record(String::from("b"));
+        let response = query(&monitor, &format!("{token}\n"));
+        assert!(response[0].ends_with(":2"));
+        assert_eq!(response[1..], ["DIRTY", "b"]);
+    }
+
+    #[test]
+    fn wait_for_cookie() {
+        let monitor = new_monitor(&["a"]);
+        let token = monitor.journal.lock().unwrap().token();
+        // The watcher records the change and the cookie while the query waits
+        let watcher = monitor.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut journal = watcher.journal.lock().unwrap();
+            journal.record(String::from("written-before-query"));
+            journal.see_cookie(format!("{COOKIE_PREFIX}1"));
+            watcher.cookie_seen.notify_all();
+        });
+        let response = query(&monitor, &format!("{token} {COOKIE_PREFIX}1\n"));
+        handle.join().unwrap();
+        assert_eq!(response[1..], ["DIRTY", "written-before-query"]);
+        assert!(!monitor.journal.lock().unwrap().take_cookie(&format!("{COOKIE_PREFIX}1")));
+    }
+
+    #[test]
+    fn full_walk_when_cookie_is_missed() {
+        let monitor = new_monitor(&["a"]);
+        let token = monitor.journal.lock().unwrap().token();
+        let start = Instant::now();
+        let response = query(&monitor, &format!("{token} {COOKIE_PREFIX}2\n"));
+        assert!(start.elapsed() >= COOKIE_TIMEOUT);
+        assert_eq!(response[1..], ["FULL"]);
+    }
+}
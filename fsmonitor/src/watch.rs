@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+use crate::{COOKIE_PREFIX, Monitor, journal::Journal};
+
+// Same paths as the ones skipped by the lrngit status walk
+const AVOID_PATH: [&str; 3] = [".lrngit", ".git", "target"];
+
+/// Inotify watcher over every directory of the working directory
+pub struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    // Watch of the repository directory, only used to see the cookie files of the clients
+    repo_dir: Option<WatchDescriptor>,
+}
+
+impl Watcher {
+    pub fn new() -> nix::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+        let mut watcher = Watcher {
+            inotify,
+            dirs: HashMap::new(),
+            repo_dir: None,
+        };
+        watcher.repo_dir = Some(watcher.inotify.add_watch(".lrngit", AddWatchFlags::IN_CREATE)?);
+        watcher.add_dir_recursive(Path::new("."), None);
+        Ok(watcher)
+    }
+
+    /// Watch a directory and all its sub directories. When a journal is given, every file found
+    /// is recorded as changed, used for directories created after the monitor started since their
+    /// content may have been written before the watch was added.
+    fn add_dir_recursive(&mut self, dir: &Path, journal: Option<&Mutex<Journal>>) {
+        let flags = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MODIFY
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_ONLYDIR;
+        match self.inotify.add_watch(dir, flags) {
+            Ok(wd) => {
+                self.dirs.insert(wd, dir.to_path_buf());
+            }
+            Err(e) => {
+                eprintln!("Failed to watch {dir:?}: {e}");
+                return;
+            }
+        }
+        let Ok(read_dir) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in read_dir.flatten() {
+            if AVOID_PATH.iter().any(|x| entry.file_name() == *x) {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                self.add_dir_recursive(&path, journal);
+            } else if let Some(journal) = journal {
+                journal.lock().unwrap().record(relative_path(&path));
+            }
+        }
+    }
+
+    /// Read inotify events forever and record changed paths in the journal. Clients waiting for
+    /// their cookie file are woken up once it is seen.
+    pub fn run(mut self, monitor: Arc<Monitor>) {
+        let journal = &monitor.journal;
+        loop {
+            let events = match self.inotify.read_events() {
+                Ok(e) => e,
+                Err(e) => {
+                    eprintln!("Failed to read inotify events: {e}");
+                    continue;
+                }
+            };
+            for event in events {
+                if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                    // Events have been dropped by the kernel, nothing can be trusted anymore
+                    journal.lock().unwrap().invalidate();
+                    continue;
+                }
+                if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                    self.dirs.remove(&event.wd);
+                    continue;
+                }
+                if Some(event.wd) == self.repo_dir {
+                    if let Some(name) = event.name.as_ref().and_then(|x| x.to_str())
+                        && name.starts_with(COOKIE_PREFIX)
+                    {
+                        journal.lock().unwrap().see_cookie(name.to_owned());
+                        monitor.cookie_seen.notify_all();
+                    }
+                    continue;
+                }
+                let (Some(dir), Some(name)) = (self.dirs.get(&event.wd), event.name) else {
+                    continue;
+                };
+                if AVOID_PATH.iter().any(|x| name == *x) {
+                    continue;
+                }
+                let path = dir.join(name);
+                journal.lock().unwrap().record(relative_path(&path));
+                if event.mask.contains(AddWatchFlags::IN_ISDIR)
+                    && (event.mask.contains(AddWatchFlags::IN_CREATE)
+                        || event.mask.contains(AddWatchFlags::IN_MOVED_TO))
+                {
+                    self.add_dir_recursive(&path, Some(journal));
+                }
+            }
+        }
+    }
+}
+
+/// Path relative to the working directory, as stored in the index
+fn relative_path(path: &Path) -> String {
+    path.strip_prefix(".")
+        .unwrap_or(path)
+        .to_str()
+        .expect("Failed to cast path to str")
+        .to_owned()
+}
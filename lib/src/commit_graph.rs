@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    io::{Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    objects::commit::{commit_parents, commit_timestamp, read_commit},
+    refs::{list_refs, read_ref},
+    revparse::peel,
+};
+
+// Relative to the repository path
+const GRAPH_PATH: &str = "objects/info/commit-graph";
+const GRAPH_MAGIC: [u8; 4] = *b"CGPH";
+const GRAPH_VERSION: u8 = 1;
+
+/// Commit-graph file, commits are sorted by hash so a commit can be found with a binary search
+/// and parents are stored as positions in the same list.
+#[derive(Debug, Serialize, Deserialize)]
+struct CommitGraphFile {
+    magic: [u8; 4],
+    version: u8,
+    commits: Vec<GraphCommit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct GraphCommit {
+    hash: [u8; 20],
+    tree: [u8; 20],
+    parents: Vec<u32>,
+    // 1 for a root commit, else one more than the highest generation of the parents
+    generation: u32,
+    timestamp: i64,
+}
+
+/// Commit data read from the commit-graph
+#[derive(Debug, Clone)]
+pub struct GraphEntry {
+    pub tree: [u8; 20],
+    pub parents: Vec<String>,
+    pub generation: u32,
+    pub timestamp: i64,
+}
+
+/// Commit-graph loaded in memory. Commits made after the last write aren't in the graph, callers
+/// fall back to reading the commit object for them.
+pub struct CommitGraph {
+    commits: Vec<GraphCommit>,
+}
+
+impl CommitGraph {
+    /// Load the commit-graph of the repository, None if there's no graph or it can't be read
+    pub fn load(repo_path: &str) -> Option<CommitGraph> {
+        let mut file = File::open(format!("{repo_path}/{GRAPH_PATH}")).ok()?;
+        let mut buff: Vec<u8> = Vec::new();
+        file.read_to_end(&mut buff).ok()?;
+        let graph: CommitGraphFile = bincode::deserialize(&buff).ok()?;
+        if graph.magic != GRAPH_MAGIC || graph.version != GRAPH_VERSION {
+            return None;
+        }
+        Some(CommitGraph {
+            commits: graph.commits,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+
+    /// Get a commit from the graph by its hex hash
+    pub fn get(&self, hash: &str) -> Option<GraphEntry> {
+        let mut raw = [0u8; 20];
+        hex::decode_to_slice(hash, &mut raw).ok()?;
+        let pos = self.commits.binary_search_by(|x| x.hash.cmp(&raw)).ok()?;
+        let commit = &self.commits[pos];
+        Some(GraphEntry {
+            tree: commit.tree,
+            parents: commit
+                .parents
+                .iter()
+                .map(|x| hex::encode(self.commits[*x as usize].hash))
+                .collect(),
+            generation: commit.generation,
+            timestamp: commit.timestamp,
+        })
+    }
+}
+
+/// Write the commit-graph with every commit reachable from HEAD and the refs, return the number
+/// of commits written
+pub fn write_commit_graph(repo_path: &str) -> Result<usize, Box<dyn Error>> {
+    // Tags are peeled to their commit, refs to other objects are skipped
+    let mut tips: Vec<String> = list_refs(repo_path, "refs")?
+        .into_iter()
+        .filter_map(|x| peel(repo_path, &x.1, "commit").ok())
+        .collect();
+    if let Some(head) = read_ref(repo_path, "HEAD")? {
+        tips.push(head);
+    }
+    // Read every reachable commit once
+    let mut read: HashMap<String, (GraphCommit, Vec<String>)> = HashMap::new();
+    let mut stack: Vec<String> = tips;
+    while let Some(hash) = stack.pop() {
+        if read.contains_key(&hash) {
+            continue;
+        }
+        let commit = read_commit(&hash, repo_path)?;
+        let parents = commit_parents(&commit);
+        let mut raw = [0u8; 20];
+        hex::decode_to_slice(&hash, &mut raw)?;
+        let entry = GraphCommit {
+            hash: raw,
+            tree: commit.tree,
+            parents: vec![],
+            generation: 0,
+            timestamp: commit_timestamp(&commit)?,
+        };
+        stack.extend(parents.iter().cloned());
+        read.insert(hash, (entry, parents));
+    }
+    let mut hashes: Vec<String> = read.keys().cloned().collect();
+    hashes.sort();
+    let position: HashMap<&String, u32> = hashes
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x, i as u32))
+        .collect();
+    let mut commits: Vec<GraphCommit> = Vec::with_capacity(hashes.len());
+    let mut parents_of: Vec<Vec<u32>> = Vec::with_capacity(hashes.len());
+    for hash in &hashes {
+        let (mut entry, parents) = read.remove(hash).unwrap();
+        entry.parents = parents.iter().map(|x| position[x]).collect();
+        parents_of.push(entry.parents.clone());
+        commits.push(entry);
+    }
+    compute_generations(&mut commits, &parents_of);
+    let graph = CommitGraphFile {
+        magic: GRAPH_MAGIC,
+        version: GRAPH_VERSION,
+        commits,
+    };
+    let count = graph.commits.len();
+    let buff = bincode::serialize(&graph)?;
+    fs::create_dir_all(format!("{repo_path}/objects/info"))?;
+    // Write aside then rename so readers never see a half written graph
+    let tmp_path = format!("{repo_path}/{GRAPH_PATH}.lock");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&buff)?;
+    fs::rename(tmp_path, format!("{repo_path}/{GRAPH_PATH}"))?;
+    Ok(count)
+}
+
+/// Set the generation number of every commit, without recursion as histories can be long
+fn compute_generations(commits: &mut [GraphCommit], parents_of: &[Vec<u32>]) {
+    for start in 0..commits.len() {
+        let mut stack: Vec<usize> = vec![start];
+        while let Some(&i) = stack.last() {
+            if commits[i].generation != 0 {
+                stack.pop();
+                continue;
+            }
+            let pending: Vec<usize> = parents_of[i]
+                .iter()
+                .map(|x| *x as usize)
+                .filter(|x| commits[*x].generation == 0)
+                .collect();
+            if pending.is_empty() {
+                let max_parent = parents_of[i]
+                    .iter()
+                    .map(|x| commits[*x as usize].generation)
+                    .max()
+                    .unwrap_or(0);
+                commits[i].generation = max_parent + 1;
+                stack.pop();
+            } else {
+                stack.extend(pending);
+            }
+        }
+    }
+}
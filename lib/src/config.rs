@@ -0,0 +1,514 @@
+use std::{
+    env,
+    error::Error,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use ini::Ini;
+
+/// Config file of the whole system
+pub const SYSTEM_CONFIG: &str = "/etc/lrngitconfig";
+
+/// Directory holding the repositories served by the services, unless `daemon.repositories` is set
+pub const DEFAULT_REPOSITORIES: &str = "/home/ubuntu/lrngit/repositories/";
+
+/// Directory of a repository served by the services. `name` comes from the network, it must be
+/// a relative path without `..` and must stay under the served root once symlinks are resolved.
+/// None if the name is rejected or the repository doesn't exist.
+pub fn served_repository(config: &Config, name: &str) -> Option<PathBuf> {
+    let root = config
+        .get_path("daemon.repositories")
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_REPOSITORIES));
+    if name.is_empty()
+        || !Path::new(name)
+            .components()
+            .all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    let root = root.canonicalize().ok()?;
+    let path = root.join(name).canonicalize().ok()?;
+    path.starts_with(&root).then_some(path)
+}
+
+/// Where a config value comes from. Scopes are read in this order, a value read later wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigScope {
+    System,
+    /// `$XDG_CONFIG_HOME/lrngit/config` then `~/.lrngitconfig`
+    Global,
+    /// `config` file of the repository
+    Local,
+    /// `LRNGIT_CONFIG_COUNT` pairs of `LRNGIT_CONFIG_KEY_<n>` and `LRNGIT_CONFIG_VALUE_<n>`
+    Env,
+}
+
+impl fmt::Display for ConfigScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigScope::System => "system",
+            ConfigScope::Global => "global",
+            ConfigScope::Local => "local",
+            ConfigScope::Env => "command",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl ConfigScope {
+    /// Files of the scope in reading order, `repo_path` is the `.lrngit` directory
+    pub fn paths(&self, repo_path: &str) -> Vec<PathBuf> {
+        match self {
+            ConfigScope::System => vec![PathBuf::from(SYSTEM_CONFIG)],
+            ConfigScope::Global => {
+                let mut paths = Vec::new();
+                let xdg = env::var("XDG_CONFIG_HOME")
+                    .ok()
+                    .filter(|x| !x.is_empty())
+                    .map(PathBuf::from)
+                    .or_else(|| dirs::home_dir().map(|x| x.join(".config")));
+                if let Some(xdg) = xdg {
+                    paths.push(xdg.join("lrngit/config"));
+                }
+                if let Some(home) = dirs::home_dir() {
+                    paths.push(home.join(".lrngitconfig"));
+                }
+                paths
+            }
+            ConfigScope::Local => vec![Path::new(repo_path).join("config")],
+            ConfigScope::Env => vec![],
+        }
+    }
+
+    /// File written by set and unset, the env can't be written
+    pub fn writable_path(&self, repo_path: &str) -> Option<PathBuf> {
+        match self {
+            ConfigScope::Env => None,
+            _ => self.paths(repo_path).pop(),
+        }
+    }
+}
+
+/// A value of the config with its dotted key, like `user.name` or `remote.origin.url`
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub scope: ConfigScope,
+    pub key: String,
+    pub value: String,
+}
+
+/// Values read from one or several config scopes. Missing or unreadable files are skipped so
+/// reading the config never fails, absent keys are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    entries: Vec<ConfigEntry>,
+}
+
+impl Config {
+    /// Load every scope merged, system then global then local then env
+    pub fn load(repo_path: &str) -> Config {
+        let scopes = [
+            ConfigScope::System,
+            ConfigScope::Global,
+            ConfigScope::Local,
+            ConfigScope::Env,
+        ];
+        let entries = scopes
+            .iter()
+            .flat_map(|x| Config::load_scope(*x, repo_path).entries)
+            .collect();
+        Config { entries }
+    }
+
+    /// Load the scopes that don't depend on a repository, system then global then env. Used by
+    /// the services, which run outside of a repository.
+    pub fn load_global() -> Config {
+        let scopes = [ConfigScope::System, ConfigScope::Global, ConfigScope::Env];
+        let entries = scopes
+            .iter()
+            .flat_map(|x| Config::load_scope(*x, "").entries)
+            .collect();
+        Config { entries }
+    }
+
+    /// Load the values of a single scope
+    pub fn load_scope(scope: ConfigScope, repo_path: &str) -> Config {
+        let mut entries = Vec::new();
+        if scope == ConfigScope::Env {
+            let count: usize = env::var("LRNGIT_CONFIG_COUNT")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0);
+            for i in 0..count {
+                let Ok(key) = env::var(format!("LRNGIT_CONFIG_KEY_{i}")) else {
+                    continue;
+                };
+                let Ok(key) = normalize_key(&key) else {
+                    continue;
+                };
+                let value = env::var(format!("LRNGIT_CONFIG_VALUE_{i}")).unwrap_or_default();
+                entries.push(ConfigEntry { scope, key, value });
+            }
+        }
+        for path in scope.paths(repo_path) {
+            entries.extend(read_file(scope, &path));
+        }
+        Config { entries }
+    }
+
+    /// Load the values of a single file, read as local
+    pub fn load_file(path: &Path) -> Config {
+        Config {
+            entries: read_file(ConfigScope::Local, path),
+        }
+    }
+
+    /// Every value in reading order
+    pub fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    /// Get the value of a key, the last one read wins
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key).ok()?;
+        self.entries
+            .iter()
+            .rev()
+            .find(|x| x.key == key)
+            .map(|x| x.value.as_str())
+    }
+
+    /// Get every value of a multi-valued key like `remote.origin.fetch`, in reading order
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let Ok(key) = normalize_key(key) else {
+            return Vec::new();
+        };
+        self.entries
+            .iter()
+            .filter(|x| x.key == key)
+            .map(|x| x.value.as_str())
+            .collect()
+    }
+
+    /// Get a boolean, `true`, `yes`, `on`, `1` or an empty value are true and `false`, `no`,
+    /// `off`, `0` are false
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Box<dyn Error>> {
+        self.get(key).map(|x| parse_bool(key, x)).transpose()
+    }
+
+    /// Get an integer, the `k`, `m` and `g` suffixes multiply it by 1024, 1024² and 1024³
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, Box<dyn Error>> {
+        self.get(key).map(|x| parse_int(key, x)).transpose()
+    }
+
+    /// Get a path, a leading `~/` is the home directory
+    pub fn get_path(&self, key: &str) -> Option<PathBuf> {
+        self.get(key).filter(|x| !x.is_empty()).map(expand_path)
+    }
+
+    /// Get a boolean or the default when the key is absent or invalid
+    pub fn get_bool_or(&self, key: &str, default: bool) -> bool {
+        self.get_bool(key).ok().flatten().unwrap_or(default)
+    }
+
+    /// Get an integer or the default when the key is absent or invalid
+    pub fn get_int_or(&self, key: &str, default: i64) -> i64 {
+        self.get_int(key).ok().flatten().unwrap_or(default)
+    }
+}
+
+/// Values of a config file, nothing if it is missing or unreadable
+fn read_file(scope: ConfigScope, path: &Path) -> Vec<ConfigEntry> {
+    let Ok(file) = Ini::load_from_file(path) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    for (section, properties) in file.iter() {
+        let Some(section) = section else {
+            continue;
+        };
+        let section = section_key(section);
+        for (name, value) in properties.iter() {
+            entries.push(ConfigEntry {
+                scope,
+                key: format!("{section}.{}", name.to_lowercase()),
+                value: value.to_owned(),
+            });
+        }
+    }
+    entries
+}
+
+pub fn parse_bool(key: &str, value: &str) -> Result<bool, Box<dyn Error>> {
+    match value.to_lowercase().as_str() {
+        "" | "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("bad boolean config value '{value}' for '{key}'").into()),
+    }
+}
+
+pub fn parse_int(key: &str, value: &str) -> Result<i64, Box<dyn Error>> {
+    let value = value.trim();
+    let (number, factor) = match value.chars().last().map(|x| x.to_ascii_lowercase()) {
+        Some('k') => (&value[..value.len() - 1], 1 << 10),
+        Some('m') => (&value[..value.len() - 1], 1 << 20),
+        Some('g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .and_then(|x| x.checked_mul(factor))
+        .ok_or_else(|| format!("bad numeric config value '{value}' for '{key}'").into())
+}
+
+pub fn expand_path(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Split a dotted key in its ini section and name. `user.name` is `name` in `[user]` and
+/// `remote.origin.url` is `url` in `[remote "origin"]`.
+pub fn split_key(key: &str) -> Result<(String, String), Box<dyn Error>> {
+    let (section, name) = key
+        .rsplit_once('.')
+        .filter(|(section, name)| !section.is_empty() && !name.is_empty())
+        .ok_or_else(|| format!("key does not contain a section: {key}"))?;
+    Ok((ini_section(section), name.to_lowercase()))
+}
+
+/// Dotted key of an ini section, `remote "origin"` is `remote.origin`
+fn section_key(section: &str) -> String {
+    match section.split_once(' ') {
+        Some((section, subsection)) => {
+            format!("{}.{}", section.to_lowercase(), subsection.trim().trim_matches('"'))
+        }
+        None => section.to_lowercase(),
+    }
+}
+
+/// Lowercase the section and the name of a key, the subsection is case sensitive
+fn normalize_key(key: &str) -> Result<String, Box<dyn Error>> {
+    let (section, name) = split_key(key)?;
+    Ok(format!("{}.{name}", section_key(&section)))
+}
+
+/// Set a value in a config file, the file is created if needed
+pub fn set_value(path: &Path, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let (section, name) = split_key(key)?;
+    let mut file = if path.exists() {
+        Ini::load_from_file(path)?
+    } else {
+        Ini::new()
+    };
+    let section = existing_section(&file, &section).unwrap_or(section);
+    remove_name(&mut file, &section, &name);
+    file.with_section(Some(section)).set(name, value);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    file.write_to_file(path)?;
+    Ok(())
+}
+
+/// Remove a value from a config file, return false if it wasn't set. A section left empty is
+/// removed.
+pub fn unset_value(path: &Path, key: &str) -> Result<bool, Box<dyn Error>> {
+    let (section, name) = split_key(key)?;
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut file = Ini::load_from_file(path)?;
+    let Some(section) = existing_section(&file, &section) else {
+        return Ok(false);
+    };
+    if !remove_name(&mut file, &section, &name) {
+        return Ok(false);
+    }
+    if file.section(Some(section.as_str())).is_some_and(|x| x.is_empty()) {
+        file.delete(Some(section.as_str()));
+    }
+    file.write_to_file(path)?;
+    Ok(true)
+}
+
+/// Remove a whole section from a config file, `section` is dotted like `remote.origin`. Return
+/// false if it wasn't there.
+pub fn remove_section(path: &Path, section: &str) -> Result<bool, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut file = Ini::load_from_file(path)?;
+    let Some(section) = existing_section(&file, &ini_section(section)) else {
+        return Ok(false);
+    };
+    file.delete(Some(section.as_str()));
+    file.write_to_file(path)?;
+    Ok(true)
+}
+
+/// Rename a section of a config file, `remote.origin` to `remote.upstream` for instance. Return
+/// false if the old section wasn't there.
+pub fn rename_section(path: &Path, old: &str, new: &str) -> Result<bool, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    let mut file = Ini::load_from_file(path)?;
+    let Some(section) = existing_section(&file, &ini_section(old)) else {
+        return Ok(false);
+    };
+    let Some(properties) = file.delete(Some(section.as_str())) else {
+        return Ok(false);
+    };
+    let target = file.entry(Some(ini_section(new))).or_insert_with(Default::default);
+    for (name, value) in properties.iter() {
+        target.append(name, value);
+    }
+    file.write_to_file(path)?;
+    Ok(true)
+}
+
+/// Ini section of a dotted section, `remote.origin` is `remote "origin"`
+fn ini_section(section: &str) -> String {
+    match section.split_once('.') {
+        Some((section, subsection)) => format!("{} \"{subsection}\"", section.to_lowercase()),
+        None => section.to_lowercase(),
+    }
+}
+
+/// Name of the section of the file matching `section`, the section name isn't case sensitive
+fn existing_section(file: &Ini, section: &str) -> Option<String> {
+    let key = section_key(section);
+    file.sections()
+        .flatten()
+        .find(|x| section_key(x) == key)
+        .map(|x| x.to_owned())
+}
+
+/// Remove every spelling of a name from a section, return true if one was found
+fn remove_name(file: &mut Ini, section: &str, name: &str) -> bool {
+    let Some(properties) = file.section_mut(Some(section)) else {
+        return false;
+    };
+    let names: Vec<String> = properties
+        .iter()
+        .map(|(x, _)| x.to_owned())
+        .filter(|x| x.to_lowercase() == name)
+        .collect();
+    for each in &names {
+        properties.remove_all(each).for_each(drop);
+    }
+    !names.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory under the system temp dir, unique per test
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("lrngit-config-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn config_with(key: &str, value: &str) -> Config {
+        Config {
+            entries: vec![ConfigEntry {
+                scope: ConfigScope::Local,
+                key: key.to_owned(),
+                value: value.to_owned(),
+            }],
+        }
+    }
+
+    #[test]
+    fn parse_bool_values() {
+        for value in ["", "true", "Yes", "on", "1"] {
+            assert!(parse_bool("k.b", value).unwrap());
+        }
+        for value in ["false", "NO", "off", "0"] {
+            assert!(!parse_bool("k.b", value).unwrap());
+        }
+        assert!(parse_bool("k.b", "maybe").is_err());
+    }
+
+    #[test]
+    fn parse_int_suffixes() {
+        assert_eq!(parse_int("k.i", "12").unwrap(), 12);
+        assert_eq!(parse_int("k.i", "2k").unwrap(), 2048);
+        assert_eq!(parse_int("k.i", "1M").unwrap(), 1 << 20);
+        assert_eq!(parse_int("k.i", "1g").unwrap(), 1 << 30);
+        assert!(parse_int("k.i", "ten").is_err());
+    }
+
+    #[test]
+    fn split_key_subsections() {
+        assert_eq!(
+            split_key("User.Name").unwrap(),
+            ("user".to_owned(), "name".to_owned())
+        );
+        assert_eq!(
+            split_key("remote.Origin.url").unwrap(),
+            ("remote \"Origin\"".to_owned(), "url".to_owned())
+        );
+        assert!(split_key("nosection").is_err());
+        assert!(split_key("user.").is_err());
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let mut config = config_with("user.name", "global");
+        config.entries.push(ConfigEntry {
+            scope: ConfigScope::Local,
+            key: "user.name".to_owned(),
+            value: "local".to_owned(),
+        });
+        assert_eq!(config.get("USER.name"), Some("local"));
+        assert_eq!(config.get_all("user.name"), vec!["global", "local"]);
+        assert_eq!(config.get("user.email"), None);
+    }
+
+    #[test]
+    fn set_unset_and_rename_sections() {
+        let dir = temp_dir("set");
+        let path = dir.join("config");
+        set_value(&path, "remote.origin.url", "lrngit://host/a").unwrap();
+        set_value(&path, "remote.origin.url", "lrngit://host/b").unwrap();
+        set_value(&path, "user.name", "Test").unwrap();
+        let config = Config::load_scope(ConfigScope::Local, dir.to_str().unwrap());
+        assert_eq!(config.get("remote.origin.url"), Some("lrngit://host/b"));
+        assert!(rename_section(&path, "remote.origin", "remote.up").unwrap());
+        assert!(unset_value(&path, "user.name").unwrap());
+        assert!(!unset_value(&path, "user.name").unwrap());
+        let config = Config::load_scope(ConfigScope::Local, dir.to_str().unwrap());
+        assert_eq!(config.get("remote.up.url"), Some("lrngit://host/b"));
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert!(remove_section(&path, "remote.up").unwrap());
+        assert!(Config::load_scope(ConfigScope::Local, dir.to_str().unwrap()).entries().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn served_repository_stays_under_root() {
+        let dir = temp_dir("served");
+        let root = dir.join("repositories");
+        std::fs::create_dir_all(root.join("team/project")).unwrap();
+        std::fs::create_dir_all(dir.join("secret")).unwrap();
+        let config = config_with("daemon.repositories", root.to_str().unwrap());
+        let served = served_repository(&config, "team/project").unwrap();
+        assert!(served.ends_with("team/project"));
+        assert_eq!(served_repository(&config, "../secret"), None);
+        assert_eq!(served_repository(&config, "team/../../secret"), None);
+        assert_eq!(served_repository(&config, dir.join("secret").to_str().unwrap()), None);
+        assert_eq!(served_repository(&config, "missing"), None);
+        assert_eq!(served_repository(&config, ""), None);
+        std::os::unix::fs::symlink(dir.join("secret"), root.join("link")).unwrap();
+        assert_eq!(served_repository(&config, "link"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
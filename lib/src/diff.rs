@@ -0,0 +1,409 @@
+use std::{collections::BTreeSet, error::Error};
+
+use crate::objects::{blob::read_blob, tree::tree_files};
+
+// Lines of context around changes in a hunk
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// One line of a diff, indices refer to the old and new list of lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// File changed between two trees, None when the file doesn't exist on that side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChange {
+    pub path: String,
+    pub old: Option<[u8; 20]>,
+    pub new: Option<[u8; 20]>,
+}
+
+/// Compute the shortest edit script between two lists of lines with the Myers algorithm, in its
+/// linear space variant
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::with_capacity(old.len().max(new.len()));
+    diff_range(old, new, 0, 0, &mut edits);
+    edits
+}
+
+/// Push the edits between two ranges of lines starting at `old_start` and `new_start`. The
+/// middle snake splits the ranges in two smaller problems solved recursively.
+fn diff_range<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    old_start: usize,
+    new_start: usize,
+    edits: &mut Vec<Edit>,
+) {
+    // Common prefix and suffix don't need to go through the algorithm
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    edits.extend((0..prefix).map(|i| Edit::Equal(old_start + i, new_start + i)));
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+    let (a_start, b_start) = (old_start + prefix, new_start + prefix);
+    if a.is_empty() || b.is_empty() {
+        edits.extend((0..a.len()).map(|i| Edit::Delete(a_start + i)));
+        edits.extend((0..b.len()).map(|i| Edit::Insert(b_start + i)));
+    } else {
+        let ((x, y), (u, v)) = middle_snake(a, b);
+        diff_range(&a[..x], &b[..y], a_start, b_start, edits);
+        edits.extend((0..u - x).map(|i| Edit::Equal(a_start + x + i, b_start + y + i)));
+        diff_range(&a[u..], &b[v..], a_start + u, b_start + v, edits);
+    }
+    let (old_suffix, new_suffix) = (old.len() - suffix, new.len() - suffix);
+    edits.extend(
+        (0..suffix).map(|i| Edit::Equal(old_start + old_suffix + i, new_start + new_suffix + i)),
+    );
+}
+
+/// Find the snake, a run of equal lines, the shortest edit script goes through halfway. The
+/// script is searched from both ends at once and only the furthest point on each diagonal
+/// `k = x - y` is kept, so memory is linear. Return the start and end of the snake, the lists
+/// must differ at both ends.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> ((usize, usize), (usize, usize)) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // Diagonals -max - 1 to max + 1 are read
+    let offset = max + 1;
+    let index = |k: isize| (k + offset) as usize;
+    // Furthest x on each diagonal from the start, and from the end on the reversed lists
+    let mut forward: Vec<isize> = vec![0; 2 * offset as usize + 1];
+    let mut backward: Vec<isize> = vec![0; 2 * offset as usize + 1];
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let start = (x as usize, y as usize);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            // The backward search made one step less, its diagonal k' is delta - k
+            let reverse_k = delta - k;
+            if odd && (1 - d..d).contains(&reverse_k) && x + backward[index(reverse_k)] >= n {
+                return (start, (x as usize, y as usize));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let end = ((n - x) as usize, (m - y) as usize);
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+            let forward_k = delta - k;
+            if !odd && (-d..=d).contains(&forward_k) && x + forward[index(forward_k)] >= n {
+                return (((n - x) as usize, (m - y) as usize), end);
+            }
+        }
+    }
+    unreachable!("the searches from both ends always meet")
+}
+
+/// Split a text in lines, new line characters are kept so a missing one at the end of the file
+/// is a difference
+pub fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Format the hunks of a unified diff between two texts, empty if they are equal
+pub fn unified_hunks(old: &str, new: &str, context: usize) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let edits = diff_lines(&old_lines, &new_lines);
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| !matches!(x, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < changes.len() {
+        // Merge changes whose contexts overlap in the same hunk
+        let start = changes[i].saturating_sub(context);
+        let mut last = changes[i];
+        while i + 1 < changes.len() && changes[i + 1] - last <= 2 * context {
+            i += 1;
+            last = changes[i];
+        }
+        let end = (last + context + 1).min(edits.len());
+        out.push_str(&format_hunk(&edits[start..end], &old_lines, &new_lines));
+        i += 1;
+    }
+    out
+}
+
+fn format_hunk(edits: &[Edit], old: &[&str], new: &[&str]) -> String {
+    // Position in each file before the first line of the hunk
+    let (mut old_pos, mut new_pos) = match edits[0] {
+        Edit::Equal(a, b) => (a, b),
+        Edit::Delete(a) => (a, first_new_pos(edits)),
+        Edit::Insert(b) => (first_old_pos(edits), b),
+    };
+    let old_count = edits
+        .iter()
+        .filter(|x| !matches!(x, Edit::Insert(_)))
+        .count();
+    let new_count = edits
+        .iter()
+        .filter(|x| !matches!(x, Edit::Delete(_)))
+        .count();
+    // An empty side starts at the line before, like `-0,0`
+    if old_count > 0 {
+        old_pos += 1;
+    }
+    if new_count > 0 {
+        new_pos += 1;
+    }
+    let mut out = format!("@@ -{old_pos},{old_count} +{new_pos},{new_count} @@\n");
+    for edit in edits {
+        let (tag, line) = match *edit {
+            Edit::Equal(a, _) => (' ', old[a]),
+            Edit::Delete(a) => ('-', old[a]),
+            Edit::Insert(b) => ('+', new[b]),
+        };
+        out.push(tag);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    out
+}
+
+/// Old position of the first line of a hunk starting with insertions
+fn first_old_pos(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .find_map(|x| match x {
+            Edit::Equal(a, _) | Edit::Delete(a) => Some(*a),
+            Edit::Insert(_) => None,
+        })
+        .unwrap_or(0)
+}
+
+/// New position of the first line of a hunk starting with deletions
+fn first_new_pos(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .find_map(|x| match x {
+            Edit::Equal(_, b) | Edit::Insert(b) => Some(*b),
+            Edit::Delete(_) => None,
+        })
+        .unwrap_or(0)
+}
+
+/// List the files that differ between two trees, a missing tree is empty
+pub fn diff_trees(
+    repo_path: &str,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+) -> Result<Vec<TreeChange>, Box<dyn Error>> {
+    let old_files = match old_tree {
+        Some(tree) => tree_files(tree, repo_path)?,
+        None => Default::default(),
+    };
+    let new_files = match new_tree {
+        Some(tree) => tree_files(tree, repo_path)?,
+        None => Default::default(),
+    };
+    let paths: BTreeSet<&String> = old_files.keys().chain(new_files.keys()).collect();
+    let mut changes: Vec<TreeChange> = Vec::new();
+    for path in paths {
+        let old = old_files.get(path).map(|x| x.hash);
+        let new = new_files.get(path).map(|x| x.hash);
+        if old != new {
+            changes.push(TreeChange {
+                path: path.clone(),
+                old,
+                new,
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Format the patch of a changed file, headers and hunks
+pub fn format_patch(repo_path: &str, change: &TreeChange) -> Result<String, Box<dyn Error>> {
+    let path = &change.path;
+    let mut out = format!("diff --lrngit a/{path} b/{path}\n");
+    let old = match change.old {
+        Some(hash) => read_blob(&hex::encode(hash), repo_path)?,
+        None => {
+            out.push_str("new file\n");
+            vec![]
+        }
+    };
+    let new = match change.new {
+        Some(hash) => read_blob(&hex::encode(hash), repo_path)?,
+        None => {
+            out.push_str("deleted file\n");
+            vec![]
+        }
+    };
+    let old_name = match change.old {
+        Some(_) => format!("a/{path}"),
+        None => "/dev/null".to_owned(),
+    };
+    let new_name = match change.new {
+        Some(_) => format!("b/{path}"),
+        None => "/dev/null".to_owned(),
+    };
+    if old.contains(&0) || new.contains(&0) {
+        out.push_str(&format!("Binary files {old_name} and {new_name} differ\n"));
+        return Ok(out);
+    }
+    out.push_str(&format!("--- {old_name}\n+++ {new_name}\n"));
+    out.push_str(&unified_hunks(
+        &String::from_utf8_lossy(&old),
+        &String::from_utf8_lossy(&new),
+        DEFAULT_CONTEXT,
+    ));
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the edits turn `old` into `new` and return how many lines changed
+    fn check_script<T: PartialEq + std::fmt::Debug>(old: &[T], new: &[T], edits: &[Edit]) -> usize {
+        let (mut next_old, mut next_new) = (0, 0);
+        for edit in edits {
+            match *edit {
+                Edit::Equal(a, b) => {
+                    assert_eq!((a, b), (next_old, next_new));
+                    assert_eq!(old[a], new[b]);
+                    (next_old, next_new) = (a + 1, b + 1);
+                }
+                Edit::Delete(a) => {
+                    assert_eq!(a, next_old);
+                    next_old += 1;
+                }
+                Edit::Insert(b) => {
+                    assert_eq!(b, next_new);
+                    next_new += 1;
+                }
+            }
+        }
+        assert_eq!((next_old, next_new), (old.len(), new.len()));
+        edits.iter().filter(|x| !matches!(x, Edit::Equal(..))).count()
+    }
+
+    /// Length of the shortest edit script from the longest common subsequence
+    fn shortest_script(old: &[u8], new: &[u8]) -> usize {
+        let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        old.len() + new.len() - 2 * lcs[0][0]
+    }
+
+    #[test]
+    fn empty_and_identical() {
+        let lines = ["a\n", "b\n"];
+        assert_eq!(diff_lines::<&str>(&[], &[]), []);
+        assert_eq!(diff_lines(&[], &lines), [Edit::Insert(0), Edit::Insert(1)]);
+        assert_eq!(diff_lines(&lines, &[]), [Edit::Delete(0), Edit::Delete(1)]);
+        assert_eq!(diff_lines(&lines, &lines), [Edit::Equal(0, 0), Edit::Equal(1, 1)]);
+        assert_eq!(unified_hunks("a\nb\n", "a\nb\n", DEFAULT_CONTEXT), "");
+        assert_eq!(unified_hunks("", "a\n", DEFAULT_CONTEXT), "@@ -0,0 +1,1 @@\n+a\n");
+    }
+
+    #[test]
+    fn pure_insert_and_delete() {
+        let old = ["a", "b", "c"];
+        let new = ["a", "x", "y", "b", "c"];
+        let inserted = [
+            Edit::Equal(0, 0),
+            Edit::Insert(1),
+            Edit::Insert(2),
+            Edit::Equal(1, 3),
+            Edit::Equal(2, 4),
+        ];
+        assert_eq!(diff_lines(&old, &new), inserted);
+        let deleted = [
+            Edit::Equal(0, 0),
+            Edit::Delete(1),
+            Edit::Delete(2),
+            Edit::Equal(3, 1),
+            Edit::Equal(4, 2),
+        ];
+        assert_eq!(diff_lines(&new, &old), deleted);
+    }
+
+    #[test]
+    fn interleaved_hunks() {
+        let lines = |change: &dyn Fn(usize) -> Option<String>| -> String {
+            (1..=21).filter_map(change).collect()
+        };
+        let old = lines(&|x| (x <= 20).then(|| format!("{x}\n")));
+        let new = lines(&|x| match x {
+            2 => Some(String::from("two\n")),
+            10 => None,
+            x => Some(format!("{x}\n")),
+        });
+        let expected = "@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+            @@ -7,7 +7,6 @@\n 7\n 8\n 9\n-10\n 11\n 12\n 13\n\
+            @@ -18,3 +17,4 @@\n 18\n 19\n 20\n+21\n";
+        assert_eq!(unified_hunks(&old, &new, DEFAULT_CONTEXT), expected);
+        // Hunks closer than twice the context are merged
+        let close = lines(&|x| match x {
+            4 | 8 => Some(format!("{x}!\n")),
+            x => (x <= 20).then(|| format!("{x}\n")),
+        });
+        let hunks = unified_hunks(&old, &close, DEFAULT_CONTEXT);
+        assert_eq!(hunks.matches("@@ -").count(), 1);
+        assert!(hunks.starts_with("@@ -1,11 +1,11 @@\n"));
+    }
+
+    #[test]
+    fn shortest_scripts() {
+        // Small alphabets give many equal lines in different orders
+        let mut seed: u32 = 7;
+        let mut next = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    b'a' + (seed >> 16) as u8 % 4
+                })
+                .collect()
+        };
+        for len in [1, 2, 5, 13, 40] {
+            for _ in 0..50 {
+                let (old, new) = (next(len), next(len + len % 3));
+                let edits = diff_lines(&old, &new);
+                assert_eq!(check_script(&old, &new, &edits), shortest_script(&old, &new));
+            }
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::pack::upload::ObjectsPackData;
 
 pub fn write_pack_to_disk(objects: Vec<ObjectsPackData>) {
     for each in objects {
-        let hash_chars: Vec<char> = str::from_utf8(&each.hash).expect("Failed to cast buffer to str").chars().collect();
+        let hash_chars: Vec<char> = each.object_name().chars().collect();
         new_file_dir(&hash_chars, &each.data).expect("Failed to create objects");
     }
 }
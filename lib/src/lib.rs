@@ -3,3 +3,13 @@ pub mod fs;
 pub mod pack;
 pub mod remote;
 pub mod out;
+pub mod commit_graph;
+pub mod diff;
+pub mod reflog;
+pub mod refs;
+pub mod revparse;
+pub mod revwalk;
+pub mod merge;
+pub mod config;
+#[cfg(test)]
+mod testing;
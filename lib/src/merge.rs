@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+};
+
+use crate::{
+    diff::{Edit, diff_lines},
+    objects::{blob::read_blob, tree::tree_files},
+};
+
+/// Result of the three-way merge of a file, only produced when the file differs from ours
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeResult {
+    /// The file is taken as is from their side
+    Blob([u8; 20]),
+    /// The file is deleted
+    Deleted,
+    /// Both sides changed the file and the changes were merged cleanly
+    Merged(Vec<u8>),
+    /// The changes clash, the content holds conflict markers or ours when it can't be merged
+    Conflict(Vec<u8>),
+}
+
+/// File of a tree merge whose content differs from ours
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeChange {
+    pub path: String,
+    pub result: MergeResult,
+}
+
+/// Labels written in the conflict markers
+pub struct MergeLabels<'a> {
+    pub ours: &'a str,
+    pub theirs: &'a str,
+}
+
+/// Merge the changes made from `base` to `theirs` into `ours`, trees are given by hash and a
+/// missing tree is empty. Return the files that must change on top of ours.
+pub fn merge_trees(
+    repo_path: &str,
+    base: Option<&str>,
+    ours: Option<&str>,
+    theirs: Option<&str>,
+    labels: &MergeLabels,
+) -> Result<Vec<MergeChange>, Box<dyn Error>> {
+    let files = |tree: Option<&str>| -> Result<HashMap<String, [u8; 20]>, Box<dyn Error>> {
+        match tree {
+            Some(tree) => Ok(tree_files(tree, repo_path)?
+                .into_iter()
+                .map(|(path, x)| (path, x.hash))
+                .collect()),
+            None => Ok(HashMap::new()),
+        }
+    };
+    let (base, ours, theirs) = (files(base)?, files(ours)?, files(theirs)?);
+    let paths: BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+    let mut changes: Vec<MergeChange> = Vec::new();
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        // Only their side changed the file, or both made the same change
+        if t == b || o == t {
+            continue;
+        }
+        let result = match (o, t) {
+            _ if o == b => match t {
+                Some(hash) => MergeResult::Blob(*hash),
+                None => MergeResult::Deleted,
+            },
+            (Some(o), Some(t)) => {
+                let base_content = match b {
+                    Some(hash) => read_blob(&hex::encode(hash), repo_path)?,
+                    None => vec![],
+                };
+                let ours_content = read_blob(&hex::encode(o), repo_path)?;
+                let theirs_content = read_blob(&hex::encode(t), repo_path)?;
+                merge_content(&base_content, &ours_content, &theirs_content, labels)
+            }
+            // Modified on one side and deleted on the other, the remaining content is kept
+            (Some(hash), None) | (None, Some(hash)) => {
+                MergeResult::Conflict(read_blob(&hex::encode(hash), repo_path)?)
+            }
+            (None, None) => continue,
+        };
+        changes.push(MergeChange {
+            path: path.clone(),
+            result,
+        });
+    }
+    Ok(changes)
+}
+
+/// Three-way merge of a file content line by line. Lines are compared as bytes, so text in any
+/// encoding is kept as is. Binary content can't be merged and is a conflict keeping ours.
+pub fn merge_content(base: &[u8], ours: &[u8], theirs: &[u8], labels: &MergeLabels) -> MergeResult {
+    if base.contains(&0) || ours.contains(&0) || theirs.contains(&0) {
+        return MergeResult::Conflict(ours.to_vec());
+    }
+    let (base, ours, theirs) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    // Base line index to the matching line index on each side
+    let matches = |side: &[&[u8]]| -> HashMap<usize, usize> {
+        diff_lines(&base, side)
+            .into_iter()
+            .filter_map(|x| match x {
+                Edit::Equal(a, b) => Some((a, b)),
+                _ => None,
+            })
+            .collect()
+    };
+    let (ours_match, theirs_match) = (matches(&ours), matches(&theirs));
+    let mut out: Vec<u8> = Vec::new();
+    let mut conflict = false;
+    let (mut i, mut a, mut b) = (0, 0, 0);
+    loop {
+        // Lines unchanged on both sides
+        while i < base.len() && ours_match.get(&i) == Some(&a) && theirs_match.get(&i) == Some(&b) {
+            out.extend_from_slice(base[i]);
+            i += 1;
+            a += 1;
+            b += 1;
+        }
+        if i == base.len() && a == ours.len() && b == theirs.len() {
+            break;
+        }
+        // Next base line kept by both sides ends the changed chunk
+        let (next_i, next_a, next_b) = (i..base.len())
+            .find_map(|x| Some((x, *ours_match.get(&x)?, *theirs_match.get(&x)?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let base_chunk = &base[i..next_i];
+        let ours_chunk = &ours[a..next_a];
+        let theirs_chunk = &theirs[b..next_b];
+        if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+            out.extend(theirs_chunk.concat());
+        } else if theirs_chunk == base_chunk {
+            out.extend(ours_chunk.concat());
+        } else {
+            conflict = true;
+            out.extend(format!("<<<<<<< {}\n", labels.ours).as_bytes());
+            push_chunk(&mut out, ours_chunk);
+            out.extend(b"=======\n");
+            push_chunk(&mut out, theirs_chunk);
+            out.extend(format!(">>>>>>> {}\n", labels.theirs).as_bytes());
+        }
+        (i, a, b) = (next_i, next_a, next_b);
+    }
+    if conflict {
+        MergeResult::Conflict(out)
+    } else {
+        MergeResult::Merged(out)
+    }
+}
+
+/// Split a content in lines, each keeping its line feed
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|x| *x == b'\n').collect()
+}
+
+/// Append the lines of a conflict side, the markers must start on their own line
+fn push_chunk(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    out.extend(lines.concat());
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+/// Check if a content still holds conflict markers
+pub fn has_conflict_markers(content: &[u8]) -> bool {
+    content
+        .split(|x| *x == b'\n')
+        .any(|x| x.starts_with(b"<<<<<<< ") || x.starts_with(b">>>>>>> "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRepo;
+
+    const LABELS: MergeLabels = MergeLabels {
+        ours: "HEAD",
+        theirs: "theirs",
+    };
+
+    fn merged(base: &str, ours: &str, theirs: &str) -> MergeResult {
+        merge_content(base.as_bytes(), ours.as_bytes(), theirs.as_bytes(), &LABELS)
+    }
+
+    fn merge(repo: &TestRepo, base: &str, ours: &str, theirs: &str) -> Vec<MergeChange> {
+        merge_trees(&repo.path, Some(base), Some(ours), Some(theirs), &LABELS).unwrap()
+    }
+
+    #[test]
+    fn clean_merge_of_separate_changes() {
+        let result = merged("a\nb\nc\nd\n", "A\nb\nc\nd\n", "a\nb\nc\nD\n");
+        assert_eq!(result, MergeResult::Merged(b"A\nb\nc\nD\n".to_vec()));
+    }
+
+    #[test]
+    fn same_change_on_both_sides() {
+        let result = merged("a\nb\n", "a\nB\n", "a\nB\n");
+        assert_eq!(result, MergeResult::Merged(b"a\nB\n".to_vec()));
+    }
+
+    #[test]
+    fn clashing_changes_get_markers() {
+        let result = merged("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n");
+        let expected = "a\n<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n";
+        assert_eq!(result, MergeResult::Conflict(expected.as_bytes().to_vec()));
+        let MergeResult::Conflict(content) = result else {
+            unreachable!()
+        };
+        assert!(has_conflict_markers(&content));
+        // A side without final newline still puts the markers on their own line
+        let result = merged("a", "b", "c");
+        let expected = "<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> theirs\n";
+        assert_eq!(result, MergeResult::Conflict(expected.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn binary_content_keeps_ours() {
+        let result = merge_content(b"a\0", b"b\0", b"c\0", &LABELS);
+        assert_eq!(result, MergeResult::Conflict(b"b\0".to_vec()));
+    }
+
+    #[test]
+    fn non_utf8_text_keeps_its_bytes() {
+        let base = b"caf\xe9\nb\nc\n";
+        let ours = b"caf\xe9\nb\nna\xefve\n";
+        let theirs = b"d\xe9j\xe0\nb\nc\n";
+        let result = merge_content(base, ours, theirs, &LABELS);
+        assert_eq!(result, MergeResult::Merged(b"d\xe9j\xe0\nb\nna\xefve\n".to_vec()));
+        let result = merge_content(base, ours, b"caf\xe9\nb\n\xff\n", &LABELS);
+        assert!(matches!(result, MergeResult::Conflict(x) if x.contains(&0xff)));
+    }
+
+    #[test]
+    fn merge_trees_changes() {
+        let repo = TestRepo::new("merge-trees");
+        let base = repo.tree(&[("kept", "k\n"), ("theirs", "t\n"), ("both", "1\n2\n3\n")]);
+        let ours = repo.tree(&[("kept", "k\n"), ("theirs", "t\n"), ("both", "one\n2\n3\n")]);
+        let theirs = repo.tree(&[("theirs", "T\n"), ("both", "1\n2\nthree\n"), ("new", "n\n")]);
+        let changes = merge(&repo, &base, &ours, &theirs);
+        let summary: Vec<(&str, &MergeResult)> =
+            changes.iter().map(|x| (x.path.as_str(), &x.result)).collect();
+        assert_eq!(summary.len(), 4);
+        assert_eq!(summary[0], ("both", &MergeResult::Merged(b"one\n2\nthree\n".to_vec())));
+        assert_eq!(summary[1], ("kept", &MergeResult::Deleted));
+        assert_eq!(summary[2].0, "new");
+        assert!(matches!(summary[2].1, MergeResult::Blob(_)));
+        assert_eq!(summary[3].0, "theirs");
+        assert!(matches!(summary[3].1, MergeResult::Blob(_)));
+    }
+
+    #[test]
+    fn merge_trees_modify_delete() {
+        let repo = TestRepo::new("merge-modify-delete");
+        let base = repo.tree(&[("file", "a\n")]);
+        let modified = repo.tree(&[("file", "b\n")]);
+        let deleted = repo.tree(&[]);
+        let conflict = MergeResult::Conflict(b"b\n".to_vec());
+        for (ours, theirs) in [(&modified, &deleted), (&deleted, &modified)] {
+            let changes = merge(&repo, &base, ours, theirs);
+            assert_eq!(changes.len(), 1);
+            assert_eq!((changes[0].path.as_str(), &changes[0].result), ("file", &conflict));
+        }
+        // Deleted on both sides, nothing changes from ours
+        assert!(merge(&repo, &base, &deleted, &deleted).is_empty());
+    }
+}
@@ -1,3 +1,7 @@
+use std::error::Error;
+
+use super::utils::{object_type, read_object, split_object_header};
+
 pub struct FileHashBlob {
     pub blob: Vec<u8>,
     pub hash: [u8; 20],
@@ -10,3 +14,13 @@ pub struct BlobObject {
     pub header: Vec<u8>,
     pub content: Vec<u8>,
 }
+
+/// Read the content of a blob object from the given repository path, header excluded
+pub fn read_blob(hash: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let buffer = read_object(hash, path)?;
+    if object_type(&buffer) != "blob" {
+        return Err(format!("object {hash} is not a blob").into());
+    }
+    let (_, content) = split_object_header(buffer);
+    Ok(content)
+}
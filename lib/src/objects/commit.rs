@@ -1,8 +1,8 @@
-use std::{error::Error, io::Read};
+use std::error::Error;
 
 use serde::{Deserialize, Serialize};
 
-use super::utils::{get_file_by_hash, split_object_header};
+use super::utils::{object_type, read_object, split_object_header};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Commit {
@@ -17,6 +17,10 @@ pub struct CommitObject {
     pub commit_content: CommitContent,
 }
 
+/// Marker of the signature block written after the commit content. Read as the length of a
+/// field it's too large to be mistaken for one.
+pub const SIGNATURE_MAGIC: &[u8; 8] = b"LRNGSIG\0";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitCommitContent {
     pub tree: [u8; 20],
@@ -28,10 +32,16 @@ pub struct InitCommitContent {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommitContent {
     pub tree: [u8; 20],
+    /// Hash of the parent commit. A commit with several parents, like a stash entry, stores them
+    /// separated by spaces, the first parent first.
     pub parent: Vec<u8>,
     pub author: Vec<u8>,
     pub commiter: Vec<u8>,
     pub message: Vec<u8>,
+    /// Armored SSH signature of the commit payload, empty when the commit isn't signed. It's
+    /// stored after the content and isn't part of its serialization.
+    #[serde(skip)]
+    pub signature: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,15 +55,51 @@ pub struct CommitUser {
 /// Parse commit from a buffer
 pub fn parse_commit(buf: Vec<u8>) -> Result<CommitContent, Box<dyn Error>> {
     let (_, content) = split_object_header(buf);
-    let commit: CommitContent = match bincode::deserialize(&content) {
+    let mut commit: CommitContent = match bincode::deserialize(&content) {
         Ok(c) => c,
         Err(e) => {
             return Err(Box::new(e));
         }
     };
+    let size = bincode::serialized_size(&commit)? as usize;
+    commit.signature = read_signature(&content[size..])?;
     Ok(commit)
 }
 
+/// Read the signature block found after the commit content, an unsigned commit has none
+fn read_signature(trailer: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if trailer.is_empty() {
+        return Ok(vec![]);
+    }
+    match trailer.strip_prefix(SIGNATURE_MAGIC) {
+        Some(bytes) => Ok(bincode::deserialize(bytes)?),
+        None => Err("unexpected data after the commit content".into()),
+    }
+}
+
+/// Canonical bytes of a commit, the content of the object without its signature. A commit
+/// without parent is written as an `InitCommitContent`.
+pub fn commit_payload(commit: &CommitContent) -> Result<Vec<u8>, Box<dyn Error>> {
+    if commit.parent.is_empty() {
+        let init_commit = InitCommitContent {
+            tree: commit.tree,
+            author: commit.author.clone(),
+            commiter: commit.commiter.clone(),
+            message: commit.message.clone(),
+        };
+        Ok(bincode::serialize(&init_commit)?)
+    } else {
+        Ok(bincode::serialize(commit)?)
+    }
+}
+
+/// Append the signature block to a commit payload
+pub fn append_signature(payload: &mut Vec<u8>, signature: &[u8]) -> Result<(), Box<dyn Error>> {
+    payload.extend_from_slice(SIGNATURE_MAGIC);
+    payload.extend(bincode::serialize(signature)?);
+    Ok(())
+}
+
 /// Parse the init commit from buffer
 pub fn parse_init_commit(buf: Vec<u8>) -> Result<InitCommitContent, Box<dyn Error>> {
     let (_, content) = split_object_header(buf);
@@ -68,36 +114,69 @@ pub fn parse_commit_author(buf: Vec<u8>) -> CommitUser {
     commit_user
 }
 
-/// Unwind commit from given commit to specified commit. Return error if there's missing commits in
-///
-/// Parameters:
-/// 'begin': hash of the commit to begin unwinding.
-/// 'end': hash of the commit where ending unwinding.
-pub fn unwind_commits(begin: &str, end: &str) {
-    // Begin unwinding
-    let mut commit_object = get_file_by_hash(begin, ".");
-    let mut content_buf: Vec<u8> = Vec::new();
-    commit_object
-        .read_to_end(&mut content_buf)
-        .expect("Failed to read commit content");
-    // decode buffer using zlib
-    let mut d = flate2::read::ZlibDecoder::new(content_buf.as_slice());
-    let mut buffer = Vec::new();
-    // Read decoded file and fill buffer
-    d.read_to_end(&mut buffer).unwrap();
-
-    let parse_commit = parse_commit(buffer.clone());
-    let init_commit: InitCommitContent;
-    if parse_commit.is_err() {
-        init_commit = parse_init_commit(buffer).unwrap();
-        eprintln!("init commit: {:?}", init_commit);
-        return;
-    };
-    let commit_unwrapped = parse_commit.unwrap();
-    let new_commit_object: CommitObject = CommitObject {
-        commit_hash: begin.into(),
-        commit_content: commit_unwrapped.clone(),
-    };
-    eprintln!("new commit object: {:?}", new_commit_object);
-    unwind_commits(str::from_utf8(&commit_unwrapped.parent.to_vec()).expect("Failed to cast bytes vector to str"), end)
+/// Read a commit object from the given repository path. The initial commit has no parent, it's
+/// returned with an empty `parent` field.
+pub fn read_commit(hash: &str, path: &str) -> Result<CommitContent, Box<dyn Error>> {
+    let buffer = read_object(hash, path)?;
+    let kind = object_type(&buffer);
+    if kind != "commit" {
+        return Err(format!("object {hash} is a {kind}, not a commit").into());
+    }
+    if let Ok(commit) = parse_commit(buffer.clone()) {
+        return Ok(commit);
+    }
+    let (_, content) = split_object_header(buffer);
+    let init_commit: InitCommitContent = bincode::deserialize(&content)
+        .map_err(|e| format!("object {hash} is not a commit: {e}"))?;
+    let size = bincode::serialized_size(&init_commit)? as usize;
+    let signature = read_signature(&content[size..])
+        .map_err(|e| format!("object {hash} is not a commit: {e}"))?;
+    Ok(CommitContent {
+        tree: init_commit.tree,
+        parent: vec![],
+        author: init_commit.author,
+        commiter: init_commit.commiter,
+        message: init_commit.message,
+        signature,
+    })
+}
+
+/// Return the parents hash of a commit, the first parent first
+pub fn commit_parents(commit: &CommitContent) -> Vec<String> {
+    String::from_utf8_lossy(&commit.parent)
+        .split_whitespace()
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+/// Return the commit date, the timestamp of the commiter
+pub fn commit_timestamp(commit: &CommitContent) -> Result<i64, Box<dyn Error>> {
+    let commiter: CommitUser = bincode::deserialize(&commit.commiter)?;
+    Ok(commiter.timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_with_parent(parent: &str) -> CommitContent {
+        CommitContent {
+            tree: [0; 20],
+            parent: parent.as_bytes().to_vec(),
+            author: vec![],
+            commiter: vec![],
+            message: vec![],
+            signature: vec![],
+        }
+    }
+
+    #[test]
+    fn parents_in_order() {
+        assert!(commit_parents(&commit_with_parent("")).is_empty());
+        assert_eq!(commit_parents(&commit_with_parent("aaaa")), ["aaaa"]);
+        assert_eq!(
+            commit_parents(&commit_with_parent("aaaa bbbb cccc")),
+            ["aaaa", "bbbb", "cccc"]
+        );
+    }
 }
@@ -1,18 +1,92 @@
-use std::path::PathBuf;
+use std::{error::Error, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+/// Version of the index written. Version 1 stored the entry count on a single byte, it is still
+/// read and rewritten as the current version.
+pub const INDEX_VERSION: u8 = 2;
+
+// Size of the version 1 header: magic number, version and entry count
+const V1_HEADER_SIZE: usize = 6;
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct IndexHeader {
     pub magic_number: [u8; 4],
     pub version: u8,
-    pub entry_count: u8,
+    pub entry_count: u32,
 }
 
+impl IndexHeader {
+    /// Header of an index of the current version holding `entry_count` entries
+    pub fn new(entry_count: usize) -> IndexHeader {
+        IndexHeader {
+            magic_number: *b"DIRC",
+            version: INDEX_VERSION,
+            entry_count: entry_count as u32,
+        }
+    }
+}
+
+/// Signature of the cached-tree extension, written after the index entries
+pub const TREE_EXTENSION: &[u8; 4] = b"TREE";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IndexObject {
     pub header: IndexHeader,
     pub entries: Vec<IndexEntry>,
+    /// Cached-tree extension, not part of the entries serialization
+    #[serde(skip)]
+    pub cached_trees: Vec<CachedTree>,
+}
+
+impl IndexObject {
+    /// Serialize the index as written in the index file, the cached-tree extension follows the
+    /// entries when there is one
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = bincode::serialize(self)?;
+        if !self.cached_trees.is_empty() {
+            bytes.extend_from_slice(TREE_EXTENSION);
+            bytes.extend(bincode::serialize(&self.cached_trees)?);
+        }
+        Ok(bytes)
+    }
+
+    /// Parse the content of an index file, an index of version 1 gets a current header
+    pub fn from_bytes(bytes: &[u8]) -> Result<IndexObject, Box<dyn Error>> {
+        if bytes.len() < V1_HEADER_SIZE || &bytes[..4] != b"DIRC" {
+            return Err("invalid index file header".into());
+        }
+        let (header, content_bytes) = match bytes[4] {
+            1 => (None, &bytes[V1_HEADER_SIZE..]),
+            INDEX_VERSION => {
+                let header: IndexHeader = bincode::deserialize(bytes)?;
+                let header_size = bincode::serialized_size(&header)? as usize;
+                (Some(header), &bytes[header_size..])
+            }
+            version => return Err(format!("unsupported index version {version}").into()),
+        };
+        let entries: Vec<IndexEntry> = bincode::deserialize(content_bytes)?;
+        // Extensions, an index written before the cached-tree has none
+        let content_size = bincode::serialized_size(&entries)? as usize;
+        let cached_trees: Vec<CachedTree> =
+            match content_bytes[content_size..].strip_prefix(TREE_EXTENSION) {
+                Some(bytes) => bincode::deserialize(bytes).unwrap_or_default(),
+                None => vec![],
+            };
+        Ok(IndexObject {
+            header: header.unwrap_or_else(|| IndexHeader::new(entries.len())),
+            entries,
+            cached_trees,
+        })
+    }
+}
+
+/// Hash of the tree written for a directory of the index, the root is the empty path.
+/// The entry is dropped when a file under the directory changes in the index.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Eq)]
+pub struct CachedTree {
+    pub path: String,
+    pub hash: [u8; 20],
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Eq, PartialOrd, Ord)]
@@ -34,3 +108,58 @@ pub struct TempIndex {
     pub changed_files: Vec<IndexEntry>,
     pub to_delete_files: Vec<PathBuf>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: usize) -> Vec<IndexEntry> {
+        (0..count)
+            .map(|i| IndexEntry {
+                mtime: i as u32,
+                file_size: 3,
+                mode: 0o100644,
+                hash: [i as u8; 20],
+                flag: 0,
+                path: format!("dir/file{i:05}").into_bytes(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip_many_entries() {
+        let index = IndexObject {
+            header: IndexHeader::new(300),
+            entries: entries(300),
+            cached_trees: vec![CachedTree {
+                path: String::from("dir"),
+                hash: [7; 20],
+            }],
+        };
+        let parsed = IndexObject::from_bytes(&index.to_bytes().unwrap()).unwrap();
+        assert_eq!(parsed.header.version, INDEX_VERSION);
+        assert_eq!(parsed.header.entry_count, 300);
+        assert_eq!(parsed.entries, index.entries);
+        assert_eq!(parsed.cached_trees, index.cached_trees);
+    }
+
+    #[test]
+    fn read_version_1() {
+        let mut bytes = b"DIRC\x01\x02".to_vec();
+        bytes.extend(bincode::serialize(&entries(2)).unwrap());
+        let parsed = IndexObject::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.header.version, INDEX_VERSION);
+        assert_eq!(parsed.header.entry_count, 2);
+        assert_eq!(parsed.entries, entries(2));
+        assert!(parsed.cached_trees.is_empty());
+        // Written back with the current header
+        assert_eq!(&parsed.to_bytes().unwrap()[..9], b"DIRC\x02\x02\0\0\0");
+    }
+
+    #[test]
+    fn reject_invalid_header() {
+        assert!(IndexObject::from_bytes(b"DIR").is_err());
+        assert!(IndexObject::from_bytes(b"JUNK\x02\0\0\0\0").is_err());
+        assert!(IndexObject::from_bytes(b"DIRC\x09\0\0\0\0").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod blob;
 pub mod commit;
+pub mod tag;
 pub mod tree;
 pub mod index;
 pub mod utils;
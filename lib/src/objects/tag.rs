@@ -0,0 +1,26 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use super::utils::{object_type, read_object, split_object_header};
+
+/// Content of an annotated tag object, the tagger is a serialized `CommitUser`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagContent {
+    pub object: [u8; 20],
+    pub object_type: Vec<u8>,
+    pub tag: Vec<u8>,
+    pub tagger: Vec<u8>,
+    pub message: Vec<u8>,
+}
+
+/// Read a tag object from the given repository path
+pub fn read_tag(hash: &str, path: &str) -> Result<TagContent, Box<dyn Error>> {
+    let buffer = read_object(hash, path)?;
+    let kind = object_type(&buffer);
+    if kind != "tag" {
+        return Err(format!("object {hash} is a {kind}, not a tag").into());
+    }
+    let (_, content) = split_object_header(buffer);
+    Ok(bincode::deserialize(&content)?)
+}
@@ -1,4 +1,9 @@
+use std::{collections::BTreeMap, error::Error};
+
 use serde::{Deserialize, Serialize};
+
+use super::utils::{object_type, read_object, split_object_header};
+
 /// The `TreeEntry` struct in Rust represents an entry in a tree object with mode, name, and SHA-1 hash.
  ///
  /// Properties:
@@ -32,3 +37,30 @@ use serde::{Deserialize, Serialize};
  pub const DIR: u32 = 0o040000;
  pub const EXE: u32 = 0o100755;
  pub const RWO: u32 = 0o100644;
+
+/// Read the entries of a tree object from the given repository path
+pub fn read_tree(hash: &str, path: &str) -> Result<Vec<TreeEntry>, Box<dyn Error>> {
+    let buffer = read_object(hash, path)?;
+    if object_type(&buffer) != "tree" {
+        return Err(format!("object {hash} is not a tree").into());
+    }
+    let (_, content) = split_object_header(buffer);
+    Ok(bincode::deserialize(&content)?)
+}
+
+/// Flatten a tree to every blob it contains, keyed by path relative to the tree
+pub fn tree_files(hash: &str, path: &str) -> Result<BTreeMap<String, TreeEntry>, Box<dyn Error>> {
+    let mut files: BTreeMap<String, TreeEntry> = BTreeMap::new();
+    let mut stack: Vec<(String, String)> = vec![(hash.to_owned(), String::new())];
+    while let Some((tree, prefix)) = stack.pop() {
+        for entry in read_tree(&tree, path)? {
+            let name = prefix.clone() + &String::from_utf8_lossy(&entry.name);
+            if entry.mode == DIR {
+                stack.push((hex::encode(entry.hash), name + "/"));
+            } else {
+                files.insert(name, entry);
+            }
+        }
+    }
+    Ok(files)
+}
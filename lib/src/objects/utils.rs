@@ -1,11 +1,14 @@
 use std::{
+    error::Error,
     fs::{self, File, read_dir},
-    io::Read,
+    io::{Read, Write},
     path::PathBuf,
     str::FromStr,
 };
 
+use flate2::{Compression, write::ZlibEncoder};
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ObjectHeader {
@@ -112,3 +115,42 @@ pub fn get_path_by_hash(hash: &[char]) -> String {
     let file_name: String = hash[2..].iter().collect::<String>().to_string();
     format!(".lrngit/objects/{folder_name}/{file_name}")
 }
+
+/// Read an object from the given repository path and return it decompressed, header included.
+/// Unlike `get_file_by_hash` a missing object is returned as an error.
+pub fn read_object(hash: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if hash.len() < 3 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid object name '{hash}'").into());
+    }
+    let object_path = format!("{path}/objects/{}/{}", &hash[..2], &hash[2..]);
+    let mut file = File::open(&object_path).map_err(|e| format!("object {hash} not found: {e}"))?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    let mut d = flate2::read::ZlibDecoder::new(buf.as_slice());
+    let mut buffer: Vec<u8> = Vec::new();
+    d.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Write an object of the given type in the given repository path and return its hash. The hash
+/// is the SHA-1 of the compressed object, header included.
+pub fn write_object(kind: &str, content: &[u8], path: &str) -> Result<String, Box<dyn Error>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(format!("{kind} {}\0", content.len()).as_bytes())?;
+    encoder.write_all(content)?;
+    let compressed = encoder.finish()?;
+    let hash = hex::encode(Sha1::digest(&compressed));
+    let dir = format!("{path}/objects/{}", &hash[..2]);
+    fs::create_dir_all(&dir)?;
+    fs::write(format!("{dir}/{}", &hash[2..]), compressed)?;
+    Ok(hash)
+}
+
+/// Return the type of a decompressed object, the first word of its header
+pub fn object_type(buffer: &[u8]) -> String {
+    let end = buffer
+        .iter()
+        .position(|x| *x == b' ' || *x == 0)
+        .unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..end]).to_string()
+}
@@ -6,10 +6,46 @@ pub struct ParsedRefsPack<'a> {
     pub origin_commit: &'a str,
 }
 
+/// Build the tags pack sent on push, one `<name> <hash>` line per tag
+pub fn build_tags_pack(tags: &[(String, String)]) -> Vec<u8> {
+    let mut buff: Vec<u8> = b"TAGS ".to_vec();
+    for (name, hash) in tags {
+        buff.extend_from_slice(format!("{name} {hash}\n").as_bytes());
+    }
+    buff
+}
+
+/// Parse tags pack content, the `TAGS ` magic excluded
+pub fn parse_tags_pack(buff: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(buff)
+        .lines()
+        .filter_map(|x| x.split_once(' '))
+        .map(|(name, hash)| (name.to_owned(), hash.to_owned()))
+        .collect()
+}
+
+/// Build the list of refs a repository advertises on fetch, one `<hash> <name>` line per ref
+pub fn build_advertised_refs(refs: &[(String, String)]) -> Vec<u8> {
+    let mut buff: Vec<u8> = b"LIST ".to_vec();
+    for (name, hash) in refs {
+        buff.extend_from_slice(format!("{hash} {name}\n").as_bytes());
+    }
+    buff
+}
+
+/// Parse the refs advertised on fetch, the `LIST ` magic excluded, as `(name, hash)` pairs
+pub fn parse_advertised_refs(buff: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(buff)
+        .lines()
+        .filter_map(|x| x.split_once(' '))
+        .map(|(hash, name)| (name.to_owned(), hash.to_owned()))
+        .collect()
+}
+
 /// Parse references pack from given bytes slice
 pub fn parse_refs_pack<'a>(buff: &'a [u8]) -> ParsedRefsPack<'a> {
     let refs_str: &str=
-        str::from_utf8(&buff).expect("Failed to cast buffer to owned string");
+        str::from_utf8(buff).expect("Failed to cast buffer to owned string");
     let split: Vec<&str> = refs_str.split(" ").collect();
     ParsedRefsPack {
         refs: split[0],
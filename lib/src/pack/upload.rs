@@ -1,4 +1,4 @@
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 
 use serde::{Deserialize, Serialize};
 
@@ -9,20 +9,63 @@ pub struct UploadPack {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ObjectsPackData {
+    /// Raw SHA-1 of the object. Clients before annotated tags sent the first 20 characters of
+    /// the hex hash instead, `object_name` reads both. A raw hash made only of hex digit bytes is
+    /// too unlikely to be confused with them.
     pub hash: [u8; 20],
     pub header: Vec<u8>,
     pub object_type: Vec<u8>,
     pub data: Vec<u8>,
 }
 
+impl ObjectsPackData {
+    /// Name the object is stored under, the hex hash. A hash sent in the old format gives the
+    /// truncated name those clients used.
+    pub fn object_name(&self) -> String {
+        if self.hash.iter().all(|x| x.is_ascii_hexdigit()) {
+            return String::from_utf8_lossy(&self.hash).into_owned();
+        }
+        hex::encode(self.hash)
+    }
+}
+
 /// Parse a slice of bytes and return an upload-pack
 pub fn parse_upload_pack(pack_slice: &[u8]) -> Result<UploadPack, Error> {
-    let parsed_pack: UploadPack = match bincode::deserialize(&pack_slice) {
+    let parsed_pack: UploadPack = match bincode::deserialize(pack_slice) {
         Ok(p) => p,
         Err(e) => {
             eprintln!("Error parsing upload pack: {e:?}");
-            return Err(Error::new(ErrorKind::Other, e));
+            return Err(Error::other(e));
         }
     };
     Ok(parsed_pack)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(hash: [u8; 20]) -> ObjectsPackData {
+        ObjectsPackData {
+            hash,
+            header: Vec::new(),
+            object_type: b"blob".to_vec(),
+            data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn object_name_from_raw_hash() {
+        let name = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let mut hash = [0u8; 20];
+        hex::decode_to_slice(name, &mut hash).unwrap();
+        assert_eq!(object(hash).object_name(), name);
+    }
+
+    #[test]
+    fn object_name_from_legacy_hash() {
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&b"e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"[..20]);
+        assert_eq!(object(hash).object_name(), "e69de29bb2d1d6434b8b");
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::Path,
+};
+
+/// Hash recorded as old value when a ref is created
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// One update of a ref, stored as a line of `logs/<ref>`:
+/// `<old> <new> <name> <<email>> <timestamp> <timezone>\t<message>`
+#[derive(Debug, Clone)]
+pub struct ReflogEntry {
+    pub old: String,
+    pub new: String,
+    pub name: String,
+    pub email: String,
+    pub timestamp: i64,
+    pub timezone: String,
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn to_line(&self) -> String {
+        // A new line in the message would break the file format
+        let message = self.message.lines().next().unwrap_or_default();
+        format!(
+            "{} {} {} <{}> {} {}\t{}\n",
+            self.old, self.new, self.name, self.email, self.timestamp, self.timezone, message
+        )
+    }
+
+    fn parse_line(line: &str) -> Option<ReflogEntry> {
+        let (identity, message) = line.split_once('\t')?;
+        let (old, rest) = identity.split_once(' ')?;
+        let (new, rest) = rest.split_once(' ')?;
+        let (name, rest) = rest.split_once(" <")?;
+        let (email, rest) = rest.split_once("> ")?;
+        let (timestamp, timezone) = rest.split_once(' ')?;
+        Some(ReflogEntry {
+            old: old.to_owned(),
+            new: new.to_owned(),
+            name: name.to_owned(),
+            email: email.to_owned(),
+            timestamp: timestamp.parse().ok()?,
+            timezone: timezone.to_owned(),
+            message: message.to_owned(),
+        })
+    }
+}
+
+/// Append an entry to the reflog of the given ref, `HEAD` or a full ref name
+pub fn append_reflog(repo_path: &str, refname: &str, entry: &ReflogEntry) -> io::Result<()> {
+    let path = format!("{repo_path}/logs/{refname}");
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(entry.to_line().as_bytes())
+}
+
+/// Read the reflog of the given ref, newest entry first. A ref without reflog has no entry.
+pub fn read_reflog(repo_path: &str, refname: &str) -> Result<Vec<ReflogEntry>, Box<dyn Error>> {
+    let content = match fs::read_to_string(format!("{repo_path}/logs/{refname}")) {
+        Ok(c) => c,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("failed to read reflog of {refname}: {e}").into()),
+    };
+    let mut entries: Vec<ReflogEntry> = Vec::new();
+    for line in content.lines().filter(|x| !x.is_empty()) {
+        let entry = ReflogEntry::parse_line(line)
+            .ok_or_else(|| format!("corrupted reflog entry for {refname}: {line}"))?;
+        entries.push(entry);
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+/// Check if a reflog exists for the given ref
+pub fn has_reflog(repo_path: &str, refname: &str) -> bool {
+    Path::new(&format!("{repo_path}/logs/{refname}")).is_file()
+}
+
+/// Replace the reflog of the given ref with the entries, newest first like `read_reflog`
+/// returns them. An empty list removes the reflog.
+pub fn write_reflog(repo_path: &str, refname: &str, entries: &[ReflogEntry]) -> io::Result<()> {
+    let path = format!("{repo_path}/logs/{refname}");
+    if entries.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let content: String = entries.iter().rev().map(|x| x.to_line()).collect();
+    let lock_path = format!("{path}.lock");
+    fs::write(&lock_path, content)?;
+    fs::rename(lock_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn entry(old: &str, new: &str, message: &str) -> ReflogEntry {
+        ReflogEntry {
+            old: old.to_owned(),
+            new: new.to_owned(),
+            name: String::from("Jane Q. Doe"),
+            email: String::from("jane@example.com"),
+            timestamp: 1_700_000_000,
+            timezone: String::from("+0200"),
+            message: message.to_owned(),
+        }
+    }
+
+    fn temp_repo(name: &str) -> String {
+        let path = env::temp_dir().join(format!("lrngit-reflog-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn line_round_trip() {
+        let line = entry(NULL_HASH, &"a".repeat(40), "commit (initial): first\tline").to_line();
+        assert_eq!(
+            line,
+            format!(
+                "{NULL_HASH} {} Jane Q. Doe <jane@example.com> 1700000000 +0200\tcommit (initial): first\tline\n",
+                "a".repeat(40)
+            )
+        );
+        let parsed = ReflogEntry::parse_line(line.trim_end()).unwrap();
+        assert_eq!(parsed.to_line(), line);
+        assert_eq!(parsed.name, "Jane Q. Doe");
+        assert_eq!(parsed.message, "commit (initial): first\tline");
+        assert!(ReflogEntry::parse_line("not a reflog line").is_none());
+    }
+
+    #[test]
+    fn message_keeps_first_line() {
+        let line = entry(NULL_HASH, NULL_HASH, "subject\n\nbody").to_line();
+        assert!(line.ends_with("\tsubject\n"));
+    }
+
+    #[test]
+    fn read_newest_first_and_rewrite() {
+        let repo = temp_repo("rewrite");
+        let refname = "refs/heads/main";
+        assert!(read_reflog(&repo, refname).unwrap().is_empty());
+        assert!(!has_reflog(&repo, refname));
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        append_reflog(&repo, refname, &entry(NULL_HASH, &a, "first")).unwrap();
+        append_reflog(&repo, refname, &entry(&a, &b, "second")).unwrap();
+        let entries = read_reflog(&repo, refname).unwrap();
+        let messages: Vec<&str> = entries.iter().map(|x| x.message.as_str()).collect();
+        assert_eq!(messages, ["second", "first"]);
+        // Entries are given back newest first, dropping the newest keeps the first one
+        write_reflog(&repo, refname, &entries[1..]).unwrap();
+        let entries = read_reflog(&repo, refname).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].new, a);
+        write_reflog(&repo, refname, &[]).unwrap();
+        assert!(!has_reflog(&repo, refname));
+        write_reflog(&repo, refname, &[]).unwrap();
+    }
+
+    #[test]
+    fn corrupted_entry_is_an_error() {
+        let repo = temp_repo("corrupted");
+        fs::create_dir_all(format!("{repo}/logs")).unwrap();
+        fs::write(format!("{repo}/logs/HEAD"), "garbage\n").unwrap();
+        assert!(read_reflog(&repo, "HEAD").is_err());
+    }
+}
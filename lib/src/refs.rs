@@ -0,0 +1,149 @@
+use std::{
+    error::Error,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+// Symbolic refs pointing to symbolic refs are followed up to this depth
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Read a ref relative to the repository path and follow symbolic refs (`ref: refs/heads/main`).
+/// Return None if the ref doesn't exist or doesn't point to any commit yet.
+pub fn read_ref(repo_path: &str, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let mut name = name.to_owned();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        let content = match fs::read_to_string(format!("{repo_path}/{name}")) {
+            Ok(c) => c,
+            Err(e) if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::IsADirectory => {
+                return Ok(None);
+            }
+            Err(e) => return Err(format!("failed to read ref {name}: {e}").into()),
+        };
+        let content = content.trim();
+        match content.strip_prefix("ref: ") {
+            Some(target) => name = target.to_owned(),
+            None if content.is_empty() => return Ok(None),
+            None => return Ok(Some(content.to_owned())),
+        }
+    }
+    Err(format!("too many levels of symbolic refs for {name}").into())
+}
+
+/// Write a ref, parent directories are created and the file is replaced atomically so readers
+/// never see an empty ref
+pub fn write_ref(repo_path: &str, name: &str, hash: &str) -> io::Result<()> {
+    let path = format!("{repo_path}/{name}");
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let lock_path = format!("{path}.lock");
+    fs::write(&lock_path, hash)?;
+    fs::rename(lock_path, path)
+}
+
+/// Delete a ref and its reflog. Directories left empty by nested refs like
+/// `refs/heads/feature/login` are removed so the name can be used by a ref again.
+pub fn delete_ref(repo_path: &str, name: &str) -> io::Result<()> {
+    fs::remove_file(format!("{repo_path}/{name}"))?;
+    remove_empty_parents(repo_path, name);
+    match fs::remove_file(format!("{repo_path}/logs/{name}")) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => {
+            remove_empty_parents(&format!("{repo_path}/logs"), name);
+            Ok(())
+        }
+    }
+}
+
+/// Rename a ref, its reflog is moved along. Fail if the new ref already exists.
+pub fn rename_ref(repo_path: &str, old: &str, new: &str) -> Result<(), Box<dyn Error>> {
+    let Some(hash) = read_ref(repo_path, old)? else {
+        return Err(format!("ref {old} doesn't exist").into());
+    };
+    if Path::new(&format!("{repo_path}/{new}")).is_file() {
+        return Err(format!("ref {new} already exists").into());
+    }
+    let log = match fs::read(format!("{repo_path}/logs/{old}")) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("failed to read reflog of {old}: {e}").into()),
+    };
+    // The old ref is removed first, `a` can be renamed `a/b` once the file is gone
+    delete_ref(repo_path, old)?;
+    if let Err(e) = write_ref(repo_path, new, &hash) {
+        write_ref(repo_path, old, &hash)?;
+        return Err(format!("failed to write ref {new}: {e}").into());
+    }
+    if let Some(content) = log {
+        let path = format!("{repo_path}/logs/{new}");
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// Remove the directories of a ref path left empty, up to the `refs/<kind>` directory
+fn remove_empty_parents(base: &str, name: &str) {
+    let mut path = Path::new(name).parent();
+    while let Some(dir) = path {
+        if dir.components().count() <= 2 || fs::remove_dir(Path::new(base).join(dir)).is_err() {
+            break;
+        }
+        path = dir.parent();
+    }
+}
+
+/// Check if a ref name is valid: HEAD or a path under `refs/` without empty, `.` or `..`
+/// components and none of the characters git forbids
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name == "HEAD" {
+        return true;
+    }
+    let Some(rest) = name.strip_prefix("refs/") else {
+        return false;
+    };
+    !rest.is_empty()
+        && rest.split('/').all(|x| {
+            !x.is_empty() && !x.starts_with('.') && !x.ends_with(".lock") && !x.ends_with('.')
+        })
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_control() || " ~^:?*[\\".contains(c))
+}
+
+/// Return the ref HEAD points to, None when HEAD is detached on a commit
+pub fn head_ref(repo_path: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let content = fs::read_to_string(format!("{repo_path}/HEAD"))
+        .map_err(|e| format!("failed to read HEAD: {e}"))?;
+    Ok(content.trim().strip_prefix("ref: ").map(|x| x.to_owned()))
+}
+
+/// List the refs under the given prefix, like `refs/heads`, with the commit they point to.
+/// Refs not pointing to any commit yet are skipped.
+pub fn list_refs(repo_path: &str, prefix: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut refs: Vec<(String, String)> = Vec::new();
+    let mut stack: Vec<String> = vec![prefix.trim_end_matches('/').to_owned()];
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(format!("{repo_path}/{dir}")) {
+            Ok(e) => e,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("failed to read {dir}: {e}").into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                stack.push(name);
+            } else if let Some(hash) = read_ref(repo_path, &name)? {
+                refs.push((name, hash));
+            }
+        }
+    }
+    refs.sort();
+    Ok(refs)
+}
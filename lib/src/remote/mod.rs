@@ -1,4 +1,20 @@
-pub mod origin;
+use std::{error::Error, path::Path};
+
+use crate::{
+    config::{Config, rename_section},
+    refs::read_ref,
+};
+
+pub mod refspec;
+
+use refspec::{Refspec, apply_refspecs, parse_refspecs};
+
+/// Remote used when none is given on the command line
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// Section holding the only remote before named remotes, `[remote]` with `url` and `fetch`. It
+/// is read as the origin remote.
+const LEGACY_SECTION: &str = "remote";
 
 #[derive(Debug)]
 pub struct RemoteAddr {
@@ -7,9 +23,272 @@ pub struct RemoteAddr {
     pub path: String,
 }
 
-pub fn parse_local_config_url(url: &str) -> RemoteAddr {
-    let protocol: Vec<&str> = url.split("://").collect();
-    let url: Vec<&str> = protocol[1].split("/").collect();
+/// A tracking ref a fetch updates, from a ref advertised by the remote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchUpdate {
+    pub remote_ref: String,
+    pub tracking_ref: String,
+    /// Commit of the tracking ref before the fetch, empty if it doesn't exist
+    pub old: String,
+    pub new: String,
+    /// The fetch refspec allows an update that isn't a fast-forward
+    pub force: bool,
+}
+
+/// A remote repository, from a `[remote "<name>"]` section of the config
+#[derive(Debug, Clone)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+    /// Refspecs mapping the branches of the remote to local refs
+    pub fetch: Vec<String>,
+}
+
+impl Remote {
+    /// Directory holding the remote tracking refs of the remote
+    pub fn refs_prefix(&self) -> String {
+        format!("refs/remotes/{}", self.name)
+    }
+
+    /// Parsed fetch refspecs of the remote
+    pub fn fetch_refspecs(&self) -> Result<Vec<Refspec>, Box<dyn Error>> {
+        parse_refspecs(&self.fetch)
+            .map_err(|e| format!("bad fetch refspec of remote {}: {e}", self.name).into())
+    }
+
+    /// Ref of the remote a tracking ref follows, the reverse of `tracking_ref`
+    pub fn remote_ref(&self, tracking_ref: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let refspecs = self.fetch_refspecs()?;
+        Ok(refspecs
+            .iter()
+            .filter(|x| !x.negative)
+            .find_map(|x| x.map_back(tracking_ref))
+            .filter(|x| apply_refspecs(&refspecs, x).is_some()))
+    }
+
+    /// Local ref a fetch updates for a ref of the remote, like `refs/remotes/origin/main` for
+    /// `refs/heads/main`. None if the fetch refspecs don't map the ref.
+    pub fn tracking_ref(&self, remote_ref: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(apply_refspecs(&self.fetch_refspecs()?, remote_ref).map(|(dst, _)| dst))
+    }
+
+    /// Tracking refs to update for the refs advertised by the remote, as `(name, hash)` pairs.
+    /// Refs the fetch refspecs don't map and tracking refs already up to date are left out.
+    pub fn fetch_updates(
+        &self,
+        repo_path: &str,
+        advertised: &[(String, String)],
+    ) -> Result<Vec<FetchUpdate>, Box<dyn Error>> {
+        let refspecs = self.fetch_refspecs()?;
+        let mut updates: Vec<FetchUpdate> = Vec::new();
+        for (name, hash) in advertised {
+            let Some((tracking_ref, force)) = apply_refspecs(&refspecs, name) else {
+                continue;
+            };
+            let old = read_ref(repo_path, &tracking_ref)?.unwrap_or_default();
+            if old == *hash {
+                continue;
+            }
+            updates.push(FetchUpdate {
+                remote_ref: name.to_owned(),
+                tracking_ref,
+                old,
+                new: hash.to_owned(),
+                force,
+            });
+        }
+        Ok(updates)
+    }
+}
+
+/// Upstream of a local branch like `refs/heads/main`, from the `branch.<name>.remote` and
+/// `branch.<name>.merge` config. It is the tracking ref the fetch refspecs of the remote map the
+/// merged branch to, or the merged branch itself for the `.` remote, the local repository.
+/// None if the branch has no upstream.
+pub fn upstream_ref(repo_path: &str, branch: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let short = branch.strip_prefix("refs/heads/").unwrap_or(branch);
+    let config = Config::load(repo_path);
+    let (Some(name), Some(merge)) = (
+        config.get(&format!("branch.{short}.remote")),
+        config.get(&format!("branch.{short}.merge")),
+    ) else {
+        return Ok(None);
+    };
+    if name == "." {
+        return Ok(Some(merge.to_owned()));
+    }
+    let remote = find_remote(&config, name)
+        .ok_or_else(|| format!("branch {short} tracks the remote {name} which doesn't exist"))?;
+    remote.tracking_ref(merge)?.map(Some).ok_or_else(|| {
+        format!("{merge} of remote {name} isn't stored as a remote tracking branch").into()
+    })
+}
+
+/// Default fetch refspec of a remote, every branch is tracked under `refs/remotes/<name>/`
+pub fn default_fetch(name: &str) -> String {
+    format!("+refs/heads/*:refs/remotes/{name}/*")
+}
+
+/// List the remotes of the config in the order they are first read. A remote without url is
+/// skipped.
+pub fn list_remotes(config: &Config) -> Vec<Remote> {
+    let mut names: Vec<&str> = Vec::new();
+    for entry in config.entries() {
+        let name = match entry.key.strip_prefix("remote.") {
+            Some("url") => Some(DEFAULT_REMOTE),
+            Some(key) => key.rsplit_once('.').map(|(name, _)| name),
+            None => None,
+        };
+        let Some(name) = name else {
+            continue;
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+        .into_iter()
+        .filter_map(|x| find_remote(config, x))
+        .collect()
+}
+
+/// Get a remote by name, None if it has no url. Origin falls back to the legacy `[remote]`
+/// section.
+pub fn find_remote(config: &Config, name: &str) -> Option<Remote> {
+    let section = match config.get(&format!("remote.{name}.url")) {
+        Some(_) => format!("remote.{name}"),
+        None if name == DEFAULT_REMOTE => LEGACY_SECTION.to_owned(),
+        None => return None,
+    };
+    let url = config.get(&format!("{section}.url"))?.to_owned();
+    if url.is_empty() {
+        return None;
+    }
+    let mut fetch: Vec<String> = config
+        .get_all(&format!("{section}.fetch"))
+        .into_iter()
+        .map(|x| x.to_owned())
+        .collect();
+    if fetch.is_empty() {
+        fetch.push(default_fetch(name));
+    }
+    Some(Remote {
+        name: name.to_owned(),
+        url,
+        fetch,
+    })
+}
+
+/// Rename the legacy `[remote]` section of a config file to `[remote "origin"]`, so the origin
+/// remote can be edited like the others. Return false if there was nothing to migrate.
+pub fn migrate_legacy_remote(path: &Path) -> Result<bool, Box<dyn Error>> {
+    let config = Config::load_file(path);
+    if config.get(&format!("{LEGACY_SECTION}.url")).is_none()
+        || config.get(&format!("remote.{DEFAULT_REMOTE}.url")).is_some()
+    {
+        return Ok(false);
+    }
+    rename_section(path, LEGACY_SECTION, &format!("remote.{DEFAULT_REMOTE}"))
+}
+
+/// Check if a remote name can be used: a single valid ref component, so its tracking refs live
+/// in `refs/remotes/<name>/`
+pub fn is_valid_remote_name(name: &str) -> bool {
+    !name.contains('/') && crate::refs::is_valid_ref_name(&format!("refs/remotes/{name}"))
+}
+
+/// Split a remote url like `lrngit://host:port/repo` in its protocol, address and path
+pub fn parse_local_config_url(url: &str) -> Result<RemoteAddr, Box<dyn Error>> {
+    let Some((protocol, rest)) = url.split_once("://") else {
+        return Err(format!("invalid remote url '{url}'").into());
+    };
+    let url: Vec<&str> = rest.split("/").collect();
     let repository_path: String = url[1..].join("/");
-    RemoteAddr { protocol: protocol[0].to_owned(), url: url[0].to_owned(), path: repository_path }
+    Ok(RemoteAddr {
+        protocol: protocol.to_owned(),
+        url: url[0].to_owned(),
+        path: repository_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("lrngit-remote-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config");
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn legacy_section_is_origin() {
+        let path = config_file("legacy", "[remote]\nurl = lrngit://host/x\n");
+        let config = Config::load_file(&path);
+        let remote = find_remote(&config, DEFAULT_REMOTE).unwrap();
+        assert_eq!(remote.url, "lrngit://host/x");
+        assert_eq!(remote.fetch, [default_fetch(DEFAULT_REMOTE)]);
+        assert!(find_remote(&config, "upstream").is_none());
+        let names: Vec<String> = list_remotes(&config).into_iter().map(|x| x.name).collect();
+        assert_eq!(names, [DEFAULT_REMOTE]);
+    }
+
+    #[test]
+    fn named_origin_wins_over_legacy() {
+        let path = config_file(
+            "named",
+            "[remote]\nurl = lrngit://old/x\n[remote \"origin\"]\nurl = lrngit://new/x\n",
+        );
+        let config = Config::load_file(&path);
+        assert_eq!(find_remote(&config, DEFAULT_REMOTE).unwrap().url, "lrngit://new/x");
+        assert_eq!(list_remotes(&config).len(), 1);
+        assert!(!migrate_legacy_remote(&path).unwrap());
+    }
+
+    #[test]
+    fn fetch_updates_follow_refspecs() {
+        let path = config_file(
+            "fetch",
+            "[remote \"origin\"]\nurl = lrngit://host/x\nfetch = +refs/heads/*:refs/remotes/origin/*\nfetch = ^refs/heads/wip\n",
+        );
+        let repo = path.parent().unwrap().to_str().unwrap();
+        let (old, new) = ("a".repeat(40), "b".repeat(40));
+        fs::create_dir_all(format!("{repo}/refs/remotes/origin")).unwrap();
+        fs::write(format!("{repo}/refs/remotes/origin/main"), &old).unwrap();
+        fs::write(format!("{repo}/refs/remotes/origin/dev"), &new).unwrap();
+        let remote = find_remote(&Config::load_file(&path), DEFAULT_REMOTE).unwrap();
+        let advertised: Vec<(String, String)> = ["main", "dev", "wip", "topic"]
+            .iter()
+            .map(|x| (format!("refs/heads/{x}"), new.clone()))
+            .chain([(String::from("refs/tags/v1"), new.clone())])
+            .collect();
+        let updates = remote.fetch_updates(repo, &advertised).unwrap();
+        let summary: Vec<(&str, &str)> = updates
+            .iter()
+            .map(|x| (x.tracking_ref.as_str(), x.old.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("refs/remotes/origin/main", old.as_str()),
+                ("refs/remotes/origin/topic", "")
+            ]
+        );
+        assert!(updates.iter().all(|x| x.force && x.new == new));
+    }
+
+    #[test]
+    fn migrate_legacy_section() {
+        let path = config_file("migrate", "[remote]\nurl = lrngit://host/x\n");
+        assert!(migrate_legacy_remote(&path).unwrap());
+        let config = Config::load_file(&path);
+        assert_eq!(config.get("remote.origin.url"), Some("lrngit://host/x"));
+        assert_eq!(config.get("remote.url"), None);
+        assert!(!migrate_legacy_remote(&path).unwrap());
+    }
 }
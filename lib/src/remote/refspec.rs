@@ -0,0 +1,196 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use crate::refs::is_valid_ref_name;
+
+/// A refspec like `+refs/heads/*:refs/remotes/origin/*`, mapping refs of a source to refs of a
+/// destination. The `+` allows updates that aren't fast-forwards, a `*` matches any part of a ref
+/// name and is substituted in the destination. A negative refspec `^refs/heads/wip*` excludes the
+/// refs it matches from the other refspecs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub negative: bool,
+    pub src: String,
+    /// None for a refspec without `:`, like a negative one or `main` given to push
+    pub dst: Option<String>,
+}
+
+impl FromStr for Refspec {
+    type Err = Box<dyn Error>;
+
+    fn from_str(spec: &str) -> Result<Refspec, Self::Err> {
+        let (negative, rest) = match spec.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let (force, rest) = match rest.strip_prefix('+') {
+            Some(rest) if !negative => (true, rest),
+            _ => (false, rest),
+        };
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, dst)) => (src, Some(dst)),
+            None => (rest, None),
+        };
+        let invalid = || format!("invalid refspec '{spec}'");
+        if negative && (dst.is_some() || src.is_empty()) {
+            return Err(format!("{}, a negative refspec has no destination", invalid()).into());
+        }
+        let globs = src.matches('*').count();
+        if globs > 1 || dst.is_some_and(|x| x.matches('*').count() != globs) {
+            return Err(invalid().into());
+        }
+        // Short names like `main` are allowed, they are completed by the command using them
+        let valid =
+            |x: &str| x.is_empty() || is_valid_ref_name(&format!("refs/{}", x.replace('*', "x")));
+        if !valid(src) || !dst.is_none_or(valid) {
+            return Err(invalid().into());
+        }
+        Ok(Refspec {
+            force,
+            negative,
+            src: src.to_owned(),
+            dst: dst.map(|x| x.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Refspec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match (self.negative, self.force) {
+            (true, _) => "^",
+            (false, true) => "+",
+            (false, false) => "",
+        };
+        match &self.dst {
+            Some(dst) => write!(f, "{prefix}{}:{dst}", self.src),
+            None => write!(f, "{prefix}{}", self.src),
+        }
+    }
+}
+
+impl Refspec {
+    pub fn is_glob(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// Check if a ref name matches the source of the refspec
+    pub fn matches(&self, name: &str) -> bool {
+        glob_match(&self.src, name).is_some()
+    }
+
+    /// Map a ref matching the source to its destination, the part matched by `*` replaces the
+    /// `*` of the destination. A refspec without destination maps a ref to itself.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let matched = glob_match(&self.src, name)?;
+        Some(match &self.dst {
+            Some(dst) => dst.replacen('*', matched, 1),
+            None => name.to_owned(),
+        })
+    }
+
+    /// Map a ref matching the destination back to its source, the reverse of `map`
+    pub fn map_back(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_deref().unwrap_or(&self.src);
+        let matched = glob_match(dst, name)?;
+        Some(self.src.replacen('*', matched, 1))
+    }
+}
+
+/// Part of the name matched by the `*` of a pattern, empty for an exact match
+fn glob_match<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => name
+            .strip_prefix(prefix)
+            .and_then(|x| x.strip_suffix(suffix))
+            .filter(|x| !x.is_empty()),
+        None => (pattern == name).then_some(""),
+    }
+}
+
+/// Map a ref through a list of refspecs, the first positive refspec matching it gives the
+/// destination and whether the update is forced. None if no refspec matches or a negative one
+/// excludes the ref.
+pub fn apply_refspecs(refspecs: &[Refspec], name: &str) -> Option<(String, bool)> {
+    if refspecs.iter().any(|x| x.negative && x.matches(name)) {
+        return None;
+    }
+    refspecs
+        .iter()
+        .filter(|x| !x.negative)
+        .find_map(|x| x.map(name).map(|dst| (dst, x.force)))
+}
+
+/// Parse a list of refspecs, like the `fetch` values of a remote
+pub fn parse_refspecs<S: AsRef<str>>(specs: &[S]) -> Result<Vec<Refspec>, Box<dyn Error>> {
+    specs.iter().map(|x| x.as_ref().parse()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refspec(spec: &str) -> Refspec {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_force_and_display() {
+        let spec = refspec("+refs/heads/*:refs/remotes/origin/*");
+        assert!(spec.force);
+        assert!(!spec.negative);
+        assert_eq!(spec.src, "refs/heads/*");
+        assert_eq!(spec.dst.as_deref(), Some("refs/remotes/origin/*"));
+        assert_eq!(spec.to_string(), "+refs/heads/*:refs/remotes/origin/*");
+        assert!(!refspec("main:main").force);
+    }
+
+    #[test]
+    fn glob_maps_both_ways() {
+        let spec = refspec("refs/heads/*:refs/remotes/origin/*");
+        assert!(spec.is_glob());
+        assert_eq!(
+            spec.map("refs/heads/feature/x").as_deref(),
+            Some("refs/remotes/origin/feature/x")
+        );
+        assert_eq!(spec.map("refs/tags/v1"), None);
+        // The glob must match at least one character
+        assert_eq!(spec.map("refs/heads/"), None);
+        assert_eq!(
+            spec.map_back("refs/remotes/origin/main").as_deref(),
+            Some("refs/heads/main")
+        );
+        assert_eq!(spec.map_back("refs/remotes/upstream/main"), None);
+    }
+
+    #[test]
+    fn missing_colon_maps_to_itself() {
+        let spec = refspec("refs/heads/main");
+        assert_eq!(spec.dst, None);
+        assert_eq!(spec.map("refs/heads/main").as_deref(), Some("refs/heads/main"));
+        assert_eq!(spec.map("refs/heads/mainline"), None);
+        assert_eq!(spec.to_string(), "refs/heads/main");
+    }
+
+    #[test]
+    fn glob_on_one_side_is_invalid() {
+        assert!("refs/heads/*:refs/remotes/origin/main".parse::<Refspec>().is_err());
+        assert!("refs/heads/main:refs/remotes/origin/*".parse::<Refspec>().is_err());
+        assert!("refs/*/*:refs/remotes/*/*".parse::<Refspec>().is_err());
+        assert!("refs/heads/a..b:x".parse::<Refspec>().is_err());
+    }
+
+    #[test]
+    fn negative_refspecs_exclude() {
+        assert!("^refs/heads/wip:x".parse::<Refspec>().is_err());
+        let specs = parse_refspecs(&["+refs/heads/*:refs/remotes/origin/*", "^refs/heads/wip*"])
+            .unwrap();
+        assert!(specs[1].negative);
+        assert!(!specs[1].force);
+        assert_eq!(
+            apply_refspecs(&specs, "refs/heads/main"),
+            Some((String::from("refs/remotes/origin/main"), true))
+        );
+        assert_eq!(apply_refspecs(&specs, "refs/heads/wip-1"), None);
+        assert_eq!(apply_refspecs(&specs, "refs/tags/v1"), None);
+    }
+}
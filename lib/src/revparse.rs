@@ -0,0 +1,374 @@
+use std::{error::Error, fs};
+
+use crate::{
+    objects::{
+        commit::{commit_parents, read_commit},
+        tag::read_tag,
+        tree::{DIR, read_tree},
+        utils::{object_type, read_object},
+    },
+    reflog::{has_reflog, read_reflog},
+    refs::{head_ref, read_ref},
+    remote::upstream_ref,
+};
+
+// Shortest abbreviated object name accepted
+const MIN_ABBREV: usize = 4;
+
+/// Resolve a revision to an object hash.
+///
+/// Supported syntax:
+/// - full or abbreviated object names, at least 4 hex characters
+/// - `HEAD`, `@`, branch, tag and remote names or any ref under `refs/`
+/// - `<rev>@{upstream}`, `<rev>@{u}` the remote branch tracked by a branch, current one if empty
+/// - `<ref>@{n}` the value of a ref n updates ago, from its reflog, current branch if empty
+/// - `<rev>~n` the n-th first parent, `<rev>^n` the n-th parent, `^` and `~` alone mean 1
+/// - `<rev>^{commit}`, `<rev>^{tree}` and `<rev>^{tag}` to peel to the given object type,
+///   `<rev>^{}` to peel tags until a non tag object
+/// - `<rev>:<path>` the blob or tree at the given path of a commit
+pub fn resolve(repo_path: &str, spec: &str) -> Result<String, Box<dyn Error>> {
+    if let Some((rev, path)) = spec.split_once(':') {
+        if rev.is_empty() {
+            return Err(format!("index paths are not supported: '{spec}'").into());
+        }
+        let tree = peel(repo_path, &resolve(repo_path, rev)?, "tree")?;
+        return tree_path(repo_path, &tree, path)
+            .ok_or_else(|| format!("path '{path}' does not exist in '{rev}'").into());
+    }
+    let (base, mut suffix) = split_suffix(spec);
+    let mut hash = resolve_name(repo_path, base)?;
+    while !suffix.is_empty() {
+        let op = suffix.as_bytes()[0];
+        suffix = &suffix[1..];
+        if op == b'^' && suffix.starts_with('{') {
+            let end = suffix
+                .find('}')
+                .ok_or_else(|| format!("invalid revision '{spec}'"))?;
+            let kind = &suffix[1..end];
+            suffix = &suffix[end + 1..];
+            hash = match kind {
+                "commit" | "tree" | "tag" => peel(repo_path, &hash, kind)?,
+                "" => peel_tags(repo_path, &hash)?,
+                _ => return Err(format!("unknown object type '{kind}' in '{spec}'").into()),
+            };
+            continue;
+        }
+        let digits = suffix.bytes().take_while(|x| x.is_ascii_digit()).count();
+        let n: usize = if digits == 0 {
+            1
+        } else {
+            suffix[..digits].parse()?
+        };
+        suffix = &suffix[digits..];
+        hash = peel(repo_path, &hash, "commit")?;
+        if op == b'~' {
+            for _ in 0..n {
+                hash = nth_parent(repo_path, &hash, 1, spec)?;
+            }
+        } else if n > 0 {
+            hash = nth_parent(repo_path, &hash, n, spec)?;
+        }
+    }
+    Ok(hash)
+}
+
+/// Resolve a revision and make sure it names a commit
+pub fn resolve_commit(repo_path: &str, spec: &str) -> Result<String, Box<dyn Error>> {
+    let hash = resolve(repo_path, spec)?;
+    peel(repo_path, &hash, "commit")
+}
+
+/// Resolve a revision to a tree, a commit is peeled to its root tree
+pub fn resolve_tree(repo_path: &str, spec: &str) -> Result<String, Box<dyn Error>> {
+    let hash = resolve(repo_path, spec)?;
+    peel(repo_path, &hash, "tree")
+}
+
+/// Split `name~2^` into the name and the `~`/`^` operators applied to it
+fn split_suffix(spec: &str) -> (&str, &str) {
+    // Skip `@{...}` so the operators inside braces aren't taken as suffix
+    let start = spec.find("@{").unwrap_or(0);
+    match spec[start..].find(['~', '^']) {
+        Some(pos) => spec.split_at(start + pos),
+        None => (spec, ""),
+    }
+}
+
+/// Resolve a name without `~`/`^` operators to a hash
+fn resolve_name(repo_path: &str, name: &str) -> Result<String, Box<dyn Error>> {
+    if let Some((branch, reflog)) = name.split_once("@{") {
+        let selector = reflog
+            .strip_suffix('}')
+            .ok_or_else(|| format!("invalid revision '{name}'"))?;
+        return match selector {
+            "upstream" | "u" => {
+                let branch = branch_ref(repo_path, branch)?;
+                let upstream = upstream_ref(repo_path, &branch)?
+                    .ok_or_else(|| format!("no upstream configured for {branch}"))?;
+                read_ref(repo_path, &upstream)?.ok_or_else(|| {
+                    format!("no upstream commit for {branch}, {upstream} is empty").into()
+                })
+            }
+            _ => match selector.parse::<usize>() {
+                Ok(n) => reflog_nth(repo_path, branch, n),
+                Err(_) => Err(format!("unknown selector '@{{{selector}}}' in '{name}'").into()),
+            },
+        };
+    }
+    let name = if name == "@" || name.is_empty() {
+        "HEAD"
+    } else {
+        name
+    };
+    if name.len() == 40 && is_hex(name) {
+        return Ok(name.to_owned());
+    }
+    for candidate in ref_candidates(name) {
+        if let Some(hash) = read_ref(repo_path, &candidate)? {
+            return Ok(hash);
+        }
+    }
+    if name == "HEAD" {
+        return Err("HEAD does not point to any commit yet".into());
+    }
+    if name.len() >= MIN_ABBREV && is_hex(name) {
+        return resolve_abbrev(repo_path, name);
+    }
+    Err(format!("unknown revision '{name}'").into())
+}
+
+/// Refs tried in order for a short name, like `main` for `refs/heads/main`
+fn ref_candidates(name: &str) -> Vec<String> {
+    if name == "HEAD" || name.starts_with("refs/") {
+        return vec![name.to_owned()];
+    }
+    vec![
+        format!("refs/{name}"),
+        format!("refs/tags/{name}"),
+        format!("refs/heads/{name}"),
+        format!("refs/remotes/{name}"),
+        format!("refs/remotes/{name}/HEAD"),
+    ]
+}
+
+/// Value of a ref n updates ago
+fn reflog_nth(repo_path: &str, name: &str, n: usize) -> Result<String, Box<dyn Error>> {
+    let refname = if name.is_empty() {
+        branch_ref(repo_path, name)?
+    } else if name == "@" {
+        "HEAD".to_owned()
+    } else {
+        ref_candidates(name)
+            .into_iter()
+            .find(|x| has_reflog(repo_path, x))
+            .ok_or_else(|| format!("no reflog for '{name}'"))?
+    };
+    let entries = read_reflog(repo_path, &refname)?;
+    match entries.get(n) {
+        Some(entry) => Ok(entry.new.clone()),
+        None => Err(format!("log for '{refname}' only has {} entries", entries.len()).into()),
+    }
+}
+
+/// Full ref of a branch name, the current branch if the name is empty or HEAD
+fn branch_ref(repo_path: &str, branch: &str) -> Result<String, Box<dyn Error>> {
+    if branch.is_empty() || branch == "HEAD" || branch == "@" {
+        return head_ref(repo_path)?.ok_or_else(|| "HEAD is not on a branch".into());
+    }
+    if branch.starts_with("refs/heads/") {
+        return Ok(branch.to_owned());
+    }
+    Ok(format!("refs/heads/{branch}"))
+}
+
+/// Find the only object whose hash starts with the given prefix
+fn resolve_abbrev(repo_path: &str, prefix: &str) -> Result<String, Box<dyn Error>> {
+    let prefix = prefix.to_ascii_lowercase();
+    let mut found: Vec<String> = Vec::new();
+    if let Ok(dir) = fs::read_dir(format!("{repo_path}/objects/{}", &prefix[..2])) {
+        for entry in dir.flatten() {
+            let file_name = entry.file_name();
+            let Some(rest) = file_name.to_str() else {
+                continue;
+            };
+            if rest.starts_with(&prefix[2..]) {
+                found.push(format!("{}{rest}", &prefix[..2]));
+            }
+        }
+    }
+    match found.len() {
+        0 => Err(format!("unknown revision '{prefix}'").into()),
+        1 => Ok(found.remove(0)),
+        _ => {
+            found.sort();
+            Err(format!(
+                "short object ID {prefix} is ambiguous, candidates are:\n  {}",
+                found.join("\n  ")
+            )
+            .into())
+        }
+    }
+}
+
+/// Peel an object to the wanted type, tags are followed to the object they point to and a
+/// commit can be peeled to its tree
+pub fn peel(repo_path: &str, hash: &str, kind: &str) -> Result<String, Box<dyn Error>> {
+    let mut hash = hash.to_owned();
+    loop {
+        let found = object_type(&read_object(&hash, repo_path)?);
+        if found == kind {
+            return Ok(hash);
+        }
+        match found.as_str() {
+            "tag" => hash = hex::encode(read_tag(&hash, repo_path)?.object),
+            "commit" if kind == "tree" => {
+                return Ok(hex::encode(read_commit(&hash, repo_path)?.tree));
+            }
+            _ => return Err(format!("object {hash} is a {found}, not a {kind}").into()),
+        }
+    }
+}
+
+/// Follow tags until an object which isn't a tag
+pub fn peel_tags(repo_path: &str, hash: &str) -> Result<String, Box<dyn Error>> {
+    let mut hash = hash.to_owned();
+    while object_type(&read_object(&hash, repo_path)?) == "tag" {
+        hash = hex::encode(read_tag(&hash, repo_path)?.object);
+    }
+    Ok(hash)
+}
+
+fn nth_parent(repo_path: &str, hash: &str, n: usize, spec: &str) -> Result<String, Box<dyn Error>> {
+    let commit = read_commit(hash, repo_path)?;
+    commit_parents(&commit)
+        .into_iter()
+        .nth(n - 1)
+        .ok_or_else(|| format!("revision '{spec}' goes past the history of {hash}").into())
+}
+
+/// Walk a tree down to the given path, an empty path is the tree itself
+fn tree_path(repo_path: &str, tree: &str, path: &str) -> Option<String> {
+    let mut hash = tree.to_owned();
+    let mut is_tree = true;
+    for name in path.split('/').filter(|x| !x.is_empty()) {
+        // Nothing can be inside a blob
+        if !is_tree {
+            return None;
+        }
+        let entries = read_tree(&hash, repo_path).ok()?;
+        let entry = entries.into_iter().find(|x| x.name == name.as_bytes())?;
+        hash = hex::encode(entry.hash);
+        is_tree = entry.mode == DIR;
+    }
+    Some(hash)
+}
+
+fn is_hex(s: &str) -> bool {
+    s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reflog::{NULL_HASH, ReflogEntry, append_reflog},
+        testing::TestRepo,
+    };
+
+    /// `c1 - c2 - c3` on main, checked out, with a tag on c1
+    fn history(name: &str) -> (TestRepo, Vec<String>) {
+        let repo = TestRepo::new(name);
+        let commits = repo.chain(None, 100, &["c1", "c2", "c3"]);
+        repo.set_ref("refs/heads/main", &commits[2]);
+        repo.set_ref("refs/tags/v1", &commits[0]);
+        fs::write(format!("{}/HEAD", repo.path), "ref: refs/heads/main").unwrap();
+        (repo, commits)
+    }
+
+    #[test]
+    fn split_operators() {
+        assert_eq!(split_suffix("main"), ("main", ""));
+        assert_eq!(split_suffix("main~2^"), ("main", "~2^"));
+        assert_eq!(split_suffix("HEAD^{tree}"), ("HEAD", "^{tree}"));
+        assert_eq!(split_suffix("main@{1}~1"), ("main@{1}", "~1"));
+    }
+
+    #[test]
+    fn names_and_hashes() {
+        let (repo, c) = history("revparse-names");
+        let path = &repo.path;
+        for spec in ["HEAD", "@", "main", "heads/main", "refs/heads/main"] {
+            assert_eq!(resolve(path, spec).unwrap(), c[2], "{spec}");
+        }
+        assert_eq!(resolve(path, "v1").unwrap(), c[0]);
+        assert_eq!(resolve(path, &c[1]).unwrap(), c[1]);
+        assert_eq!(resolve(path, &c[1][..30].to_uppercase()).unwrap(), c[1]);
+        assert_eq!(resolve(path, &c[1][..MIN_ABBREV]).unwrap(), c[1]);
+        // Write blobs until two of them share the shortest prefix
+        let mut seen = std::collections::HashSet::new();
+        let hash = (0..)
+            .map(|i| hex::encode(repo.write_object("blob", format!("{i}").as_bytes())))
+            .find(|hash| !seen.insert(hash[..MIN_ABBREV].to_owned()))
+            .unwrap();
+        let prefix = &hash[..MIN_ABBREV];
+        let err = resolve(path, prefix).unwrap_err().to_string();
+        assert!(err.contains("ambiguous"), "{err}");
+        assert!(resolve(path, "nothing").is_err());
+    }
+
+    #[test]
+    fn parents_and_peeling() {
+        let (repo, c) = history("revparse-parents");
+        let path = &repo.path;
+        assert_eq!(resolve(path, "main~").unwrap(), c[1]);
+        assert_eq!(resolve(path, "main^").unwrap(), c[1]);
+        assert_eq!(resolve(path, "main~2").unwrap(), c[0]);
+        assert_eq!(resolve(path, "main^^").unwrap(), c[0]);
+        assert_eq!(resolve(path, "main^0").unwrap(), c[2]);
+        let err = resolve(path, "main~3").unwrap_err().to_string();
+        assert!(err.contains("goes past the history"), "{err}");
+        assert!(resolve(path, "main^2").is_err());
+        assert_eq!(resolve(path, "v1^{commit}").unwrap(), c[0]);
+        assert_eq!(resolve_tree(path, "HEAD").unwrap(), repo.tree(&[]));
+        assert_eq!(resolve(path, "HEAD~1^{tree}").unwrap(), repo.tree(&[]));
+        assert!(resolve(path, "HEAD^{blob}").is_err());
+    }
+
+    #[test]
+    fn tree_paths() {
+        let repo = TestRepo::new("revparse-paths");
+        let blob = hex::encode(repo.write_object("blob", b"hello"));
+        let tree = repo.tree(&[("a.txt", "hello"), ("b.txt", "bye")]);
+        assert_eq!(resolve(&repo.path, &format!("{tree}:a.txt")).unwrap(), blob);
+        assert_eq!(resolve(&repo.path, &format!("{tree}:")).unwrap(), tree);
+        assert!(resolve(&repo.path, &format!("{tree}:c.txt")).is_err());
+        assert!(resolve(&repo.path, &format!("{tree}:a.txt/x")).is_err());
+        assert!(resolve(&repo.path, ":a.txt").is_err());
+    }
+
+    #[test]
+    fn reflog_selector() {
+        let (repo, c) = history("revparse-reflog");
+        for (old, new) in [(NULL_HASH, &c[0]), (&c[0], &c[1]), (&c[1], &c[2])] {
+            let entry = ReflogEntry {
+                old: old.to_owned(),
+                new: new.to_owned(),
+                name: String::from("Jane Q. Doe"),
+                email: String::from("jane@example.com"),
+                timestamp: 100,
+                timezone: String::from("+0000"),
+                message: String::from("commit"),
+            };
+            append_reflog(&repo.path, "refs/heads/main", &entry).unwrap();
+        }
+        let nth = |spec: &str| resolve(&repo.path, spec);
+        assert_eq!(nth("main@{0}").unwrap(), c[2]);
+        assert_eq!(nth("main@{2}").unwrap(), c[0]);
+        assert_eq!(nth("@{1}").unwrap(), c[1]);
+        assert_eq!(nth("main@{1}~1").unwrap(), c[0]);
+        let err = nth("main@{3}").unwrap_err().to_string();
+        assert!(err.contains("only has 3 entries"), "{err}");
+        assert!(nth("v1@{0}").is_err());
+        assert!(nth("main@{x}").is_err());
+    }
+}
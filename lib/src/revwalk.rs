@@ -0,0 +1,481 @@
+use std::{
+    collections::{BinaryHeap, HashMap, HashSet},
+    error::Error,
+};
+
+use crate::{
+    commit_graph::CommitGraph,
+    objects::commit::{commit_parents, commit_timestamp, read_commit},
+};
+
+/// Order in which the walk returns commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sort {
+    /// Newest commit date first
+    #[default]
+    Time,
+    /// Never return a parent before all of its children, newest first when there's a choice
+    Topological,
+}
+
+/// Iterator over the commit history, non recursive so long histories can't overflow the stack.
+///
+/// Commits are pushed as starting points and hidden commits, with all their ancestors, are
+/// excluded from the walk. Items are commit hashes, use `read_commit` to get their content.
+/// Parents and dates come from the commit-graph when the repository has one.
+pub struct RevWalk {
+    repo_path: String,
+    graph: Option<CommitGraph>,
+    sorting: Sort,
+    first_parent: bool,
+    pushed: Vec<String>,
+    hidden: Vec<String>,
+    state: Option<WalkState>,
+}
+
+/// State created on the first call to `next`, once all options are known
+struct WalkState {
+    // Commits reachable from the hidden ones
+    uninteresting: HashSet<String>,
+    // Commits already queued
+    seen: HashSet<String>,
+    // Parents of every queued commit
+    parents: HashMap<String, Vec<String>>,
+    // Newest commit on top, the sequence number keep insertion order for same dates
+    queue: BinaryHeap<(i64, u64, String)>,
+    seq: u64,
+    // Full output computed ahead, used by topological sorting
+    sorted: Option<std::vec::IntoIter<String>>,
+}
+
+impl RevWalk {
+    pub fn new(repo_path: &str) -> Self {
+        RevWalk {
+            repo_path: repo_path.to_owned(),
+            graph: CommitGraph::load(repo_path),
+            sorting: Sort::default(),
+            first_parent: false,
+            pushed: Vec::new(),
+            hidden: Vec::new(),
+            state: None,
+        }
+    }
+
+    /// Add a commit to start the walk from
+    pub fn push(&mut self, hash: &str) {
+        self.pushed.push(hash.to_owned());
+    }
+
+    /// Exclude a commit and all its ancestors from the walk
+    pub fn hide(&mut self, hash: &str) {
+        self.hidden.push(hash.to_owned());
+    }
+
+    /// Push a range of commits given as hashes.
+    /// `A..B` walks commits reachable from B but not from A.
+    /// `A...B` walks commits reachable from either A or B but not from both, every merge base is
+    /// hidden.
+    pub fn push_range(&mut self, range: &str) -> Result<(), Box<dyn Error>> {
+        if let Some((a, b)) = range.split_once("...") {
+            self.push(a);
+            self.push(b);
+            for base in merge_bases(&self.repo_path, a, b)? {
+                self.hide(&base);
+            }
+        } else if let Some((a, b)) = range.split_once("..") {
+            self.hide(a);
+            self.push(b);
+        } else {
+            return Err(format!("invalid range '{range}'").into());
+        }
+        Ok(())
+    }
+
+    pub fn set_sorting(&mut self, sorting: Sort) {
+        self.sorting = sorting;
+    }
+
+    /// Only follow the first parent of each commit
+    pub fn simplify_first_parent(&mut self) {
+        self.first_parent = true;
+    }
+
+    fn parents(&self, hash: &str) -> Result<(Vec<String>, i64), Box<dyn Error>> {
+        let (mut parents, timestamp) = commit_info(self.graph.as_ref(), &self.repo_path, hash)?;
+        if self.first_parent {
+            parents.truncate(1);
+        }
+        Ok((parents, timestamp))
+    }
+
+    fn init_state(&self) -> Result<WalkState, Box<dyn Error>> {
+        let uninteresting = self.mark_uninteresting()?;
+        let mut state = WalkState {
+            uninteresting,
+            seen: HashSet::new(),
+            parents: HashMap::new(),
+            queue: BinaryHeap::new(),
+            seq: 0,
+            sorted: None,
+        };
+        for hash in &self.pushed {
+            self.enqueue(&mut state, hash)?;
+        }
+        if self.sorting == Sort::Topological {
+            state.sorted = Some(self.topological_order(&mut state)?.into_iter());
+        }
+        Ok(state)
+    }
+
+    /// Mark the hidden commits and their ancestors as uninteresting. Pushed and hidden commits
+    /// are walked together by date, the mark is passed down to parents, and the walk stops once
+    /// every queued commit is uninteresting, so the history below the range isn't read.
+    fn mark_uninteresting(&self) -> Result<HashSet<String>, Box<dyn Error>> {
+        let mut uninteresting: HashSet<String> = self.hidden.iter().cloned().collect();
+        if self.pushed.is_empty() {
+            return Ok(uninteresting);
+        }
+        let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+        let mut queue: BinaryHeap<(i64, u64, String)> = BinaryHeap::new();
+        for hash in self.hidden.iter().chain(&self.pushed) {
+            self.enqueue_for_marking(&mut parents, &mut queue, hash)?;
+        }
+        while queue.iter().any(|(_, _, x)| !uninteresting.contains(x)) {
+            let Some((_, _, hash)) = queue.pop() else {
+                break;
+            };
+            let commit_parents = parents.get(&hash).cloned().unwrap_or_default();
+            if uninteresting.contains(&hash) {
+                // Parents already walked pass the mark down to their own ancestors
+                let mut stack: Vec<String> = commit_parents.clone();
+                while let Some(parent) = stack.pop() {
+                    if uninteresting.insert(parent.clone())
+                        && let Some(grand_parents) = parents.get(&parent)
+                    {
+                        stack.extend(grand_parents.iter().cloned());
+                    }
+                }
+            }
+            for parent in &commit_parents {
+                self.enqueue_for_marking(&mut parents, &mut queue, parent)?;
+            }
+        }
+        Ok(uninteresting)
+    }
+
+    fn enqueue_for_marking(
+        &self,
+        parents: &mut HashMap<String, Vec<String>>,
+        queue: &mut BinaryHeap<(i64, u64, String)>,
+        hash: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if parents.contains_key(hash) {
+            return Ok(());
+        }
+        let (commit_parents, timestamp) = self.parents(hash)?;
+        parents.insert(hash.to_owned(), commit_parents);
+        // Negative sequence so the first inserted wins between same dates
+        let seq = u64::MAX - parents.len() as u64;
+        queue.push((timestamp, seq, hash.to_owned()));
+        Ok(())
+    }
+
+    fn enqueue(&self, state: &mut WalkState, hash: &str) -> Result<(), Box<dyn Error>> {
+        if state.uninteresting.contains(hash) || !state.seen.insert(hash.to_owned()) {
+            return Ok(());
+        }
+        let (parents, timestamp) = self.parents(hash)?;
+        state.parents.insert(hash.to_owned(), parents);
+        // Negative sequence so the first inserted wins between same dates
+        state.seq += 1;
+        state
+            .queue
+            .push((timestamp, u64::MAX - state.seq, hash.to_owned()));
+        Ok(())
+    }
+
+    /// Pop the newest commit of the queue and queue its parents
+    fn next_by_time(&self, state: &mut WalkState) -> Result<Option<String>, Box<dyn Error>> {
+        let Some((_, _, hash)) = state.queue.pop() else {
+            return Ok(None);
+        };
+        let parents = state.parents.get(&hash).cloned().unwrap_or_default();
+        for parent in parents {
+            self.enqueue(state, &parent)?;
+        }
+        Ok(Some(hash))
+    }
+
+    /// Drain the walk by date then reorder it so children always come before their parents
+    fn topological_order(&self, state: &mut WalkState) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut by_time: Vec<String> = Vec::new();
+        while let Some(hash) = self.next_by_time(state)? {
+            by_time.push(hash);
+        }
+        let position: HashMap<&String, usize> =
+            by_time.iter().enumerate().map(|(i, x)| (x, i)).collect();
+        let mut parents: Vec<Vec<usize>> = Vec::with_capacity(by_time.len());
+        let mut children_count: Vec<usize> = vec![0; by_time.len()];
+        for hash in &by_time {
+            let parent_positions: Vec<usize> = state.parents[hash]
+                .iter()
+                .filter_map(|x| position.get(x).copied())
+                .collect();
+            for each in &parent_positions {
+                children_count[*each] += 1;
+            }
+            parents.push(parent_positions);
+        }
+        // Smallest position is the newest commit
+        let mut ready: BinaryHeap<std::cmp::Reverse<usize>> = (0..by_time.len())
+            .filter(|x| children_count[*x] == 0)
+            .map(std::cmp::Reverse)
+            .collect();
+        let mut sorted: Vec<String> = Vec::with_capacity(by_time.len());
+        while let Some(std::cmp::Reverse(i)) = ready.pop() {
+            sorted.push(by_time[i].clone());
+            for parent in &parents[i] {
+                children_count[*parent] -= 1;
+                if children_count[*parent] == 0 {
+                    ready.push(std::cmp::Reverse(*parent));
+                }
+            }
+        }
+        Ok(sorted)
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<String, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state.is_none() {
+            match self.init_state() {
+                Ok(state) => self.state = Some(state),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let mut state = self.state.take().unwrap();
+        let next = match state.sorted.as_mut() {
+            Some(sorted) => Ok(sorted.next()),
+            None => self.next_by_time(&mut state),
+        };
+        self.state = Some(state);
+        next.transpose()
+    }
+}
+
+/// Find the best common ancestor of two commits, a common ancestor which isn't an ancestor of
+/// another common ancestor. Return None if the commits don't share any history.
+pub fn merge_base(repo_path: &str, a: &str, b: &str) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(merge_bases(repo_path, a, b)?.into_iter().next())
+}
+
+/// Find all the best common ancestors of two commits, several with criss-cross merges. The
+/// first one is the newest in topological order.
+pub fn merge_bases(repo_path: &str, a: &str, b: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut walk_a = RevWalk::new(repo_path);
+    walk_a.push(a);
+    let ancestors_a: HashSet<String> = walk_a.collect::<Result<_, _>>()?;
+    // In topological order a descendant always comes first, so a common commit which isn't below
+    // an earlier best one is a best one too
+    let mut walk_b = RevWalk::new(repo_path);
+    walk_b.push(b);
+    walk_b.set_sorting(Sort::Topological);
+    let graph = CommitGraph::load(repo_path);
+    let mut bases: Vec<String> = Vec::new();
+    let mut below_bases: HashSet<String> = HashSet::new();
+    for hash in walk_b {
+        let hash = hash?;
+        if !ancestors_a.contains(&hash) || below_bases.contains(&hash) {
+            continue;
+        }
+        let (mut stack, _) = commit_info(graph.as_ref(), repo_path, &hash)?;
+        while let Some(parent) = stack.pop() {
+            if below_bases.insert(parent.clone()) {
+                stack.extend(commit_info(graph.as_ref(), repo_path, &parent)?.0);
+            }
+        }
+        bases.push(hash);
+    }
+    Ok(bases)
+}
+
+/// Count the commits of `local` missing from `upstream` and the commits of `upstream` missing
+/// from `local`, the ahead and behind counts of a branch
+pub fn ahead_behind(
+    repo_path: &str,
+    local: &str,
+    upstream: &str,
+) -> Result<(usize, usize), Box<dyn Error>> {
+    let count = |from: &str, hidden: &str| -> Result<usize, Box<dyn Error>> {
+        let mut walk = RevWalk::new(repo_path);
+        walk.push(from);
+        walk.hide(hidden);
+        walk.try_fold(0, |n, x| x.map(|_| n + 1))
+    };
+    Ok((count(local, upstream)?, count(upstream, local)?))
+}
+
+/// Check if `ancestor` is reachable from `descendant`, a commit is its own ancestor.
+/// With a commit-graph, commits whose generation is lower than the ancestor one can't lead to it
+/// and aren't walked.
+pub fn is_ancestor(
+    repo_path: &str,
+    ancestor: &str,
+    descendant: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let graph = CommitGraph::load(repo_path);
+    let min_generation = graph
+        .as_ref()
+        .and_then(|x| x.get(ancestor))
+        .map(|x| x.generation);
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut stack: Vec<String> = vec![descendant.to_owned()];
+    while let Some(hash) = stack.pop() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        if let (Some(min), Some(entry)) =
+            (min_generation, graph.as_ref().and_then(|x| x.get(&hash)))
+            && entry.generation <= min
+        {
+            continue;
+        }
+        let (parents, _) = commit_info(graph.as_ref(), repo_path, &hash)?;
+        stack.extend(parents);
+    }
+    Ok(false)
+}
+
+/// Parents and date of a commit, from the commit-graph if it's there else from the object
+fn commit_info(
+    graph: Option<&CommitGraph>,
+    repo_path: &str,
+    hash: &str,
+) -> Result<(Vec<String>, i64), Box<dyn Error>> {
+    if let Some(entry) = graph.and_then(|x| x.get(hash)) {
+        return Ok((entry.parents, entry.timestamp));
+    }
+    let commit = read_commit(hash, repo_path)?;
+    Ok((commit_parents(&commit), commit_timestamp(&commit)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRepo;
+
+    /// Messages of the commits walked, the test commits are named by their message
+    fn walk(repo: &TestRepo, setup: impl FnOnce(&mut RevWalk)) -> Vec<String> {
+        let mut walk = RevWalk::new(&repo.path);
+        setup(&mut walk);
+        walk.map(|x| read_commit(&x.unwrap(), &repo.path).unwrap().message)
+            .map(|x| String::from_utf8(x).unwrap())
+            .collect()
+    }
+
+    /// `a1 - a2 - a3` with `b1 - b2` branching from a2, b commits are the newest
+    fn forked_history(repo: &TestRepo) -> (Vec<String>, Vec<String>) {
+        let a = repo.chain(None, 100, &["a1", "a2", "a3"]);
+        let b = repo.chain(Some(&a[1]), 200, &["b1", "b2"]);
+        (a, b)
+    }
+
+    #[test]
+    fn walk_by_date_and_hide() {
+        let repo = TestRepo::new("revwalk-date");
+        let (a, b) = forked_history(&repo);
+        let all = walk(&repo, |x| {
+            x.push(&a[2]);
+            x.push(&b[1]);
+        });
+        assert_eq!(all, ["b2", "b1", "a3", "a2", "a1"]);
+        let hidden = walk(&repo, |x| {
+            x.push(&b[1]);
+            x.hide(&a[2]);
+        });
+        assert_eq!(hidden, ["b2", "b1"]);
+    }
+
+    #[test]
+    fn ranges() {
+        let repo = TestRepo::new("revwalk-range");
+        let (a, b) = forked_history(&repo);
+        let two_dots = walk(&repo, |x| x.push_range(&format!("{}..{}", a[2], b[1])).unwrap());
+        assert_eq!(two_dots, ["b2", "b1"]);
+        let three_dots = walk(&repo, |x| x.push_range(&format!("{}...{}", a[2], b[1])).unwrap());
+        assert_eq!(three_dots, ["b2", "b1", "a3"]);
+        assert!(RevWalk::new(&repo.path).push_range(&a[0]).is_err());
+    }
+
+    #[test]
+    fn topological_puts_children_first() {
+        let repo = TestRepo::new("revwalk-topo");
+        // The parent is dated after its child, like with a skewed clock
+        let parent = repo.commit(None, 500, "parent");
+        let child = repo.commit(Some(&parent), 100, "child");
+        let by_date = walk(&repo, |x| {
+            x.push(&child);
+            x.push(&parent);
+        });
+        assert_eq!(by_date, ["parent", "child"]);
+        let topological = walk(&repo, |x| {
+            x.push(&child);
+            x.push(&parent);
+            x.set_sorting(Sort::Topological);
+        });
+        assert_eq!(topological, ["child", "parent"]);
+    }
+
+    #[test]
+    fn merge_base_ahead_behind_and_ancestry() {
+        let repo = TestRepo::new("revwalk-base");
+        let (a, b) = forked_history(&repo);
+        let path = &repo.path;
+        assert_eq!(merge_base(path, &a[2], &b[1]).unwrap().as_ref(), Some(&a[1]));
+        assert_eq!(merge_base(path, &a[0], &b[1]).unwrap().as_ref(), Some(&a[0]));
+        let unrelated = repo.commit(None, 300, "unrelated");
+        assert_eq!(merge_base(path, &unrelated, &b[1]).unwrap(), None);
+        assert_eq!(ahead_behind(path, &a[2], &b[1]).unwrap(), (1, 2));
+        assert_eq!(ahead_behind(path, &b[1], &b[1]).unwrap(), (0, 0));
+        assert!(is_ancestor(path, &a[0], &b[1]).unwrap());
+        assert!(is_ancestor(path, &b[1], &b[1]).unwrap());
+        assert!(!is_ancestor(path, &a[2], &b[1]).unwrap());
+        assert!(!is_ancestor(path, &b[1], &a[0]).unwrap());
+    }
+
+    #[test]
+    fn criss_cross_merge_bases() {
+        let repo = TestRepo::new("revwalk-criss-cross");
+        let root = repo.commit(None, 100, "root");
+        let x1 = repo.commit(Some(&root), 110, "x1");
+        let y1 = repo.commit(Some(&root), 120, "y1");
+        let x2 = repo.commit(Some(&format!("{x1} {y1}")), 130, "x2");
+        let y2 = repo.commit(Some(&format!("{y1} {x1}")), 140, "y2");
+        let mut bases = merge_bases(&repo.path, &x2, &y2).unwrap();
+        bases.sort();
+        let mut expected = vec![x1, y1];
+        expected.sort();
+        assert_eq!(bases, expected);
+        let three_dots = walk(&repo, |x| x.push_range(&format!("{x2}...{y2}")).unwrap());
+        assert_eq!(three_dots, ["y2", "x2"]);
+    }
+
+    #[test]
+    fn hidden_history_is_not_read_further_than_needed() {
+        let repo = TestRepo::new("revwalk-limit");
+        let c = repo.chain(None, 100, &["c1", "c2", "c3", "c4", "c5"]);
+        // The walk would fail on the missing commit if it read the whole hidden history
+        std::fs::remove_file(format!("{}/objects/{}/{}", repo.path, &c[0][..2], &c[0][2..]))
+            .unwrap();
+        let range = walk(&repo, |x| {
+            x.push(&c[4]);
+            x.hide(&c[2]);
+        });
+        assert_eq!(range, ["c5", "c4"]);
+    }
+}
@@ -0,0 +1,86 @@
+// Repository written in a temporary directory for the unit tests. Objects are written like the
+// client does, so their hashes are the real ones.
+
+use std::{env, fs};
+
+use crate::{
+    objects::{
+        commit::{CommitContent, CommitUser, commit_payload},
+        tree::{RWO, TreeEntry},
+        utils::write_object,
+    },
+    refs::write_ref,
+};
+
+pub struct TestRepo {
+    pub path: String,
+}
+
+impl TestRepo {
+    /// Create an empty repository, the name keeps the directories of the tests apart
+    pub fn new(name: &str) -> TestRepo {
+        let path = env::temp_dir().join(format!("lrngit-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestRepo {
+            path: path.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Write an object of the given type and return its hash
+    pub fn write_object(&self, kind: &str, content: &[u8]) -> [u8; 20] {
+        let mut hash = [0u8; 20];
+        hex::decode_to_slice(write_object(kind, content, &self.path).unwrap(), &mut hash).unwrap();
+        hash
+    }
+
+    /// Write a tree of files with their content, return its hash
+    pub fn tree(&self, files: &[(&str, &str)]) -> String {
+        let entries: Vec<TreeEntry> = files
+            .iter()
+            .map(|(name, content)| TreeEntry {
+                mode: RWO,
+                name: name.as_bytes().to_vec(),
+                hash: self.write_object("blob", content.as_bytes()),
+            })
+            .collect();
+        hex::encode(self.write_object("tree", &bincode::serialize(&entries).unwrap()))
+    }
+
+    /// Write a commit of an empty tree, the message is also used as author name so commits made
+    /// at the same time differ
+    pub fn commit(&self, parent: Option<&str>, timestamp: i64, message: &str) -> String {
+        let user = CommitUser {
+            name: message.as_bytes().to_vec(),
+            email: b"test@example.com".to_vec(),
+            timestamp,
+            timezone: b"+0000".to_vec(),
+        };
+        let user = bincode::serialize(&user).unwrap();
+        let mut tree = [0u8; 20];
+        hex::decode_to_slice(self.tree(&[]), &mut tree).unwrap();
+        let commit = CommitContent {
+            tree,
+            parent: parent.unwrap_or_default().as_bytes().to_vec(),
+            author: user.clone(),
+            commiter: user,
+            message: message.as_bytes().to_vec(),
+            signature: vec![],
+        };
+        hex::encode(self.write_object("commit", &commit_payload(&commit).unwrap()))
+    }
+
+    /// Write a chain of commits on top of a parent, one second apart, return them oldest first
+    pub fn chain(&self, parent: Option<&str>, start: i64, messages: &[&str]) -> Vec<String> {
+        let mut commits: Vec<String> = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            let parent = commits.last().map(|x| x.as_str()).or(parent);
+            commits.push(self.commit(parent, start + i as i64, message));
+        }
+        commits
+    }
+
+    pub fn set_ref(&self, name: &str, hash: &str) {
+        write_ref(&self.path, name, hash).unwrap();
+    }
+}
@@ -1,30 +1,70 @@
-use std::{fs::{File, OpenOptions}, io::Write};
+use std::error::Error;
 
-use lrngitcore::{objects::commit::unwind_commits, pack::refs::ParsedRefsPack};
+use lrngitcore::{
+    objects::{commit::read_commit, utils::read_object},
+    pack::refs::ParsedRefsPack,
+    refs::{is_valid_ref_name, read_ref, write_ref},
+    revwalk::{RevWalk, is_ancestor},
+};
 
-/// Update HEAD file and given refs from 'refs' parameter. Check if there's no conflict and if
-/// there is all mandatory objects to rebuild historic
-pub fn update_refs(refs: ParsedRefsPack) {
-    // Update HEAD
-    let mut head: File = OpenOptions::new()
-        .read(false)
-        .write(true)
-        .append(false)
-        .open("HEAD")
-        .expect("Failed to open HEAD file");
-    let updated_head: String = String::from("ref: ") + refs.refs;
-    head.write_all(updated_head.as_bytes())
-        .expect("Failed to write updated HEAD content");
-    // Update refs/heads
-    let mut ref_head: File = OpenOptions::new()
-        .read(false)
-        .write(true)
-        .append(false)
-        .open(refs.refs)
-        .expect("Failed to open given refs/heads file");
-    ref_head
-        .write_all(refs.local_commit.as_bytes())
-        .expect("Failed to update refs/heads with last commit");
-    // Unwind commits
-    unwind_commits(refs.local_commit, refs.origin_commit);
+/// Update the branch given by 'refs' parameter. The name must be a valid branch, the update must
+/// start from the commit the client knows or be a fast-forward of the current commit, and every
+/// object needed to rebuild the history must exist before the ref is touched. HEAD is left as is,
+/// pushing a branch doesn't change the default branch of the remote.
+pub fn update_refs(refs: ParsedRefsPack) -> Result<(), Box<dyn Error>> {
+    check_branch_name(refs.refs)?;
+    if let Some(current) = read_ref(".", refs.refs)?
+        && current != refs.origin_commit
+        && !is_ancestor(".", &current, refs.local_commit).unwrap_or(false)
+    {
+        return Err(format!("{} has moved, fetch first (non-fast-forward)", refs.refs).into());
+    }
+    check_history(refs)?;
+    write_ref(".", refs.refs, refs.local_commit)?;
+    Ok(())
+}
+
+/// Check that a pushed ref is a valid branch name
+pub fn check_branch_name(name: &str) -> Result<(), Box<dyn Error>> {
+    if !name.starts_with("refs/heads/") || !is_valid_ref_name(name) {
+        return Err(format!("invalid branch name '{name}'").into());
+    }
+    Ok(())
+}
+
+/// Walk the pushed commits down to the last commit known by the remote and check that every
+/// commit and its root tree exist in the repository
+fn check_history(refs: ParsedRefsPack) -> Result<(), Box<dyn Error>> {
+    let mut walk = RevWalk::new(".");
+    walk.push(refs.local_commit);
+    if !refs.origin_commit.is_empty() && read_commit(refs.origin_commit, ".").is_ok() {
+        walk.hide(refs.origin_commit);
+    }
+    for hash in walk {
+        let commit = read_commit(&hash?, ".")?;
+        read_object(&hex::encode(commit.tree), ".")?;
+    }
+    Ok(())
+}
+
+/// Create the pushed tags. Every tag is checked before any is written, an existing tag can't be
+/// moved and the tagged object must exist.
+pub fn update_tags(tags: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+    let mut new_tags: Vec<(String, &str)> = Vec::new();
+    for (name, hash) in tags {
+        let refname = format!("refs/tags/{name}");
+        if !is_valid_ref_name(&refname) {
+            return Err(format!("invalid tag name '{name}'").into());
+        }
+        read_object(hash, ".")?;
+        match read_ref(".", &refname)? {
+            Some(current) if current == *hash => continue,
+            Some(_) => return Err(format!("tag '{name}' already exists").into()),
+            None => new_tags.push((refname, hash)),
+        }
+    }
+    for (refname, hash) in new_tags {
+        write_ref(".", &refname, hash)?;
+    }
+    Ok(())
 }
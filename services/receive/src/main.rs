@@ -2,18 +2,18 @@ use std::{
     env::{self, set_current_dir},
     io::{self, Read},
     os::fd::FromRawFd,
-    path::Path,
     process::exit,
 };
 
 use std::net::{Shutdown, TcpStream};
 
-use head::update_refs;
+use head::{check_branch_name, update_refs, update_tags};
 use lrngitcore::{
+    config::{Config, served_repository},
     fs::pack::write_pack_to_disk,
     out::write_framed_message_stdout,
     pack::{
-        refs::{ParsedRefsPack, parse_refs_pack},
+        refs::{ParsedRefsPack, parse_refs_pack, parse_tags_pack},
         upload::parse_upload_pack,
     },
 };
@@ -23,20 +23,19 @@ mod head;
 fn main() {
     let mut stdout = io::stdout();
     let args: Vec<String> = env::args().collect();
-    let lrngit_repo_path: &str = "/home/ubuntu/lrngit/repositories/";
     if args.len() < 2 {
         write_framed_message_stdout("ERR repository name argument missing", &mut stdout);
         // Create stream from fd and shutdown to properly send err to client
         let _ = unsafe { TcpStream::from_raw_fd(1) }.shutdown(Shutdown::Write);
         exit(1);
     }
-    let repo_path = lrngit_repo_path.to_owned() + &args[1];
-    if !Path::new(&repo_path).exists() {
+    // The repository must be under the served directory, from the system or global config
+    let Some(repo_path) = served_repository(&Config::load_global(), &args[1]) else {
         write_framed_message_stdout("ERR repository doesn't exist", &mut stdout);
         // Create stream from fd and shutdown to properly send err to client
         let _ = unsafe { TcpStream::from_raw_fd(1) }.shutdown(Shutdown::Write);
         exit(1)
-    }
+    };
     set_current_dir(repo_path).expect("Failed to change current dir");
     handle_stream(stdout);
     // Close properly stream when handling stream returned
@@ -55,6 +54,8 @@ fn handle_stream(mut stdout: io::Stdout) {
         local_commit: "",
         origin_commit: "",
     };
+    // Tags pushed with the pack, created once the objects are written
+    let mut tags: Vec<(String, String)> = Vec::new();
     loop {
         let mut stream_length = [0u8; 4];
         // Read buffer length
@@ -93,15 +94,15 @@ fn handle_stream(mut stdout: io::Stdout) {
             "REFS" => {
                 parsed_buffer.clone_from_slice(&buffer);
                 refs = parse_refs_pack(&parsed_buffer[5..length as usize]);
-                // Check if refs exist on remote repository
-                if !Path::exists(Path::new(refs.refs)) {
-                    write_framed_message_stdout(
-                        "ERR reference doesn't exist on remote host",
-                        &mut stdout,
-                    );
+                // Check the pushed ref is a branch before receiving the pack
+                if let Err(e) = check_branch_name(refs.refs) {
+                    write_framed_message_stdout(&format!("ERR {e}"), &mut stdout);
                     break;
                 }
             }
+            "TAGS" => {
+                tags = parse_tags_pack(&buffer[5..length as usize]);
+            }
             "PACK" => {
                 // Drain 4 first bytes + \0
                 let pack = match parse_upload_pack(&buffer[5..]) {
@@ -115,7 +116,14 @@ fn handle_stream(mut stdout: io::Stdout) {
                 // Write pack content to disk
                 write_pack_to_disk(pack.data);
                 // Update head using refs and pack
-                update_refs(refs);
+                if let Err(e) = update_refs(refs) {
+                    write_framed_message_stdout(&format!("ERR {e}"), &mut stdout);
+                    break;
+                }
+                if let Err(e) = update_tags(&tags) {
+                    write_framed_message_stdout(&format!("ERR {e}"), &mut stdout);
+                    break;
+                }
                 write_framed_message_stdout("ACK", &mut stdout);
             }
             _ => {
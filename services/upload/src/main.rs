@@ -1,24 +1,53 @@
-use std::{env::{self, set_current_dir}, io::{self, Write}, net::{Shutdown, TcpStream}, os::fd::FromRawFd, path::Path, process::exit};
+use std::{
+    env::{self, set_current_dir},
+    io,
+    net::{Shutdown, TcpStream},
+    os::fd::FromRawFd,
+    process::exit,
+};
+
+use lrngitcore::{
+    config::{Config, served_repository},
+    out::write_framed_message_stdout,
+    pack::refs::build_advertised_refs,
+    refs::list_refs,
+};
 
 fn main() {
-    println!("[SERVICE] lrngit-upload");
-    io::stdout().flush().unwrap();
+    let mut stdout = io::stdout();
     let args: Vec<String> = env::args().collect();
-    let lrngit_repo_path: &str = "/home/ubuntu/lrngit/repositories/";
     if args.len() < 2 {
-        println!("ERR: repository name argument missing");
-        io::stdout().flush().unwrap();
+        write_framed_message_stdout("ERR repository name argument missing", &mut stdout);
         // Create stream from fd and shutdown to properly send err to client
         let _ = unsafe { TcpStream::from_raw_fd(1) }.shutdown(Shutdown::Write);
         exit(1);
     }
-    let repo_path = lrngit_repo_path.to_owned() + &args[1];
-    if !Path::new(&repo_path).exists() {
-        println!("ERR repository doesn't exist");
-        io::stdout().flush().unwrap();
+    // The repository must be under the served directory, from the system or global config
+    let Some(repo_path) = served_repository(&Config::load_global(), &args[1]) else {
+        write_framed_message_stdout("ERR repository doesn't exist", &mut stdout);
         // Create stream from fd and shutdown to properly send err to client
         let _ = unsafe { TcpStream::from_raw_fd(1) }.shutdown(Shutdown::Write);
         exit(1)
-    }
+    };
     set_current_dir(repo_path).expect("Failed to change current dir");
+    advertise_refs(&mut stdout);
+    // Close properly stream once refs are sent
+    let _ = unsafe { TcpStream::from_raw_fd(1) }.shutdown(Shutdown::Write);
+}
+
+/// Send the branches and tags of the repository with the commit they point to
+fn advertise_refs(stdout: &mut io::Stdout) {
+    let mut refs: Vec<(String, String)> = Vec::new();
+    for prefix in ["refs/heads", "refs/tags"] {
+        match list_refs(".", prefix) {
+            Ok(found) => refs.extend(found),
+            Err(e) => {
+                write_framed_message_stdout(&format!("ERR failed to list refs: {e}"), stdout);
+                return;
+            }
+        }
+    }
+    refs.sort();
+    let advertised = build_advertised_refs(&refs);
+    write_framed_message_stdout(&String::from_utf8_lossy(&advertised), stdout);
 }